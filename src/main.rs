@@ -8,17 +8,21 @@ use colored::Colorize;
 use getch::Getch;
 use rand::Rng;
 use std::collections::btree_set;
-use std::fs::File;
 use std::io;
-use std::io::prelude::*;
 use std::iter;
 use std::path::Path;
 use std::time::SystemTime;
 use std::vec::Vec;
 
 mod multi_seg_network;
+use crate::multi_seg_network::binary_format::{
+    convert_binary_file_to_json, convert_json_file_to_binary, PrecisionEnum,
+};
+use crate::multi_seg_network::error::NetworkError;
 use crate::multi_seg_network::flexible_network::{ActivationFunctionEnum, FlexibleNetwork};
 use crate::multi_seg_network::function_segment::{FunctionSegment, FunctionSegmentFunctionEnum};
+use crate::multi_seg_network::initializer::InitializerEnum;
+use crate::multi_seg_network::loss_function::LossFunctionEnum;
 use crate::multi_seg_network::*;
 
 mod maze_game;
@@ -26,16 +30,6 @@ use crate::maze_game::*;
 
 const MODEL_PATH: &str = "model/net.json";
 
-macro_rules! until_ok {
-    ($do_something: expr) => {
-        loop {
-            let result = std::panic::catch_unwind(|| $do_something());
-            if result.is_ok() {
-                break;
-            }
-        }
-    };
-}
 fn random_as_probability<T>(value_list: Vec<T>, probability_list: Vec<f64>) -> T
 where
     T: Clone,
@@ -60,7 +54,37 @@ where
     }
 }
 
+fn convert_model_file(argument_list: &[String]) -> Result<(), NetworkError> {
+    // rust-rl convert <from> <to> [f32]
+    // A ".json" source is converted to the binary form, the other files are converted back to json.
+    if argument_list.len() < 2 {
+        return Err(NetworkError::FileError(String::from(
+            "usage: rust-rl convert <from> <to> [f32]",
+        )));
+    }
+    let (from_path, to_path) = (&argument_list[0], &argument_list[1]);
+    if from_path.ends_with(".json") {
+        let precision_enum: PrecisionEnum = match argument_list.get(2).map(|s| s.as_str()) {
+            Some("f32") => PrecisionEnum::F32,
+            _ => PrecisionEnum::F64,
+        };
+        convert_json_file_to_binary(from_path, to_path, precision_enum)
+    } else {
+        convert_binary_file_to_json(from_path, to_path)
+    }
+}
+
 fn main() {
+    let argument_list: Vec<String> = std::env::args().skip(1).collect();
+    if argument_list.first().map(|s| s.as_str()) == Some("convert") {
+        match convert_model_file(&argument_list[1..]) {
+            Ok(()) => println!("done!"),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+    // Convert a model file instead of training.
+
     let start_time: SystemTime = SystemTime::now();
     let mut net_data: String;
 
@@ -214,16 +238,10 @@ fn main() {
     //     Err(_) => {}
     // }
 
-    fn try_fitting() {
+    fn try_fitting() -> Result<(), NetworkError> {
         let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
-        let mut flexible_net_id: usize = 0;
         if Path::new(MODEL_PATH).exists() {
-            let mut file: File = File::open(MODEL_PATH).unwrap();
-            let mut content = String::new();
-            file.read_to_string(&mut content).unwrap();
-            let net_data: String = content.to_string();
-            multi_seg.import_data(net_data);
-            flexible_net_id = 0;
+            multi_seg.load(MODEL_PATH)?;
         } else {
             let mut flexible_net = FlexibleNetwork::new();
             let input_layer: usize =
@@ -232,26 +250,34 @@ fn main() {
                 .iter()
                 .map(|n| flexible_net.new_layer(5, 0.0, ActivationFunctionEnum::ReLU))
                 .collect::<Vec<usize>>();
-            let output_layer: usize =
-                flexible_net.new_layer(5, 0.0, ActivationFunctionEnum::ReLU);
-            flexible_net.connect_layer(input_layer, hidden_layer_list[0], 0.1);
+            let output_layer: usize = flexible_net.new_layer(5, 0.0, ActivationFunctionEnum::ReLU);
+            flexible_net.connect_layer(
+                input_layer,
+                hidden_layer_list[0],
+                InitializerEnum::HeUniform,
+            )?;
             for i in 0..hidden_layer_list.len() - 1 {
-                flexible_net.connect_layer(hidden_layer_list[i], hidden_layer_list[i + 1], 0.1);
+                flexible_net.connect_layer(
+                    hidden_layer_list[i],
+                    hidden_layer_list[i + 1],
+                    InitializerEnum::HeUniform,
+                )?;
             }
             flexible_net.connect_layer(
                 hidden_layer_list[hidden_layer_list.len() - 1],
                 output_layer,
-                0.1,
-            );
-            flexible_net.set_input_layer(input_layer);
-            flexible_net.set_output_layer(output_layer);
+                InitializerEnum::XavierUniform,
+            )?;
+            flexible_net.set_input_layer(input_layer)?;
+            flexible_net.set_output_layer(output_layer)?;
 
             let mut output_function: FunctionSegment = FunctionSegment::new();
-            output_function.set_function(FunctionSegmentFunctionEnum::SoftMax);
+            output_function.set_function(FunctionSegmentFunctionEnum::SoftMax)?;
 
-            flexible_net_id = multi_seg.push_seg(flexible_net);
-            multi_seg.push_seg(output_function);
+            multi_seg.push_seg(flexible_net)?;
+            multi_seg.push_seg(output_function)?;
         }
+        multi_seg.set_loss_function(LossFunctionEnum::PolicyGradient);
 
         // let rate: f64 = 0.00062;
         let rate: f64 = 0.00001;
@@ -262,6 +288,8 @@ fn main() {
             game.set_screen_size([5, 5]);
             game.set_maze_size([2, 2]);
             game.start();
+            multi_seg.reset_state();
+            // Every episode starts without the memory of the last one.
             while game.playing() {
                 if game.get_step_count() > 10 {
                     break;
@@ -286,8 +314,8 @@ fn main() {
                     })
                     .collect::<Vec<Vec<f64>>>()
                     .concat();
-                multi_seg.set_input(input_data.clone());
-                multi_seg.next();
+                multi_seg.set_input(input_data.clone())?;
+                multi_seg.next()?;
                 let mut action: GameAction = GameAction::Hold;
                 let mut last_value: f64 = -100.0;
                 let output_data: Vec<f64> = multi_seg.get_output();
@@ -307,46 +335,49 @@ fn main() {
                 //         };
                 //     }
                 // }
-                action = random_as_probability::<GameAction>(
-                    Vec::from([
-                        GameAction::Up,
-                        GameAction::Down,
-                        GameAction::Left,
-                        GameAction::Right,
-                        GameAction::Hold,
-                    ]),
-                    output_data.clone(),
-                );
+                let action_index: usize =
+                    random_as_probability::<usize>(Vec::from([0, 1, 2, 3, 4]), output_data.clone());
+                action = match action_index {
+                    0 => GameAction::Up,
+                    1 => GameAction::Down,
+                    2 => GameAction::Left,
+                    3 => GameAction::Right,
+                    _ => GameAction::Hold,
+                };
                 game.action(action);
-                data_pair_list.push([input_data, output_data]);
+                let mut action_one_hot: Vec<f64> = vec![0.0; output_data.len()];
+                action_one_hot[action_index] = 1.0;
+                data_pair_list.push([input_data, action_one_hot]);
             }
             let score: f64 = game.get_score();
             println!("score: {score}");
             let reword = score - 0.5;
-            for data_pair in data_pair_list {
-                multi_seg.set_input(data_pair[0].clone());
-                multi_seg.next();
-                multi_seg.operate_seg(flexible_net_id, |seg| {
-                    seg.fitting(
-                        data_pair[1]
-                            .clone()
+            let data_pair_list: Vec<[Vec<f64>; 2]> = data_pair_list
+                .into_iter()
+                .map(|[input_data, action_one_hot]| {
+                    [
+                        input_data,
+                        action_one_hot
                             .iter()
                             .map(|v| v * reword)
                             .collect::<Vec<f64>>(),
-                        rate,
-                    );
-                });
-            }
+                    ]
+                })
+                .collect::<Vec<[Vec<f64>; 2]>>();
+            multi_seg.reset_state();
+            multi_seg.fitting_batch(&data_pair_list, rate)?;
+            // Update once with the whole episode, the taken actions are weighted by the reword.
         }
 
-        let mut file = File::create("model/net.json").unwrap();
-        let _ = file.write_all(multi_seg.export_data().as_bytes());
+        multi_seg.save(MODEL_PATH)?;
 
         println!("done!");
+        Ok(())
     }
-    // until_ok!(try_fitting);
     for _ in 0..1000 {
-        try_fitting();
+        if let Err(e) = try_fitting() {
+            println!("{}", e);
+        }
     }
 
     match start_time.elapsed() {
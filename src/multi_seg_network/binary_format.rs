@@ -0,0 +1,519 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/binary_format.rs
+ */
+
+use serde_json::{Map, Number, Value};
+// The binary form stores the same tree as the json form.
+use std::path::Path;
+// Path of the model files
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::model_file::write_atomically;
+
+pub const MAGIC_BYTES: [u8; 4] = *b"RRLM";
+pub const FORMAT_VERSION: u16 = 1;
+// Increase it when the layout changes, the older versions should still be readable.
+const HEADER_SIZE: usize = 8;
+// magic bytes (4) + version (u16) + precision (u8) + reserved (u8)
+const CHECKSUM_SIZE: usize = 4;
+
+const NULL_TAG: u8 = 0;
+const FALSE_TAG: u8 = 1;
+const TRUE_TAG: u8 = 2;
+const UNSIGNED_TAG: u8 = 3;
+const SIGNED_TAG: u8 = 4;
+const FLOAT_TAG: u8 = 5;
+const STRING_TAG: u8 = 6;
+const ARRAY_TAG: u8 = 7;
+const OBJECT_TAG: u8 = 8;
+const FLOAT_ARRAY_TAG: u8 = 9;
+// A list of floats packed one after another, such as the weights.
+const EMBEDDED_JSON_TAG: u8 = 10;
+// A string of the child segment data stored as a tree instead of text, it is read back as the same tree,
+// but its text is written again with the keys sorted, so it is not byte for byte the same as the source.
+const EMBEDDED_JSON_KEY: &str = "data";
+// Only the strings listed under this key of an object, such as "MultiSegNetworkData::data", are embedded,
+// the other strings are stored as they are.
+const MAX_DEPTH: usize = 512;
+// Nesting depth of the value tree counted through the embedded data, deeper data is rejected
+// instead of overflowing the stack.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrecisionEnum {
+    F64,
+    F32,
+    // Half the size, the floats are rounded to the nearest f32.
+}
+impl PrecisionEnum {
+    fn get_id(self: &Self) -> u8 {
+        match self {
+            PrecisionEnum::F64 => 0,
+            PrecisionEnum::F32 => 1,
+        }
+    }
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(PrecisionEnum::F64),
+            1 => Ok(PrecisionEnum::F32),
+            _ => Err(NetworkError::MalformedData(format!(
+                "unknown float precision {}",
+                id
+            ))),
+        }
+    }
+}
+
+pub fn get_checksum(bytes: &[u8]) -> u32 {
+    // CRC-32 (IEEE 802.3)
+    let mut crc: u32 = 0xFFFFFFFF;
+    for &byte in bytes.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct BinaryWriter {
+    bytes: Vec<u8>,
+    precision_enum: PrecisionEnum,
+}
+impl BinaryWriter {
+    fn write_length(self: &mut Self, length: usize) -> Result<()> {
+        match u32::try_from(length) {
+            Ok(length) => {
+                self.bytes.extend(length.to_le_bytes());
+                Ok(())
+            }
+            Err(_) => Err(NetworkError::MalformedData(format!(
+                "length {} does not fit in the u32 length field",
+                length
+            ))),
+        }
+    }
+    fn write_float(self: &mut Self, x: f64) {
+        match self.precision_enum {
+            PrecisionEnum::F64 => self.bytes.extend(x.to_le_bytes()),
+            PrecisionEnum::F32 => self.bytes.extend((x as f32).to_le_bytes()),
+        }
+    }
+    fn write_string(self: &mut Self, string: &str) -> Result<()> {
+        self.write_length(string.len())?;
+        self.bytes.extend(string.as_bytes());
+        Ok(())
+    }
+    fn write_value(
+        self: &mut Self,
+        value: &Value,
+        depth: usize,
+        is_segment_data: bool,
+    ) -> Result<()> {
+        // "is_segment_data" marks the items of the list under "EMBEDDED_JSON_KEY".
+        if depth > MAX_DEPTH {
+            return Err(NetworkError::MalformedData(format!(
+                "the value tree is nested deeper than {}",
+                MAX_DEPTH
+            )));
+        }
+        match value {
+            Value::Null => self.bytes.push(NULL_TAG),
+            Value::Bool(false) => self.bytes.push(FALSE_TAG),
+            Value::Bool(true) => self.bytes.push(TRUE_TAG),
+            Value::Number(number) => {
+                if let Some(x) = number.as_u64() {
+                    self.bytes.push(UNSIGNED_TAG);
+                    self.bytes.extend(x.to_le_bytes());
+                } else if let Some(x) = number.as_i64() {
+                    self.bytes.push(SIGNED_TAG);
+                    self.bytes.extend(x.to_le_bytes());
+                } else {
+                    self.bytes.push(FLOAT_TAG);
+                    self.write_float(number.as_f64().unwrap());
+                }
+            }
+            Value::String(string) => {
+                let embedded_value: Option<Value> = if is_segment_data {
+                    serde_json::from_str::<Value>(string)
+                        .ok()
+                        .filter(|embedded_value| embedded_value.is_object())
+                } else {
+                    None
+                };
+                match embedded_value {
+                    Some(embedded_value) => {
+                        self.bytes.push(EMBEDDED_JSON_TAG);
+                        self.write_value(&embedded_value, depth + 1, false)?;
+                    }
+                    None => {
+                        self.bytes.push(STRING_TAG);
+                        self.write_string(string)?;
+                    }
+                }
+            }
+            Value::Array(array) => {
+                if !array.is_empty() && array.iter().all(|item| item.is_f64()) {
+                    self.bytes.push(FLOAT_ARRAY_TAG);
+                    self.write_length(array.len())?;
+                    for item in array.iter() {
+                        self.write_float(item.as_f64().unwrap());
+                    }
+                } else {
+                    self.bytes.push(ARRAY_TAG);
+                    self.write_length(array.len())?;
+                    for item in array.iter() {
+                        self.write_value(item, depth + 1, is_segment_data)?;
+                    }
+                }
+            }
+            Value::Object(object) => {
+                self.bytes.push(OBJECT_TAG);
+                self.write_length(object.len())?;
+                for (key, item) in object.iter() {
+                    self.write_string(key)?;
+                    self.write_value(item, depth + 1, key == EMBEDDED_JSON_KEY)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    precision_enum: PrecisionEnum,
+}
+impl<'a> BinaryReader<'a> {
+    fn read_bytes(self: &mut Self, length: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.position < length {
+            return Err(NetworkError::MalformedData(String::from(
+                "unexpected end of the binary model data",
+            )));
+        }
+        let bytes: &'a [u8] = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+    fn read_array<const N: usize>(self: &mut Self) -> Result<[u8; N]> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+    fn read_length(self: &mut Self) -> Result<usize> {
+        Ok(u32::from_le_bytes(self.read_array()?) as usize)
+    }
+    fn read_float(self: &mut Self) -> Result<Value> {
+        let x: f64 = match self.precision_enum {
+            PrecisionEnum::F64 => f64::from_le_bytes(self.read_array()?),
+            PrecisionEnum::F32 => f32::from_le_bytes(self.read_array()?) as f64,
+        };
+        match Number::from_f64(x) {
+            Some(number) => Ok(Value::Number(number)),
+            None => Err(NetworkError::MalformedData(format!(
+                "{} can not be stored in json",
+                x
+            ))),
+        }
+    }
+    fn read_string(self: &mut Self) -> Result<String> {
+        let length: usize = self.read_length()?;
+        match String::from_utf8(self.read_bytes(length)?.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(e) => Err(NetworkError::MalformedData(e.to_string())),
+        }
+    }
+    fn read_value(self: &mut Self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(NetworkError::MalformedData(format!(
+                "the value tree is nested deeper than {}",
+                MAX_DEPTH
+            )));
+        }
+        let tag: u8 = self.read_array::<1>()?[0];
+        match tag {
+            NULL_TAG => Ok(Value::Null),
+            FALSE_TAG => Ok(Value::Bool(false)),
+            TRUE_TAG => Ok(Value::Bool(true)),
+            UNSIGNED_TAG => Ok(Value::from(u64::from_le_bytes(self.read_array()?))),
+            SIGNED_TAG => Ok(Value::from(i64::from_le_bytes(self.read_array()?))),
+            FLOAT_TAG => self.read_float(),
+            STRING_TAG => Ok(Value::String(self.read_string()?)),
+            ARRAY_TAG | FLOAT_ARRAY_TAG => {
+                let length: usize = self.read_length()?;
+                let mut array: Vec<Value> = Vec::new();
+                for _ in 0..length {
+                    array.push(if tag == FLOAT_ARRAY_TAG {
+                        self.read_float()?
+                    } else {
+                        self.read_value(depth + 1)?
+                    });
+                }
+                Ok(Value::Array(array))
+            }
+            OBJECT_TAG => {
+                let length: usize = self.read_length()?;
+                let mut object: Map<String, Value> = Map::new();
+                for _ in 0..length {
+                    let key: String = self.read_string()?;
+                    object.insert(key, self.read_value(depth + 1)?);
+                }
+                Ok(Value::Object(object))
+            }
+            EMBEDDED_JSON_TAG => Ok(Value::String(serde_json::to_string(
+                &self.read_value(depth + 1)?,
+            )?)),
+            _ => Err(NetworkError::MalformedData(format!(
+                "unknown value tag {} at byte {}",
+                tag,
+                self.position - 1
+            ))),
+        }
+    }
+}
+
+pub fn json_to_binary(json: &str, precision_enum: PrecisionEnum) -> Result<Vec<u8>> {
+    // Layout: magic bytes, version (u16), precision (u8), reserved (u8), the value tree, CRC-32 (u32) of all the bytes before.
+    // Every number is little-endian. The json read back is the same tree, with the keys sorted and no whitespace.
+    let value: Value = serde_json::from_str(json)?;
+    let mut writer: BinaryWriter = BinaryWriter {
+        bytes: Vec::new(),
+        precision_enum,
+    };
+    writer.bytes.extend(MAGIC_BYTES);
+    writer.bytes.extend(FORMAT_VERSION.to_le_bytes());
+    writer.bytes.push(precision_enum.get_id());
+    writer.bytes.push(0);
+    writer.write_value(&value, 0, false)?;
+    let checksum: u32 = get_checksum(&writer.bytes);
+    writer.bytes.extend(checksum.to_le_bytes());
+    Ok(writer.bytes)
+}
+pub fn binary_to_json(bytes: &[u8]) -> Result<String> {
+    if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE || bytes[..4] != MAGIC_BYTES {
+        return Err(NetworkError::MalformedData(String::from(
+            "not a binary model file",
+        )));
+    }
+    let version: u16 = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version > FORMAT_VERSION {
+        return Err(NetworkError::MalformedData(format!(
+            "binary model version {} is newer than the supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    let content_size: usize = bytes.len() - CHECKSUM_SIZE;
+    let checksum: u32 = u32::from_le_bytes(bytes[content_size..].try_into().unwrap());
+    if get_checksum(&bytes[..content_size]) != checksum {
+        return Err(NetworkError::MalformedData(String::from(
+            "checksum mismatch, the binary model file is damaged",
+        )));
+    }
+    let mut reader: BinaryReader = BinaryReader {
+        bytes: &bytes[..content_size],
+        position: HEADER_SIZE,
+        precision_enum: PrecisionEnum::from_id(bytes[6])?,
+    };
+    let value: Value = reader.read_value(0)?;
+    if reader.position != content_size {
+        return Err(NetworkError::MalformedData(format!(
+            "{} bytes left after the value tree",
+            content_size - reader.position
+        )));
+    }
+    Ok(serde_json::to_string(&value)?)
+}
+pub fn convert_json_file_to_binary(
+    json_path: impl AsRef<Path>,
+    binary_path: impl AsRef<Path>,
+    precision_enum: PrecisionEnum,
+) -> Result<()> {
+    let json: String = std::fs::read_to_string(json_path)?;
+    write_atomically(binary_path, &json_to_binary(&json, precision_enum)?)
+}
+pub fn convert_binary_file_to_json(
+    binary_path: impl AsRef<Path>,
+    json_path: impl AsRef<Path>,
+) -> Result<()> {
+    let bytes: Vec<u8> = std::fs::read(binary_path)?;
+    write_atomically(json_path, binary_to_json(&bytes)?.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        binary_to_json, convert_binary_file_to_json, convert_json_file_to_binary, get_checksum,
+        json_to_binary, BinaryWriter, PrecisionEnum, ARRAY_TAG, FORMAT_VERSION, HEADER_SIZE,
+        MAGIC_BYTES, MAX_DEPTH,
+    };
+    use crate::multi_seg_network::dense_layer::DenseLayer;
+    use crate::multi_seg_network::error::NetworkError;
+    use crate::multi_seg_network::flexible_network::ActivationFunctionEnum;
+    use crate::multi_seg_network::function_segment::{
+        FunctionSegment, FunctionSegmentFunctionEnum,
+    };
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+
+    const INPUT_VALUE: [f64; 3] = [0.3, -0.7, 1.2];
+
+    fn network() -> MultiSegNetwork {
+        let mut hidden_layer: DenseLayer = DenseLayer::new();
+        hidden_layer.set_seed(0);
+        hidden_layer.set_activation_function(ActivationFunctionEnum::PReLU);
+        hidden_layer.set_size(3, 8, InitializerEnum::HeUniform);
+        let mut output_layer: DenseLayer = DenseLayer::new();
+        output_layer.set_seed(1);
+        output_layer.set_size(8, 4, InitializerEnum::XavierUniform);
+        let mut soft_max: FunctionSegment = FunctionSegment::new();
+        soft_max
+            .set_function(FunctionSegmentFunctionEnum::TemperatureSoftMax { temperature: 0.5 })
+            .unwrap();
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(hidden_layer).unwrap();
+        multi_seg.push_seg(output_layer).unwrap();
+        multi_seg.push_seg(soft_max).unwrap();
+        multi_seg
+    }
+    fn get_output(multi_seg: &mut MultiSegNetwork) -> Vec<f64> {
+        multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        multi_seg.next().unwrap();
+        multi_seg.get_output()
+    }
+
+    #[test]
+    fn test_checksum_is_crc32() {
+        assert_eq!(get_checksum(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut multi_seg: MultiSegNetwork = network();
+        let output: Vec<f64> = get_output(&mut multi_seg);
+        let json: String = multi_seg.export_data();
+
+        let bytes: Vec<u8> = multi_seg.export_binary(PrecisionEnum::F64).unwrap();
+        assert!(bytes.len() < json.len());
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        imported_seg.import_binary(&bytes).unwrap();
+        assert_eq!(get_output(&mut imported_seg), output);
+        assert_eq!(imported_seg.export_data(), json);
+
+        let small_bytes: Vec<u8> = multi_seg.export_binary(PrecisionEnum::F32).unwrap();
+        assert!(small_bytes.len() < bytes.len());
+        imported_seg.import_binary(&small_bytes).unwrap();
+        for (x, y) in get_output(&mut imported_seg).iter().zip(output.iter()) {
+            assert!((x - y).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_binary_rejects_damaged_data() {
+        let bytes: Vec<u8> = json_to_binary(&network().export_data(), PrecisionEnum::F64).unwrap();
+        assert!(binary_to_json(&bytes).is_ok());
+
+        let mut damaged_bytes: Vec<u8> = bytes.clone();
+        damaged_bytes[20] ^= 0x10;
+        assert!(matches!(
+            binary_to_json(&damaged_bytes),
+            Err(NetworkError::MalformedData(_))
+        ));
+        assert!(binary_to_json(&bytes[..bytes.len() - 1]).is_err());
+        assert!(binary_to_json(b"{\"types\":[]}").is_err());
+
+        let mut newer_bytes: Vec<u8> = bytes.clone();
+        newer_bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let content_size: usize = newer_bytes.len() - 4;
+        let checksum: u32 = get_checksum(&newer_bytes[..content_size]);
+        newer_bytes[content_size..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(binary_to_json(&newer_bytes).is_err());
+    }
+
+    #[test]
+    fn test_only_the_segment_data_is_embedded() {
+        let json: &str = r#"{"data":["{\"w\":[0.5],\"b\":[1]}","[1,2]","{not json"],"name":"{\"w\": [0.5]}","types":["[1, 2]"]}"#;
+        let round_trip_json: String =
+            binary_to_json(&json_to_binary(json, PrecisionEnum::F64).unwrap()).unwrap();
+        assert_eq!(
+            round_trip_json,
+            r#"{"data":["{\"b\":[1],\"w\":[0.5]}","[1,2]","{not json"],"name":"{\"w\": [0.5]}","types":["[1, 2]"]}"#
+        );
+        // The other strings are kept byte for byte, the child segment data is the same tree with the keys sorted.
+    }
+
+    #[test]
+    fn test_length_must_fit_in_u32() {
+        let mut writer: BinaryWriter = BinaryWriter {
+            bytes: Vec::new(),
+            precision_enum: PrecisionEnum::F64,
+        };
+        assert!(writer.write_length(u32::MAX as usize).is_ok());
+        if let Some(length) = (u32::MAX as usize).checked_add(1) {
+            assert!(matches!(
+                writer.write_length(length),
+                Err(NetworkError::MalformedData(_))
+            ));
+        }
+        assert_eq!(writer.bytes, u32::MAX.to_le_bytes());
+    }
+
+    #[test]
+    fn test_binary_rejects_deep_nesting() {
+        let nested_json: String = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        assert!(binary_to_json(&json_to_binary(&nested_json, PrecisionEnum::F64).unwrap()).is_ok());
+
+        let mut bytes: Vec<u8> = Vec::from(MAGIC_BYTES);
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bytes.extend([0, 0]);
+        assert_eq!(bytes.len(), HEADER_SIZE);
+        for _ in 0..MAX_DEPTH * 4 {
+            bytes.push(ARRAY_TAG);
+            bytes.extend(1u32.to_le_bytes());
+        }
+        bytes.push(ARRAY_TAG);
+        bytes.extend(0u32.to_le_bytes());
+        let checksum: u32 = get_checksum(&bytes);
+        bytes.extend(checksum.to_le_bytes());
+        assert!(matches!(
+            binary_to_json(&bytes),
+            Err(NetworkError::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_convert_model_files() {
+        let directory =
+            std::env::temp_dir().join(format!("rust-rl-binary-format-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut multi_seg: MultiSegNetwork = network();
+        let output: Vec<f64> = get_output(&mut multi_seg);
+        multi_seg
+            .save_binary(directory.join("net.bin"), PrecisionEnum::F64)
+            .unwrap();
+
+        convert_binary_file_to_json(directory.join("net.bin"), directory.join("net.json")).unwrap();
+        convert_json_file_to_binary(
+            directory.join("net.json"),
+            directory.join("net_copy.bin"),
+            PrecisionEnum::F64,
+        )
+        .unwrap();
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        imported_seg
+            .import_data(std::fs::read_to_string(directory.join("net.json")).unwrap())
+            .unwrap();
+        assert_eq!(get_output(&mut imported_seg), output);
+        imported_seg
+            .load_binary(directory.join("net_copy.bin"))
+            .unwrap();
+        assert_eq!(get_output(&mut imported_seg), output);
+        assert!(matches!(
+            imported_seg.load_binary(directory.join("missing.bin")),
+            Err(NetworkError::FileError(_))
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
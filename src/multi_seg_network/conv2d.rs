@@ -0,0 +1,560 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/conv2d.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::flexible_network::{ActivationFunction, ActivationFunctionEnum};
+use crate::multi_seg_network::initializer::InitializerEnum;
+use crate::multi_seg_network::layer_parameters::LayerParameters;
+use crate::multi_seg_network::loss_function::LossFunctionEnum;
+use crate::multi_seg_network::optimizer::{OptimizerEnum, OptimizerState};
+use crate::multi_seg_network::Segment;
+
+pub fn get_output_length(
+    input_length: usize,
+    kernel_length: usize,
+    stride: usize,
+    padding: usize,
+) -> Result<usize> {
+    // Return how many times the kernel fits in the padded input along one axis.
+    if kernel_length == 0 || stride == 0 || kernel_length > input_length + 2 * padding {
+        return Err(NetworkError::InvalidShape(format!(
+            "kernel {} with stride {} does not fit the input {} with padding {}",
+            kernel_length, stride, input_length, padding
+        )));
+    }
+    Ok((input_length + 2 * padding - kernel_length) / stride + 1)
+}
+
+pub struct Conv2D {
+    // 2D convolution, the values are in the order of [channel][row][column].
+    input_shape: [usize; 3],
+    output_shape: [usize; 3],
+    // [channel count, height, width]
+    kernel_size: [usize; 2],
+    stride: [usize; 2],
+    padding: [usize; 2],
+    // [height, width], the padding is filled with zero.
+    params: LayerParameters,
+    // Kernels indexed by [((output channel * input channel count + input channel) * kernel height + row) * kernel width + column],
+    // the bias term and the activation function are shared by an output channel.
+    input_value: Vec<f64>,
+    sum: Vec<f64>,
+    output_value: Vec<f64>,
+}
+#[derive(Serialize, Deserialize)]
+struct Conv2DData {
+    i_s: [usize; 3],
+    o_c: usize,
+    k_s: [usize; 2],
+    s: [usize; 2],
+    p: [usize; 2],
+    w: Vec<f64>,
+    b: Vec<f64>,
+    a_fn: ActivationFunctionEnum,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    a_p: Vec<f64>,
+    #[serde(default, skip_serializing_if = "OptimizerState::is_empty")]
+    o_s: OptimizerState,
+    #[serde(default)]
+    opt: OptimizerEnum,
+    #[serde(default)]
+    l_fn: LossFunctionEnum,
+}
+#[allow(dead_code)]
+impl Segment for Conv2D {
+    fn new() -> Self {
+        Self {
+            input_shape: [0; 3],
+            output_shape: [0; 3],
+            kernel_size: [1; 2],
+            stride: [1; 2],
+            padding: [0; 2],
+            params: LayerParameters::new(),
+            input_value: Vec::new(),
+            sum: Vec::new(),
+            output_value: Vec::new(),
+        }
+    }
+    fn get_type(self: &Self) -> String {
+        String::from("Conv2D")
+    }
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        let input_size: usize = self.input_shape.iter().product();
+        if input.len() != input_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: input_size,
+                actual: input.len(),
+            });
+        }
+        self.input_value = input;
+        Ok(())
+    }
+    fn get_output(self: &mut Self) -> Vec<f64> {
+        self.output_value.clone()
+    }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from(self.input_shape))
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from(self.output_shape))
+    }
+    fn export_data(self: &mut Self) -> String {
+        let data = Conv2DData {
+            i_s: self.input_shape,
+            o_c: self.output_shape[0],
+            k_s: self.kernel_size,
+            s: self.stride,
+            p: self.padding,
+            w: self.params.w.clone(),
+            b: self.params.b.clone(),
+            a_fn: self.params.activation_fn_enum,
+            a_p: self.params.activation_params.clone(),
+            o_s: self.params.optimizer_state.clone(),
+            opt: self.params.optimizer_enum,
+            l_fn: self.params.loss_fn_enum,
+        };
+        serde_json::to_string(&data).unwrap()
+    }
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: Conv2DData = serde_json::from_str(data.as_str())?;
+        let mut imported_conv: Conv2D = Conv2D::new();
+        imported_conv.set_activation_function(data.a_fn);
+        imported_conv.set_size(
+            data.i_s,
+            data.o_c,
+            data.k_s,
+            data.s,
+            data.p,
+            InitializerEnum::Constant { value: 0.0 },
+        )?;
+        if data.w.len() != imported_conv.params.w.len()
+            || data.b.len() != imported_conv.params.b.len()
+        {
+            return Err(NetworkError::MalformedData(format!(
+                "convolution needs {} weights and {} bias terms but has {} and {}",
+                imported_conv.params.w.len(),
+                imported_conv.params.b.len(),
+                data.w.len(),
+                data.b.len()
+            )));
+        }
+        imported_conv.params.w = data.w;
+        imported_conv.params.b = data.b;
+        imported_conv
+            .params
+            .import_activation_parameters(data.a_p)?;
+        imported_conv.params.optimizer_state = data.o_s;
+        imported_conv.params.optimizer_enum = data.opt;
+        imported_conv.params.loss_fn_enum = data.l_fn;
+        imported_conv.params.rng = self.params.rng.clone();
+        *self = imported_conv;
+        Ok(())
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        (self.sum, self.output_value) = self.calc_value(&self.input_value);
+        Ok(())
+    }
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Run every input of the batch from its slice, only the values of the last one are kept for "accumulate_gradients".
+        let input_size: usize = self.input_shape.iter().product();
+        for input in input_batch.iter() {
+            if input.len() != input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: input_size,
+                    actual: input.len(),
+                });
+            }
+        }
+        let mut output_batch: Vec<Vec<f64>> = Vec::with_capacity(input_batch.len());
+        for input in input_batch.iter() {
+            let (sum, output_value) = self.calc_value(input);
+            output_batch.push(output_value);
+            self.sum = sum;
+        }
+        if let (Some(input), Some(output)) = (input_batch.last(), output_batch.last()) {
+            self.input_value = input.clone();
+            self.output_value = output.clone();
+        }
+        Ok(output_batch)
+    }
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        self.params.loss_fn_enum
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_value.len(),
+                actual: output_partial.len(),
+            });
+        }
+        let [input_channels, input_height, input_width] = self.input_shape;
+        let [output_channels, output_height, output_width] = self.output_shape;
+        let [kernel_height, kernel_width] = self.kernel_size;
+        let derivative = ActivationFunction::get_derivative(self.params.activation_fn_enum);
+        let parameter_derivative =
+            ActivationFunction::get_parameter_derivative(self.params.activation_fn_enum);
+        let parameter_count: usize = self.params.get_activation_parameter_count();
+        let trainable: bool = !self.params.activation_params_gradient.is_empty();
+        let mut input_partial: Vec<f64> = vec![0.0; self.input_value.len()];
+        for oc in 0..output_channels {
+            let p: Vec<f64> = self.params.get_activation_parameters(oc).to_vec();
+            for oy in 0..output_height {
+                for ox in 0..output_width {
+                    let index: usize = (oc * output_height + oy) * output_width + ox;
+                    let derivative_c_b: f64 =
+                        output_partial[index] * derivative(self.sum[index], &p);
+                    self.params.b_gradient[oc] += derivative_c_b;
+                    if trainable {
+                        for (k, d) in parameter_derivative(self.sum[index], &p).iter().enumerate() {
+                            self.params.activation_params_gradient[oc * parameter_count + k] +=
+                                output_partial[index] * d;
+                        }
+                    }
+                    for ic in 0..input_channels {
+                        for ky in 0..kernel_height {
+                            let Some(iy) = self.get_input_index(oy, ky, 0) else {
+                                continue;
+                            };
+                            for kx in 0..kernel_width {
+                                let Some(ix) = self.get_input_index(ox, kx, 1) else {
+                                    continue;
+                                };
+                                let w_index: usize = ((oc * input_channels + ic) * kernel_height
+                                    + ky)
+                                    * kernel_width
+                                    + kx;
+                                let input_index: usize =
+                                    (ic * input_height + iy) * input_width + ix;
+                                self.params.w_gradient[w_index] +=
+                                    derivative_c_b * self.input_value[input_index];
+                                input_partial[input_index] +=
+                                    derivative_c_b * self.params.w[w_index];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.params.gradient_count += 1;
+        Ok(input_partial)
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        self.params.parameters()
+    }
+    fn parameter_count(self: &Self) -> usize {
+        self.params.parameter_count()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        self.params.set_parameters(parameters)
+    }
+    fn gradients(self: &Self) -> Vec<f64> {
+        self.params.gradients()
+    }
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        self.params.set_gradients(gradients)
+    }
+    fn apply_gradients(self: &mut Self, learning_rate: f64) {
+        self.params.apply_gradients(learning_rate);
+    }
+    fn zero_grad(self: &mut Self) {
+        self.params.zero_grad();
+    }
+}
+#[allow(dead_code)]
+impl Conv2D {
+    fn get_input_index(
+        self: &Self,
+        output_index: usize,
+        kernel_index: usize,
+        axis: usize,
+    ) -> Option<usize> {
+        // Return the input row (axis 0) or column (axis 1) under the kernel, or None if it is in the padding.
+        let index: usize = output_index * self.stride[axis] + kernel_index;
+        if index < self.padding[axis] || index - self.padding[axis] >= self.input_shape[axis + 1] {
+            None
+        } else {
+            Some(index - self.padding[axis])
+        }
+    }
+    fn calc_value(self: &Self, input_value: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        // Return the values before and after the activation function of the input.
+        let [input_channels, input_height, input_width] = self.input_shape;
+        let [output_channels, output_height, output_width] = self.output_shape;
+        let [kernel_height, kernel_width] = self.kernel_size;
+        let activation_fn = ActivationFunction::get_function(self.params.activation_fn_enum);
+        let mut sum_list: Vec<f64> = vec![0.0; output_channels * output_height * output_width];
+        let mut output_value: Vec<f64> = vec![0.0; sum_list.len()];
+        for oc in 0..output_channels {
+            let p: &[f64] = self.params.get_activation_parameters(oc);
+            for oy in 0..output_height {
+                for ox in 0..output_width {
+                    let mut sum: f64 = self.params.b[oc];
+                    for ic in 0..input_channels {
+                        for ky in 0..kernel_height {
+                            let Some(iy) = self.get_input_index(oy, ky, 0) else {
+                                continue;
+                            };
+                            for kx in 0..kernel_width {
+                                let Some(ix) = self.get_input_index(ox, kx, 1) else {
+                                    continue;
+                                };
+                                sum += self.params.w[((oc * input_channels + ic) * kernel_height
+                                    + ky)
+                                    * kernel_width
+                                    + kx]
+                                    * input_value[(ic * input_height + iy) * input_width + ix];
+                            }
+                        }
+                    }
+                    let index: usize = (oc * output_height + oy) * output_width + ox;
+                    sum_list[index] = sum;
+                    output_value[index] = activation_fn(sum, p);
+                }
+            }
+        }
+        (sum_list, output_value)
+    }
+    pub fn set_size(
+        self: &mut Self,
+        input_shape: [usize; 3],
+        output_channels: usize,
+        kernel_size: [usize; 2],
+        stride: [usize; 2],
+        padding: [usize; 2],
+        initializer_enum: InitializerEnum,
+    ) -> Result<()> {
+        // Set the [channel count, height, width] of the input, the [height, width] of the kernel, stride and padding,
+        // then initialize the kernels and set the bias terms to zero.
+        let output_height: usize =
+            get_output_length(input_shape[1], kernel_size[0], stride[0], padding[0])?;
+        let output_width: usize =
+            get_output_length(input_shape[2], kernel_size[1], stride[1], padding[1])?;
+        let fan_in: usize = input_shape[0] * kernel_size[0] * kernel_size[1];
+        self.input_shape = input_shape;
+        self.output_shape = [output_channels, output_height, output_width];
+        self.kernel_size = kernel_size;
+        self.stride = stride;
+        self.padding = padding;
+        self.params.init(initializer_enum, fan_in, output_channels);
+        self.input_value.clear();
+        self.sum.clear();
+        self.output_value.clear();
+        Ok(())
+    }
+    pub fn set_activation_function(self: &mut Self, activation_fn_enum: ActivationFunctionEnum) {
+        // Change the activation function of every output channel, and reset its parameters to the default.
+        self.params.set_activation_function(activation_fn_enum);
+    }
+    pub fn set_seed(self: &mut Self, seed: u64) {
+        // Reset the random number generator used by "set_size", the same seed gives the same weights.
+        self.params.set_seed(seed);
+    }
+    pub fn set_optimizer(self: &mut Self, optimizer_enum: OptimizerEnum) {
+        // Select the optimizer used by "fitting_batch".
+        self.params.optimizer_enum = optimizer_enum;
+    }
+    pub fn set_loss_function(self: &mut Self, loss_fn_enum: LossFunctionEnum) {
+        // Select the loss of the output used by "fitting_batch".
+        self.params.loss_fn_enum = loss_fn_enum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Conv2D;
+    use crate::multi_seg_network::dense_layer::DenseLayer;
+    use crate::multi_seg_network::flatten::Flatten;
+    use crate::multi_seg_network::flexible_network::ActivationFunctionEnum;
+    use crate::multi_seg_network::function_segment::{
+        FunctionSegment, FunctionSegmentFunctionEnum,
+    };
+    use crate::multi_seg_network::gradient_check::{get_lost, gradient_check, relative_error};
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::loss_function::LossFunctionEnum;
+    use crate::multi_seg_network::pooling::{Pooling2D, PoolingEnum};
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+
+    #[test]
+    fn test_conv2d_with_stride_and_padding() {
+        let mut conv: Conv2D = Conv2D::new();
+        conv.set_size(
+            [1, 3, 3],
+            1,
+            [2, 2],
+            [2, 2],
+            [1, 1],
+            InitializerEnum::Constant { value: 1.0 },
+        )
+        .unwrap();
+        conv.params.b = Vec::from([0.5]);
+
+        conv.set_input(Vec::from([1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]))
+            .unwrap();
+        conv.next().unwrap();
+
+        assert_eq!(conv.get_output_shape(), Some(Vec::from([1, 2, 2])));
+        assert_eq!(conv.get_output(), Vec::from([1.5, 5.5, 11.5, 28.5]));
+        assert_eq!(
+            conv.forward_batch(&[
+                vec![0.0; 9],
+                Vec::from([1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0])
+            ])
+            .unwrap(),
+            Vec::from([vec![0.5; 4], Vec::from([1.5, 5.5, 11.5, 28.5])])
+        );
+        assert!(conv.accumulate_gradients(vec![1.0; 4]).is_ok());
+        // The values of the last input are kept for the backward pass.
+        assert!(conv
+            .set_size(
+                [1, 3, 3],
+                1,
+                [6, 2],
+                [1, 1],
+                [1, 1],
+                InitializerEnum::default()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_conv2d_gradients_match_finite_difference() {
+        const H: f64 = 1e-6;
+        let input_value: Vec<f64> = (0..2 * 4 * 5)
+            .map(|i| ((i * 7 % 11) as f64 - 5.0) / 5.0)
+            .collect::<Vec<f64>>();
+        let anticipated: Vec<f64> = (0..3 * 2 * 3)
+            .map(|i| (i % 4) as f64 / 4.0)
+            .collect::<Vec<f64>>();
+        let mut conv: Conv2D = Conv2D::new();
+        conv.set_activation_function(ActivationFunctionEnum::ScaledTanh);
+        conv.set_size(
+            [2, 4, 5],
+            3,
+            [3, 2],
+            [1, 2],
+            [0, 1],
+            InitializerEnum::HeUniform,
+        )
+        .unwrap();
+
+        let report = gradient_check(&mut conv, &input_value, &anticipated, H).unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+
+        get_lost(&mut conv, &input_value, &anticipated).unwrap();
+        let output_partial: Vec<f64> = conv
+            .get_output()
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| 2.0 * (o - a) / anticipated.len() as f64)
+            .collect::<Vec<f64>>();
+        conv.accumulate_gradients(output_partial).unwrap();
+        let lost = |conv: &mut Conv2D| get_lost(conv, &input_value, &anticipated).unwrap();
+        for i in 0..conv.params.w.len() {
+            conv.params.w[i] += H;
+            let lost_plus: f64 = lost(&mut conv);
+            conv.params.w[i] -= 2.0 * H;
+            let lost_minus: f64 = lost(&mut conv);
+            conv.params.w[i] += H;
+            let numerical_gradient: f64 = (lost_plus - lost_minus) / (2.0 * H);
+            assert!(relative_error(conv.params.w_gradient[i], numerical_gradient) < 1e-6);
+        }
+        for oc in 0..conv.params.b.len() {
+            conv.params.b[oc] += H;
+            let lost_plus: f64 = lost(&mut conv);
+            conv.params.b[oc] -= 2.0 * H;
+            let lost_minus: f64 = lost(&mut conv);
+            conv.params.b[oc] += H;
+            let numerical_gradient: f64 = (lost_plus - lost_minus) / (2.0 * H);
+            assert!(relative_error(conv.params.b_gradient[oc], numerical_gradient) < 1e-6);
+        }
+        for k in 0..conv.params.activation_params.len() {
+            conv.params.activation_params[k] += H;
+            let lost_plus: f64 = lost(&mut conv);
+            conv.params.activation_params[k] -= 2.0 * H;
+            let lost_minus: f64 = lost(&mut conv);
+            conv.params.activation_params[k] += H;
+            let numerical_gradient: f64 = (lost_plus - lost_minus) / (2.0 * H);
+            assert!(
+                relative_error(
+                    conv.params.activation_params_gradient[k],
+                    numerical_gradient
+                ) < 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_convolutional_pipeline_learns_where_the_player_is() {
+        // A 5x5 screen with the player in one of the corners, the answer is the corner.
+        let corner_list: [[usize; 2]; 4] = [[0, 0], [0, 4], [4, 0], [4, 4]];
+        let data_pair_list: Vec<[Vec<f64>; 2]> = corner_list
+            .iter()
+            .enumerate()
+            .map(|(i, &[y, x])| {
+                let mut screen: Vec<f64> = vec![0.2; 25];
+                screen[y * 5 + x] = 1.0;
+                let mut anticipated: Vec<f64> = vec![0.0; 4];
+                anticipated[i] = 1.0;
+                [screen, anticipated]
+            })
+            .collect::<Vec<[Vec<f64>; 2]>>();
+
+        let mut conv: Conv2D = Conv2D::new();
+        conv.set_activation_function(ActivationFunctionEnum::ReLU);
+        conv.set_size(
+            [1, 5, 5],
+            4,
+            [3, 3],
+            [1, 1],
+            [1, 1],
+            InitializerEnum::HeUniform,
+        )
+        .unwrap();
+        let mut pooling: Pooling2D = Pooling2D::new();
+        pooling.set_pooling(PoolingEnum::Max);
+        pooling.set_size([4, 5, 5], [2, 2], [2, 2]).unwrap();
+        let mut flatten: Flatten = Flatten::new();
+        flatten.set_input_shape([4, 2, 2]);
+        let mut output_layer: DenseLayer = DenseLayer::new();
+        output_layer.set_size(16, 4, InitializerEnum::XavierUniform);
+        let mut output_function: FunctionSegment = FunctionSegment::new();
+        output_function
+            .set_function(FunctionSegmentFunctionEnum::SoftMax)
+            .unwrap();
+
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(conv).unwrap();
+        multi_seg.push_seg(pooling).unwrap();
+        multi_seg.push_seg(flatten).unwrap();
+        multi_seg.push_seg(output_layer).unwrap();
+        multi_seg.push_seg(output_function).unwrap();
+        multi_seg.set_loss_function(LossFunctionEnum::PolicyGradient);
+        for _ in 0..300 {
+            multi_seg.fitting_batch(&data_pair_list, 0.1).unwrap();
+        }
+        for data_pair in data_pair_list.iter() {
+            multi_seg.set_input(data_pair[0].clone()).unwrap();
+            multi_seg.next().unwrap();
+            let output: Vec<f64> = multi_seg.get_output();
+            let answer: usize = data_pair[1].iter().position(|&a| a == 1.0).unwrap();
+            assert!(output[answer] > 0.9, "{:?}", output);
+        }
+
+        let data: String = multi_seg.export_data();
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        imported_seg.import_data(data.clone()).unwrap();
+        assert_eq!(imported_seg.export_data(), data);
+        imported_seg
+            .set_input(data_pair_list[0][0].clone())
+            .unwrap();
+        imported_seg.next().unwrap();
+        multi_seg.set_input(data_pair_list[0][0].clone()).unwrap();
+        multi_seg.next().unwrap();
+        assert_eq!(imported_seg.get_output(), multi_seg.get_output());
+    }
+}
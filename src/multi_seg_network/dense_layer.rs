@@ -0,0 +1,590 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/dense_layer.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::flexible_network::{ActivationFunction, ActivationFunctionEnum};
+use crate::multi_seg_network::initializer::InitializerEnum;
+use crate::multi_seg_network::layer_parameters::LayerParameters;
+use crate::multi_seg_network::loss_function::LossFunctionEnum;
+use crate::multi_seg_network::optimizer::{OptimizerEnum, OptimizerState};
+use crate::multi_seg_network::{get_lost_partial, Segment};
+
+pub struct DenseLayer {
+    // Fully connected layer, the same as a FlexibleNetwork layer made by "connect_layer" but stored in contiguous vectors.
+    input_size: usize,
+    output_size: usize,
+    params: LayerParameters,
+    // The weight matrix is indexed by [to * input_size + from].
+    batch_size: usize,
+    input_value: Vec<f64>,
+    sum: Vec<f64>,
+    output_value: Vec<f64>,
+    // Values of every sample in the last "next" or "forward_batch", indexed by [sample * size + index].
+}
+#[derive(Serialize, Deserialize)]
+struct DenseLayerData {
+    i_len: usize,
+    o_len: usize,
+    w: Vec<f64>,
+    b: Vec<f64>,
+    a_fn: ActivationFunctionEnum,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    a_p: Vec<f64>,
+    // The default parameters of "a_fn" are used if it is missing.
+    #[serde(default, skip_serializing_if = "OptimizerState::is_empty")]
+    o_s: OptimizerState,
+    #[serde(default)]
+    opt: OptimizerEnum,
+    #[serde(default)]
+    l_fn: LossFunctionEnum,
+}
+#[allow(dead_code)]
+impl Segment for DenseLayer {
+    fn new() -> Self {
+        Self {
+            input_size: 0,
+            output_size: 0,
+            params: LayerParameters::new(),
+            batch_size: 0,
+            input_value: Vec::new(),
+            sum: Vec::new(),
+            output_value: Vec::new(),
+        }
+    }
+    fn get_type(self: &Self) -> String {
+        String::from("DenseLayer")
+    }
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        if input.len() != self.input_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.input_size,
+                actual: input.len(),
+            });
+        }
+        self.input_value = input;
+        self.batch_size = 1;
+        Ok(())
+    }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from([self.input_size]))
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from([self.output_size]))
+    }
+    fn get_output(self: &mut Self) -> Vec<f64> {
+        // Return the output of the last sample.
+        let start: usize = self.output_value.len() - self.output_size.min(self.output_value.len());
+        self.output_value[start..].to_vec()
+    }
+    fn export_data(self: &mut Self) -> String {
+        let data = DenseLayerData {
+            i_len: self.input_size,
+            o_len: self.output_size,
+            w: self.params.w.clone(),
+            b: self.params.b.clone(),
+            a_fn: self.params.activation_fn_enum,
+            a_p: self.params.activation_params.clone(),
+            o_s: self.params.optimizer_state.clone(),
+            opt: self.params.optimizer_enum,
+            l_fn: self.params.loss_fn_enum,
+        };
+        serde_json::to_string(&data).unwrap()
+    }
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: DenseLayerData = serde_json::from_str(data.as_str())?;
+        if data.w.len() != data.i_len * data.o_len || data.b.len() != data.o_len {
+            return Err(NetworkError::MalformedData(format!(
+                "dense layer from {} to {} nodes has {} weights and {} bias terms",
+                data.i_len,
+                data.o_len,
+                data.w.len(),
+                data.b.len()
+            )));
+        }
+        let mut imported_layer: DenseLayer = DenseLayer::new();
+        imported_layer.input_size = data.i_len;
+        imported_layer.output_size = data.o_len;
+        imported_layer.params.w = data.w;
+        imported_layer.params.b = data.b;
+        imported_layer.set_activation_function(data.a_fn);
+        imported_layer
+            .params
+            .import_activation_parameters(data.a_p)?;
+        imported_layer.params.optimizer_state = data.o_s;
+        imported_layer.params.optimizer_enum = data.opt;
+        imported_layer.params.loss_fn_enum = data.l_fn;
+        imported_layer.zero_grad();
+        imported_layer.params.rng = self.params.rng.clone();
+        *self = imported_layer;
+        Ok(())
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        self.calc_value();
+        Ok(())
+    }
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        self.params.loss_fn_enum
+    }
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Run every input of the batch, and keep their values for "accumulate_gradients_batch".
+        for input in input_batch.iter() {
+            if input.len() != self.input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.input_size,
+                    actual: input.len(),
+                });
+            }
+        }
+        self.input_value = input_batch.concat();
+        self.batch_size = input_batch.len();
+        self.calc_value();
+        Ok(self
+            .output_value
+            .chunks(self.output_size.max(1))
+            .map(|output| output.to_vec())
+            .collect::<Vec<Vec<f64>>>())
+    }
+    fn fitting_batch(self: &mut Self, data_pair_list: &[[Vec<f64>; 2]], rate: f64) -> Result<()> {
+        // Run the whole batch at once instead of one "next" per pair.
+        let input_batch: Vec<Vec<f64>> = data_pair_list
+            .iter()
+            .map(|data_pair| data_pair[0].clone())
+            .collect::<Vec<Vec<f64>>>();
+        let output_batch: Vec<Vec<f64>> = self.forward_batch(&input_batch)?;
+        let mut output_partial_batch: Vec<Vec<f64>> = Vec::new();
+        for (output, data_pair) in output_batch.iter().zip(data_pair_list.iter()) {
+            output_partial_batch.push(get_lost_partial(
+                self.params.loss_fn_enum,
+                output,
+                &data_pair[1],
+            )?);
+        }
+        self.zero_grad();
+        self.accumulate_gradients_batch(&output_partial_batch)?;
+        self.apply_gradients(rate);
+        self.zero_grad();
+        Ok(())
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        self.keep_last_sample();
+        // The partial is of the output given by "get_output", which is the last sample after "forward_batch".
+        let mut input_partial_batch: Vec<Vec<f64>> =
+            self.accumulate_gradients_batch(&Vec::from([output_partial]))?;
+        Ok(input_partial_batch.remove(0))
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        self.params.parameters()
+    }
+    fn parameter_count(self: &Self) -> usize {
+        self.params.parameter_count()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        self.params.set_parameters(parameters)
+    }
+    fn gradients(self: &Self) -> Vec<f64> {
+        self.params.gradients()
+    }
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        self.params.set_gradients(gradients)
+    }
+    fn apply_gradients(self: &mut Self, learning_rate: f64) {
+        self.params.apply_gradients(learning_rate);
+    }
+    fn zero_grad(self: &mut Self) {
+        self.params.zero_grad();
+    }
+}
+#[allow(dead_code)]
+impl DenseLayer {
+    fn calc_value(self: &mut Self) {
+        // Calculate the output of every sample in the input values.
+        let activation_fn = ActivationFunction::get_function(self.params.activation_fn_enum);
+        self.sum = vec![0.0; self.batch_size * self.output_size];
+        self.output_value = vec![0.0; self.batch_size * self.output_size];
+        for s in 0..self.batch_size {
+            let input: &[f64] = &self.input_value[s * self.input_size..(s + 1) * self.input_size];
+            for o in 0..self.output_size {
+                let row: &[f64] = &self.params.w[o * self.input_size..(o + 1) * self.input_size];
+                let sum: f64 = self.params.b[o]
+                    + row
+                        .iter()
+                        .zip(input.iter())
+                        .map(|(w, x)| w * x)
+                        .sum::<f64>();
+                self.sum[s * self.output_size + o] = sum;
+                self.output_value[s * self.output_size + o] =
+                    activation_fn(sum, self.params.get_activation_parameters(o));
+            }
+        }
+    }
+    fn keep_last_sample(self: &mut Self) {
+        // Drop the values of the other samples of the last "forward_batch".
+        if self.batch_size > 1 {
+            let drop_count: usize = self.batch_size - 1;
+            self.input_value.drain(..drop_count * self.input_size);
+            self.sum.drain(..drop_count * self.output_size);
+            self.output_value.drain(..drop_count * self.output_size);
+            self.batch_size = 1;
+        }
+    }
+    pub fn accumulate_gradients_batch(
+        self: &mut Self,
+        output_partial_batch: &[Vec<f64>],
+    ) -> Result<Vec<Vec<f64>>> {
+        // Take $\frac{\partial lost}{\partial output}$ of every sample of the last "forward_batch",
+        // add the gradients into the layer, and return $\frac{\partial lost}{\partial input}$ of every sample.
+        if output_partial_batch.len() != self.batch_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.batch_size,
+                actual: output_partial_batch.len(),
+            });
+        }
+        for output_partial in output_partial_batch.iter() {
+            if output_partial.len() != self.output_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.output_size,
+                    actual: output_partial.len(),
+                });
+            }
+        }
+        if self.params.w_gradient.len() != self.params.w.len() {
+            self.zero_grad();
+        }
+        let derivative = ActivationFunction::get_derivative(self.params.activation_fn_enum);
+        let parameter_derivative =
+            ActivationFunction::get_parameter_derivative(self.params.activation_fn_enum);
+        let parameter_count: usize = self.params.get_activation_parameter_count();
+        let trainable: bool = !self.params.activation_params_gradient.is_empty();
+        let mut input_partial_batch: Vec<Vec<f64>> = Vec::new();
+        for (s, output_partial) in output_partial_batch
+            .iter()
+            .enumerate()
+            .take(self.batch_size)
+        {
+            let input: &[f64] = &self.input_value[s * self.input_size..(s + 1) * self.input_size];
+            let mut input_partial: Vec<f64> = vec![0.0; self.input_size];
+            for (o, &partial) in output_partial.iter().enumerate().take(self.output_size) {
+                let sum: f64 = self.sum[s * self.output_size + o];
+                let p: Vec<f64> = self.params.get_activation_parameters(o).to_vec();
+                let derivative_c_b: f64 = partial * derivative(sum, &p);
+                for i in 0..self.input_size {
+                    self.params.w_gradient[o * self.input_size + i] += derivative_c_b * input[i];
+                    input_partial[i] += derivative_c_b * self.params.w[o * self.input_size + i];
+                }
+                self.params.b_gradient[o] += derivative_c_b;
+                if trainable {
+                    for (k, d) in parameter_derivative(sum, &p).iter().enumerate() {
+                        self.params.activation_params_gradient[o * parameter_count + k] +=
+                            partial * d;
+                    }
+                }
+            }
+            input_partial_batch.push(input_partial);
+        }
+        self.params.gradient_count += self.batch_size;
+        Ok(input_partial_batch)
+    }
+    pub fn set_size(
+        self: &mut Self,
+        input_size: usize,
+        output_size: usize,
+        initializer_enum: InitializerEnum,
+    ) {
+        // Resize the layer, initialize the weights and set the bias terms to zero.
+        self.input_size = input_size;
+        self.output_size = output_size;
+        self.params.init(initializer_enum, input_size, output_size);
+        self.batch_size = 0;
+        self.input_value.clear();
+        self.sum.clear();
+        self.output_value.clear();
+    }
+    pub fn set_weights(self: &mut Self, w: Vec<Vec<f64>>, b: Vec<f64>) -> Result<()> {
+        // Set the weights indexed by [to][from] and the bias terms of the output nodes.
+        if w.len() != self.output_size || b.len() != self.output_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_size,
+                actual: if w.len() != self.output_size {
+                    w.len()
+                } else {
+                    b.len()
+                },
+            });
+        }
+        for row in w.iter() {
+            if row.len() != self.input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.input_size,
+                    actual: row.len(),
+                });
+            }
+        }
+        self.params.w = w.concat();
+        self.params.b = b;
+        Ok(())
+    }
+    pub fn set_activation_function(self: &mut Self, activation_fn_enum: ActivationFunctionEnum) {
+        // Change the activation function of every output node, and reset its parameters to the default.
+        self.params.set_activation_function(activation_fn_enum);
+    }
+    pub fn set_activation_parameters(
+        self: &mut Self,
+        to: usize,
+        activation_params: Vec<f64>,
+    ) -> Result<()> {
+        // Set the parameters of the activation function of an output node.
+        let parameter_count: usize = self.params.get_activation_parameter_count();
+        if to >= self.output_size {
+            return Err(NetworkError::NodeNotFound(to));
+        }
+        if activation_params.len() != parameter_count {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: parameter_count,
+                actual: activation_params.len(),
+            });
+        }
+        self.params.activation_params[to * parameter_count..(to + 1) * parameter_count]
+            .copy_from_slice(&activation_params);
+        Ok(())
+    }
+    pub fn get_input_size(self: &Self) -> usize {
+        self.input_size
+    }
+    pub fn get_output_size(self: &Self) -> usize {
+        self.output_size
+    }
+    pub fn set_seed(self: &mut Self, seed: u64) {
+        // Reset the random number generator used by "set_size", the same seed gives the same weights.
+        self.params.set_seed(seed);
+    }
+    pub fn set_optimizer(self: &mut Self, optimizer_enum: OptimizerEnum) {
+        // Select the optimizer used by "fitting_batch".
+        self.params.optimizer_enum = optimizer_enum;
+    }
+    pub fn set_loss_function(self: &mut Self, loss_fn_enum: LossFunctionEnum) {
+        // Select the loss of the output used by "fitting_batch".
+        self.params.loss_fn_enum = loss_fn_enum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DenseLayer;
+    use crate::multi_seg_network::flexible_network::{ActivationFunctionEnum, FlexibleNetwork};
+    use crate::multi_seg_network::function_segment::{
+        FunctionSegment, FunctionSegmentFunctionEnum,
+    };
+    use crate::multi_seg_network::gradient_check::gradient_check;
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::loss_function::LossFunctionEnum;
+    use crate::multi_seg_network::optimizer::OptimizerEnum;
+    use crate::multi_seg_network::{get_lost_partial, MultiSegNetwork, Segment};
+
+    const INPUT_SIZE: usize = 3;
+    const OUTPUT_SIZE: usize = 4;
+
+    fn same_layer_pair(
+        activation_fn_enum: ActivationFunctionEnum,
+    ) -> (DenseLayer, FlexibleNetwork) {
+        // Build a DenseLayer and a FlexibleNetwork layer with the same weights.
+        let mut dense_layer: DenseLayer = DenseLayer::new();
+        dense_layer.set_seed(7);
+        dense_layer.set_activation_function(activation_fn_enum);
+        dense_layer.set_size(INPUT_SIZE, OUTPUT_SIZE, InitializerEnum::HeUniform);
+
+        let mut flexible_net: FlexibleNetwork = FlexibleNetwork::new();
+        flexible_net.set_seed(7);
+        let input_layer: usize =
+            flexible_net.new_layer(INPUT_SIZE, 0.0, ActivationFunctionEnum::DoNothing);
+        let output_layer: usize = flexible_net.new_layer(OUTPUT_SIZE, 0.0, activation_fn_enum);
+        flexible_net
+            .connect_layer(input_layer, output_layer, InitializerEnum::HeUniform)
+            .unwrap();
+        flexible_net.set_input_layer(input_layer).unwrap();
+        flexible_net.set_output_layer(output_layer).unwrap();
+
+        let optimizer_enum: OptimizerEnum = OptimizerEnum::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+        };
+        dense_layer.set_optimizer(optimizer_enum);
+        flexible_net.set_optimizer(optimizer_enum);
+        (dense_layer, flexible_net)
+    }
+
+    fn assert_same_output(dense_layer: &mut DenseLayer, flexible_net: &mut FlexibleNetwork) {
+        for input in [[0.5, -1.0, 2.0], [-0.3, 0.8, 0.1]] {
+            dense_layer.set_input(Vec::from(input)).unwrap();
+            dense_layer.next().unwrap();
+            flexible_net.set_input(Vec::from(input)).unwrap();
+            flexible_net.next().unwrap();
+            for (a, b) in dense_layer
+                .get_output()
+                .iter()
+                .zip(flexible_net.get_output().iter())
+            {
+                assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dense_layer_trains_like_a_flexible_network_layer() {
+        let data_pair_list: Vec<[Vec<f64>; 2]> = Vec::from([
+            [Vec::from([0.5, -1.0, 2.0]), Vec::from([0.1, 0.2, 0.3, 0.4])],
+            [
+                Vec::from([1.5, 0.2, -0.7]),
+                Vec::from([-0.4, 0.0, 0.6, 0.2]),
+            ],
+            [
+                Vec::from([-0.9, 0.4, 0.3]),
+                Vec::from([0.3, -0.1, 0.2, 0.0]),
+            ],
+        ]);
+        for activation_fn_enum in [
+            ActivationFunctionEnum::Tanh,
+            ActivationFunctionEnum::PReLU,
+            ActivationFunctionEnum::ScaledTanh,
+        ] {
+            let (mut dense_layer, mut flexible_net) = same_layer_pair(activation_fn_enum);
+            assert_same_output(&mut dense_layer, &mut flexible_net);
+            for _ in 0..5 {
+                dense_layer.fitting_batch(&data_pair_list, 0.05).unwrap();
+                flexible_net.fitting_batch(&data_pair_list, 0.05).unwrap();
+            }
+            assert_same_output(&mut dense_layer, &mut flexible_net);
+
+            dense_layer
+                .fitting_batch(&data_pair_list[..1], 0.05)
+                .unwrap();
+            flexible_net
+                .fitting_batch(&data_pair_list[..1], 0.05)
+                .unwrap();
+            assert_same_output(&mut dense_layer, &mut flexible_net);
+        }
+    }
+
+    #[test]
+    fn test_forward_batch_matches_next() {
+        let (mut dense_layer, _) = same_layer_pair(ActivationFunctionEnum::Sigmoid);
+        let input_batch: Vec<Vec<f64>> = Vec::from([
+            Vec::from([0.5, -1.0, 2.0]),
+            Vec::from([-0.3, 0.8, 0.1]),
+            Vec::from([0.0, 0.0, 0.0]),
+        ]);
+
+        let output_batch: Vec<Vec<f64>> = dense_layer.forward_batch(&input_batch).unwrap();
+
+        assert_eq!(output_batch.len(), input_batch.len());
+        assert_eq!(dense_layer.get_output(), output_batch[2]);
+        for (input, output) in input_batch.iter().zip(output_batch.iter()) {
+            dense_layer.set_input(input.clone()).unwrap();
+            dense_layer.next().unwrap();
+            assert_eq!(&dense_layer.get_output(), output);
+        }
+        assert!(dense_layer
+            .forward_batch(&Vec::from([Vec::from([1.0, 2.0])]))
+            .is_err());
+        assert!(dense_layer
+            .accumulate_gradients_batch(&vec![vec![1.0; OUTPUT_SIZE]; 2])
+            .is_err());
+
+        let report = gradient_check(
+            &mut dense_layer,
+            &input_batch[0],
+            &Vec::from([0.2, 0.4, 0.6, 0.8]),
+            1e-6,
+        )
+        .unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+    }
+
+    #[test]
+    fn test_fitting_after_forward_batch() {
+        let (mut dense_layer, _) = same_layer_pair(ActivationFunctionEnum::Sigmoid);
+        let (mut single_layer, _) = same_layer_pair(ActivationFunctionEnum::Sigmoid);
+        let input_batch: Vec<Vec<f64>> =
+            Vec::from([Vec::from([0.5, -1.0, 2.0]), Vec::from([-0.3, 0.8, 0.1])]);
+        let anticipated: Vec<f64> = Vec::from([0.2, 0.4, 0.6, 0.8]);
+
+        dense_layer.forward_batch(&input_batch).unwrap();
+        let output_partial: Vec<f64> = get_lost_partial(
+            LossFunctionEnum::default(),
+            &dense_layer.get_output(),
+            &anticipated,
+        )
+        .unwrap();
+        dense_layer.backward(output_partial, 0.1).unwrap();
+        single_layer.set_input(input_batch[1].clone()).unwrap();
+        single_layer.next().unwrap();
+        let output_partial: Vec<f64> = get_lost_partial(
+            LossFunctionEnum::default(),
+            &single_layer.get_output(),
+            &anticipated,
+        )
+        .unwrap();
+        single_layer.backward(output_partial, 0.1).unwrap();
+        // The single-sample fitting goes through the last sample of the batch.
+
+        assert_eq!(dense_layer.parameters(), single_layer.parameters());
+    }
+
+    #[test]
+    fn test_dense_layers_stack_with_function_segments_and_round_trip() {
+        const INPUT_VALUE: [f64; 3] = [0.4, 0.9, -0.2];
+        const ANTICIPATED_VALUE: [f64; 2] = [0.8, 0.2];
+        let mut hidden_layer: DenseLayer = DenseLayer::new();
+        hidden_layer.set_activation_function(ActivationFunctionEnum::PReLU);
+        hidden_layer.set_size(3, 5, InitializerEnum::HeUniform);
+        let mut output_layer: DenseLayer = DenseLayer::new();
+        output_layer.set_seed(1);
+        output_layer.set_size(5, 2, InitializerEnum::XavierUniform);
+        let mut output_function: FunctionSegment = FunctionSegment::new();
+        output_function
+            .set_function(FunctionSegmentFunctionEnum::SoftMax)
+            .unwrap();
+
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(hidden_layer).unwrap();
+        multi_seg.push_seg(output_layer).unwrap();
+        multi_seg.push_seg(output_function).unwrap();
+        multi_seg.set_loss_function(LossFunctionEnum::BinaryCrossEntropy);
+        for _ in 0..500 {
+            multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+            multi_seg.next().unwrap();
+            multi_seg
+                .fitting(Vec::from(ANTICIPATED_VALUE), 0.1)
+                .unwrap();
+        }
+        multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        multi_seg.next().unwrap();
+        let output: Vec<f64> = multi_seg.get_output();
+        assert!(
+            (output[0] - ANTICIPATED_VALUE[0]).abs() < 0.01,
+            "{:?}",
+            output
+        );
+
+        let data: String = multi_seg.export_data();
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        imported_seg.import_data(data.clone()).unwrap();
+        imported_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        imported_seg.next().unwrap();
+        assert_eq!(imported_seg.get_output(), output);
+        assert_eq!(imported_seg.export_data(), data);
+
+        let mut broken_layer: DenseLayer = DenseLayer::new();
+        assert!(broken_layer
+            .import_data(
+                r#"{"i_len":2,"o_len":2,"w":[1.0,2.0,3.0],"b":[0.0,0.0],"a_fn":"DoNothing"}"#
+                    .to_string()
+            )
+            .is_err());
+    }
+}
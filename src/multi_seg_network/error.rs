@@ -0,0 +1,132 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/error.rs
+ */
+
+use std::fmt;
+// Display trait for the error messages
+use colored::Colorize;
+// Colored error messages.
+
+use crate::multi_seg_network::registry::get_registered_type_list;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    InputSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    // The length of the given values is not the same as the segment needs.
+    LayerNotFound(usize),
+    NodeNotFound(usize),
+    // The id is not referring to any node in the network.
+    MalformedData(String),
+    // The model data can not be parsed or is not self-consistent.
+    OutOfDomain {
+        x: f64,
+        min: f64,
+        max: f64,
+    },
+    InvalidShape(String),
+    // The sizes given to a segment can not work together, such as a kernel larger than the padded input.
+    ShapeMismatch {
+        segment: usize,
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+    // The output of the segments before can not be taken by the segment at the index.
+    SegmentTypeNotFound(String),
+    // No segment constructor is registered under the type name.
+    HeadNotFound(String),
+    // The network has no output head with the name.
+    FileError(String),
+    // The model file can not be read or written.
+}
+pub type Result<T> = std::result::Result<T, NetworkError>;
+impl fmt::Display for NetworkError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::InputSizeMismatch { expected, actual } => write!(
+                f,
+                r#"[{}]: Input size mismatch! Expected {} values, but get "{}"."#,
+                "InputSizeMismatch".red(),
+                expected.to_string().yellow(),
+                actual.to_string().yellow()
+            ),
+            NetworkError::LayerNotFound(layer_id) => write!(
+                f,
+                r#"[{}]: Layer not found! There is no layer start at "{}"."#,
+                "LayerNotFound".red(),
+                layer_id.to_string().yellow()
+            ),
+            NetworkError::NodeNotFound(id) => write!(
+                f,
+                r#"[{}]: Node not found! There is no node with id "{}"."#,
+                "NodeNotFound".red(),
+                id.to_string().yellow()
+            ),
+            NetworkError::MalformedData(message) => write!(
+                f,
+                r#"[{}]: Malformed model data! Message: "{}"."#,
+                "MalformedData".red(),
+                message.yellow()
+            ),
+            NetworkError::OutOfDomain { x, min, max } => write!(
+                f,
+                r#"[{}]: Function input out of range! x must between {} and {}, but get "{}"."#,
+                "OutOfDomain".red(),
+                min.to_string().yellow(),
+                max.to_string().yellow(),
+                x.to_string().yellow()
+            ),
+            NetworkError::InvalidShape(message) => write!(
+                f,
+                r#"[{}]: Invalid shape! Message: "{}"."#,
+                "InvalidShape".red(),
+                message.yellow()
+            ),
+            NetworkError::ShapeMismatch {
+                segment,
+                expected,
+                actual,
+            } => write!(
+                f,
+                r#"[{}]: Shape mismatch! Segment {} takes values of shape {}, but the segments before give "{}"."#,
+                "ShapeMismatch".red(),
+                segment.to_string().yellow(),
+                format!("{:?}", expected).yellow(),
+                format!("{:?}", actual).yellow()
+            ),
+            NetworkError::SegmentTypeNotFound(type_name) => write!(
+                f,
+                r#"[{}]: Segment type not found! There is no segment registered as "{}", the registered types are {}."#,
+                "SegmentTypeNotFound".red(),
+                type_name.yellow(),
+                format!("{:?}", get_registered_type_list()).yellow()
+            ),
+            NetworkError::HeadNotFound(name) => write!(
+                f,
+                r#"[{}]: Output head not found! There is no output head named "{}"."#,
+                "HeadNotFound".red(),
+                name.yellow()
+            ),
+            NetworkError::FileError(message) => write!(
+                f,
+                r#"[{}]: File error! Message: "{}"."#,
+                "FileError".red(),
+                message.yellow()
+            ),
+        }
+    }
+}
+impl std::error::Error for NetworkError {}
+impl From<serde_json::Error> for NetworkError {
+    fn from(error: serde_json::Error) -> Self {
+        NetworkError::MalformedData(error.to_string())
+    }
+}
+impl From<std::io::Error> for NetworkError {
+    fn from(error: std::io::Error) -> Self {
+        NetworkError::FileError(error.to_string())
+    }
+}
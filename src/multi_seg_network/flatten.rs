@@ -0,0 +1,125 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/flatten.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::Segment;
+
+pub struct Flatten {
+    // Pass the values in the order of [channel][row][column] on as a list, between the 2D segments and the other ones.
+    input_shape: [usize; 3],
+    value: Vec<f64>,
+}
+#[derive(Serialize, Deserialize)]
+struct FlattenData {
+    i_s: [usize; 3],
+}
+#[allow(dead_code)]
+impl Segment for Flatten {
+    fn new() -> Self {
+        Self {
+            input_shape: [0; 3],
+            value: Vec::new(),
+        }
+    }
+    fn get_type(self: &Self) -> String {
+        String::from("Flatten")
+    }
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        let input_size: usize = self.input_shape.iter().product();
+        if input.len() != input_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: input_size,
+                actual: input.len(),
+            });
+        }
+        self.value = input;
+        Ok(())
+    }
+    fn get_output(self: &mut Self) -> Vec<f64> {
+        self.value.clone()
+    }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from(self.input_shape))
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from([self.input_shape.iter().product()]))
+    }
+    fn export_data(self: &mut Self) -> String {
+        let data = FlattenData {
+            i_s: self.input_shape,
+        };
+        serde_json::to_string(&data).unwrap()
+    }
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: FlattenData = serde_json::from_str(data.as_str())?;
+        self.set_input_shape(data.i_s);
+        Ok(())
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        Ok(())
+    }
+    // The values are stored flat already.
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        let input_size: usize = self.input_shape.iter().product();
+        for input in input_batch.iter() {
+            if input.len() != input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: input_size,
+                    actual: input.len(),
+                });
+            }
+        }
+        if let Some(input) = input_batch.last() {
+            self.value = input.clone();
+        }
+        Ok(input_batch.to_vec())
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.value.len(),
+                actual: output_partial.len(),
+            });
+        }
+        Ok(output_partial)
+    }
+}
+#[allow(dead_code)]
+impl Flatten {
+    pub fn set_input_shape(self: &mut Self, input_shape: [usize; 3]) {
+        // Set the [channel count, height, width] of the input.
+        self.input_shape = input_shape;
+        self.value.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Flatten;
+    use crate::multi_seg_network::Segment;
+
+    #[test]
+    fn test_flatten_checks_the_input_shape() {
+        let mut flatten: Flatten = Flatten::new();
+        flatten.set_input_shape([2, 1, 3]);
+
+        assert!(flatten.set_input(Vec::from([1.0, 2.0])).is_err());
+        flatten
+            .set_input(Vec::from([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]))
+            .unwrap();
+        flatten.next().unwrap();
+        assert_eq!(
+            flatten.get_output(),
+            Vec::from([1.0, 2.0, 3.0, 4.0, 5.0, 6.0])
+        );
+        assert_eq!(
+            flatten.accumulate_gradients(Vec::from([1.0; 6])).unwrap(),
+            Vec::from([1.0; 6])
+        );
+    }
+}
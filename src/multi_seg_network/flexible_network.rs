@@ -3,16 +3,30 @@
  * rust-rl/src/multi_seg_network/flexible_network.rs
  */
 
-use std::collections::HashMap;
-// HashMap Type
+use std::collections::{BTreeMap, VecDeque};
+// BTreeMap Type, keep the layer map in a stable order when exporting, VecDeque for the recurrent history
+use std::fmt;
+// Display trait for the validation report
 use std::f64::INFINITY;
 // the Infinity Value
 use serde::{Deserialize, Serialize};
 // Make the customize struct be able to json stringify
+use serde_json::{Map, Value};
+// Old data is upgraded as a json tree by the migrations.
 use colored::Colorize;
 // Colored print and panic.
 
-use crate::multi_seg_network::{Segment, SegmentTypes};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+// Seedable random number generator for the weight initializers.
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::gradient_check::{get_lost, relative_error, GradientCheckReport};
+use crate::multi_seg_network::initializer::{Initializer, InitializerEnum};
+use crate::multi_seg_network::loss_function::{LossFunction, LossFunctionEnum};
+use crate::multi_seg_network::optimizer::{Optimizer, OptimizerEnum, OptimizerState};
+use crate::multi_seg_network::schema::{check_required, insert_default, migrate, Migration};
+use crate::multi_seg_network::{check_parameter_count, get_lost_partial, Segment};
 
 fn check_ian(x: f64, message: String) {
     if x.is_infinite() {
@@ -29,29 +43,17 @@ fn check_ian(x: f64, message: String) {
         );
     }
 }
-fn check_domain(x: f64, min: f64, max: f64) -> Result<f64, String> {
+fn check_domain(x: f64, min: f64, max: f64) -> Result<f64> {
     // To check the input value is between the min and max or not.
-    if x > max {
-        Err(format!(
-            r#"[{}]: Function input out of range! x must lower then max value({}), but get "{}"."#,
-            "check_domain".red(),
-            max.to_string().yellow(),
-            x.to_string().yellow()
-        ))
-    } else if x < min {
-        Err(format!(
-            r#"[{}]: Function input out of range! x must higher then min value({}), but get "{}"."#,
-            "check_domain".red(),
-            min.to_string().yellow(),
-            x.to_string().yellow()
-        ))
+    if x > max || x < min {
+        Err(NetworkError::OutOfDomain { x, min, max })
     } else {
         Ok(x)
     }
 }
 
 pub struct ActivationFunction();
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 pub enum ActivationFunctionEnum {
@@ -59,88 +61,345 @@ pub enum ActivationFunctionEnum {
     Sigmoid,
     Tanh,
     ReLU,
+    LeakyReLU,
+    ELU,
+    SELU,
+    GELU,
+    Softplus,
+    Swish,
+    // Also called SiLU.
+    HardSigmoid,
+    Sine,
+    PReLU,
+    // LeakyReLU with a trainable slope.
+    ScaledTanh,
+    // $a \tanh(b x)$ with trainable a and b.
 }
+const LEAKY_RELU_SLOPE: f64 = 0.01;
+const PRELU_SLOPE: f64 = 0.25;
+const SCALED_TANH_A: f64 = 1.7159;
+const SCALED_TANH_B: f64 = 2.0 / 3.0;
+// Default parameters, every node keeps its own copy in "Node::activation_params".
+const ELU_ALPHA: f64 = 1.0;
+const SELU_ALPHA: f64 = 1.6732632423543772;
+const SELU_LAMBDA: f64 = 1.0507009873554805;
+const GELU_COEFFICIENT: f64 = 0.044715;
+// GELU uses the tanh approximation, $0.5x(1 + \tanh(\sqrt{2/\pi}(x + 0.044715x^3)))$.
+const GELU_MINIMUM_X: f64 = -0.7517916;
+const SWISH_MINIMUM_X: f64 = -1.2784645;
+// GELU and Swish are only inverted on the increasing side of their minimum.
 #[allow(dead_code)]
 impl ActivationFunction {
-    fn do_nothing(x: f64) -> f64 {
+    // Every derivative takes the pre-activation value x, which is kept in "Node::sum".
+    fn do_nothing(x: f64, _p: &[f64]) -> f64 {
         x
     }
-    fn do_nothing_derivative(x: f64) -> f64 {
+    fn do_nothing_derivative(_x: f64, _p: &[f64]) -> f64 {
         1.0
     }
-    fn sigmoid(x: f64) -> f64 {
-        1.0 / (1.0 + std::f64::consts::E.powf(-x))
+    fn do_nothing_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        Ok(x)
     }
-    fn sigmoid_inverse(x: f64) -> f64 {
-        match check_domain(x, 0.0, 1.0) {
-            Ok(v) => (v / (1.0 - v)).ln(),
-            Err(e) => panic!("{}", e),
+    fn sigmoid(x: f64, _p: &[f64]) -> f64 {
+        if x >= 0.0 {
+            1.0 / (1.0 + (-x).exp())
+        } else {
+            let e_powf_x: f64 = x.exp();
+            e_powf_x / (1.0 + e_powf_x)
         }
+        // Keep the exponent negative, so it does not overflow.
+    }
+    fn sigmoid_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, 0.0, 1.0)?;
+        Ok((v / (1.0 - v)).ln())
     }
-    fn sigmoid_derivative(x: f64) -> f64 {
-        x / (1.0 - x)
+    fn sigmoid_derivative(x: f64, p: &[f64]) -> f64 {
+        let s: f64 = ActivationFunction::sigmoid(x, p);
+        s * (1.0 - s)
     }
-    fn tanh(x: f64) -> f64 {
-        let e_powf_2x: f64 = std::f64::consts::E.powf(2.0 * x);
-        (e_powf_2x - 1.0) / (e_powf_2x + 1.0)
+    fn tanh(x: f64, _p: &[f64]) -> f64 {
+        x.tanh()
     }
-    fn tanh_inverse(x: f64) -> f64 {
-        match check_domain(x, -1.0, 1.0) {
-            Ok(v) => 0.5 * ((1.0 + v) / (1.0 - v)).ln(),
-            Err(e) => panic!("{}", e),
+    fn tanh_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, -1.0, 1.0)?;
+        Ok(0.5 * ((1.0 + v) / (1.0 - v)).ln())
+    }
+    fn tanh_derivative(x: f64, _p: &[f64]) -> f64 {
+        1.0 - x.tanh().powi(2)
+    }
+    fn relu(x: f64, _p: &[f64]) -> f64 {
+        if x <= 0.0 {
+            0.0
+        } else {
+            x
         }
     }
-    fn tanh_derivative(x: f64) -> f64 {
-        1.0 - ActivationFunction::tanh(x).powi(2)
+    fn relu_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, 0.0, INFINITY)?;
+        Ok(if v <= 0.0 { 0.0 } else { v })
     }
-    fn relu(x: f64) -> f64 {
+    fn relu_derivative(x: f64, _p: &[f64]) -> f64 {
         if x <= 0.0 {
             0.0
+        } else {
+            1.0
+        }
+    }
+    fn leaky_relu(x: f64, p: &[f64]) -> f64 {
+        // p = [slope]
+        if x <= 0.0 {
+            p[0] * x
         } else {
             x
         }
     }
-    fn relu_inverse(x: f64) -> f64 {
-        match check_domain(x, 0.0, INFINITY) {
-            Ok(v) => {
-                if v <= 0.0 {
-                    0.0
-                } else {
-                    v
-                }
-            }
-            Err(e) => panic!("{}", e),
+    fn leaky_relu_inverse(x: f64, p: &[f64]) -> Result<f64> {
+        // p = [slope], a zero slope maps every negative x to 0, so only 0 is inverted on that side.
+        if x <= 0.0 && p[0] == 0.0 {
+            check_domain(x, 0.0, INFINITY)
+        } else {
+            Ok(if x <= 0.0 { x / p[0] } else { x })
         }
     }
-    fn relu_derivative(x: f64) -> f64 {
+    fn leaky_relu_derivative(x: f64, p: &[f64]) -> f64 {
         if x <= 0.0 {
-            0.0
+            p[0]
+        } else {
+            1.0
+        }
+    }
+    fn elu(x: f64, _p: &[f64]) -> f64 {
+        if x <= 0.0 {
+            ELU_ALPHA * x.exp_m1()
+        } else {
+            x
+        }
+    }
+    fn elu_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, -ELU_ALPHA, INFINITY)?;
+        Ok(if v <= 0.0 { (v / ELU_ALPHA).ln_1p() } else { v })
+    }
+    fn elu_derivative(x: f64, _p: &[f64]) -> f64 {
+        if x <= 0.0 {
+            ELU_ALPHA * x.exp()
         } else {
             1.0
         }
     }
-    pub fn get_function(activation_fn_enum: ActivationFunctionEnum) -> fn(f64) -> f64 {
+    fn selu(x: f64, _p: &[f64]) -> f64 {
+        if x <= 0.0 {
+            SELU_LAMBDA * SELU_ALPHA * x.exp_m1()
+        } else {
+            SELU_LAMBDA * x
+        }
+    }
+    fn selu_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, -SELU_LAMBDA * SELU_ALPHA, INFINITY)?;
+        Ok(if v <= 0.0 {
+            (v / (SELU_LAMBDA * SELU_ALPHA)).ln_1p()
+        } else {
+            v / SELU_LAMBDA
+        })
+    }
+    fn selu_derivative(x: f64, _p: &[f64]) -> f64 {
+        if x <= 0.0 {
+            SELU_LAMBDA * SELU_ALPHA * x.exp()
+        } else {
+            SELU_LAMBDA
+        }
+    }
+    fn gelu(x: f64, _p: &[f64]) -> f64 {
+        let u: f64 = (2.0 / std::f64::consts::PI).sqrt() * (x + GELU_COEFFICIENT * x.powi(3));
+        0.5 * x * (1.0 + u.tanh())
+    }
+    fn gelu_inverse(x: f64, p: &[f64]) -> Result<f64> {
+        ActivationFunction::increasing_inverse(ActivationFunction::gelu, x, p, GELU_MINIMUM_X)
+    }
+    fn gelu_derivative(x: f64, _p: &[f64]) -> f64 {
+        let k: f64 = (2.0 / std::f64::consts::PI).sqrt();
+        let tanh_u: f64 = (k * (x + GELU_COEFFICIENT * x.powi(3))).tanh();
+        0.5 * (1.0 + tanh_u)
+            + 0.5 * x * (1.0 - tanh_u.powi(2)) * k * (1.0 + 3.0 * GELU_COEFFICIENT * x.powi(2))
+    }
+    fn softplus(x: f64, _p: &[f64]) -> f64 {
+        x.max(0.0) + (-x.abs()).exp().ln_1p()
+    }
+    fn softplus_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, 0.0, INFINITY)?;
+        Ok(v + (-(-v).exp_m1()).ln())
+        // $\ln(e^v - 1) = v + \ln(1 - e^{-v})$
+    }
+    fn softplus_derivative(x: f64, p: &[f64]) -> f64 {
+        ActivationFunction::sigmoid(x, p)
+    }
+    fn swish(x: f64, p: &[f64]) -> f64 {
+        x * ActivationFunction::sigmoid(x, p)
+    }
+    fn swish_inverse(x: f64, p: &[f64]) -> Result<f64> {
+        ActivationFunction::increasing_inverse(ActivationFunction::swish, x, p, SWISH_MINIMUM_X)
+    }
+    fn swish_derivative(x: f64, p: &[f64]) -> f64 {
+        let s: f64 = ActivationFunction::sigmoid(x, p);
+        s + x * s * (1.0 - s)
+    }
+    fn hard_sigmoid(x: f64, _p: &[f64]) -> f64 {
+        (x / 6.0 + 0.5).clamp(0.0, 1.0)
+    }
+    fn hard_sigmoid_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, 0.0, 1.0)?;
+        Ok((v - 0.5) * 6.0)
+    }
+    fn hard_sigmoid_derivative(x: f64, _p: &[f64]) -> f64 {
+        if x <= -3.0 || x >= 3.0 {
+            0.0
+        } else {
+            1.0 / 6.0
+        }
+    }
+    fn sine(x: f64, _p: &[f64]) -> f64 {
+        x.sin()
+    }
+    fn sine_inverse(x: f64, _p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x, -1.0, 1.0)?;
+        Ok(v.asin())
+    }
+    fn sine_derivative(x: f64, _p: &[f64]) -> f64 {
+        x.cos()
+    }
+    fn prelu_parameter_derivative(x: f64, _p: &[f64]) -> Vec<f64> {
+        Vec::from([if x <= 0.0 { x } else { 0.0 }])
+    }
+    fn scaled_tanh(x: f64, p: &[f64]) -> f64 {
+        // p = [a, b]
+        p[0] * (p[1] * x).tanh()
+    }
+    fn scaled_tanh_inverse(x: f64, p: &[f64]) -> Result<f64> {
+        let v: f64 = check_domain(x / p[0], -1.0, 1.0)?;
+        Ok(0.5 * ((1.0 + v) / (1.0 - v)).ln() / p[1])
+    }
+    fn scaled_tanh_derivative(x: f64, p: &[f64]) -> f64 {
+        p[0] * p[1] * (1.0 - (p[1] * x).tanh().powi(2))
+    }
+    fn scaled_tanh_parameter_derivative(x: f64, p: &[f64]) -> Vec<f64> {
+        let tanh_bx: f64 = (p[1] * x).tanh();
+        Vec::from([tanh_bx, p[0] * x * (1.0 - tanh_bx.powi(2))])
+    }
+    fn no_parameter_derivative(_x: f64, _p: &[f64]) -> Vec<f64> {
+        Vec::new()
+    }
+    fn increasing_inverse(
+        function: fn(f64, &[f64]) -> f64,
+        x: f64,
+        p: &[f64],
+        minimum_x: f64,
+    ) -> Result<f64> {
+        // Find the inverse by bisection, the function should be increasing after minimum_x.
+        if !x.is_finite() {
+            return Err(NetworkError::OutOfDomain {
+                x,
+                min: function(minimum_x, p),
+                max: INFINITY,
+            });
+        }
+        // NaN passes the comparisons of "check_domain", and the bisection can not reach the infinity.
+        let v: f64 = check_domain(x, function(minimum_x, p), INFINITY)?;
+        let mut low: f64 = minimum_x;
+        let mut high: f64 = 1.0;
+        while function(high, p) < v {
+            high *= 2.0;
+        }
+        for _ in 0..100 {
+            let middle: f64 = 0.5 * (low + high);
+            if function(middle, p) < v {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(0.5 * (low + high))
+    }
+    pub fn get_function(activation_fn_enum: ActivationFunctionEnum) -> fn(f64, &[f64]) -> f64 {
         match activation_fn_enum {
             ActivationFunctionEnum::DoNothing => ActivationFunction::do_nothing,
             ActivationFunctionEnum::Sigmoid => ActivationFunction::sigmoid,
             ActivationFunctionEnum::Tanh => ActivationFunction::tanh,
             ActivationFunctionEnum::ReLU => ActivationFunction::relu,
+            ActivationFunctionEnum::LeakyReLU => ActivationFunction::leaky_relu,
+            ActivationFunctionEnum::ELU => ActivationFunction::elu,
+            ActivationFunctionEnum::SELU => ActivationFunction::selu,
+            ActivationFunctionEnum::GELU => ActivationFunction::gelu,
+            ActivationFunctionEnum::Softplus => ActivationFunction::softplus,
+            ActivationFunctionEnum::Swish => ActivationFunction::swish,
+            ActivationFunctionEnum::HardSigmoid => ActivationFunction::hard_sigmoid,
+            ActivationFunctionEnum::Sine => ActivationFunction::sine,
+            ActivationFunctionEnum::PReLU => ActivationFunction::leaky_relu,
+            ActivationFunctionEnum::ScaledTanh => ActivationFunction::scaled_tanh,
         }
     }
-    pub fn get_inverse(activation_fn_enum: ActivationFunctionEnum) -> fn(f64) -> f64 {
+    pub fn get_inverse(
+        activation_fn_enum: ActivationFunctionEnum,
+    ) -> fn(f64, &[f64]) -> Result<f64> {
         match activation_fn_enum {
-            ActivationFunctionEnum::DoNothing => ActivationFunction::do_nothing,
+            ActivationFunctionEnum::DoNothing => ActivationFunction::do_nothing_inverse,
             ActivationFunctionEnum::Sigmoid => ActivationFunction::sigmoid_inverse,
             ActivationFunctionEnum::Tanh => ActivationFunction::tanh_inverse,
             ActivationFunctionEnum::ReLU => ActivationFunction::relu_inverse,
+            ActivationFunctionEnum::LeakyReLU => ActivationFunction::leaky_relu_inverse,
+            ActivationFunctionEnum::ELU => ActivationFunction::elu_inverse,
+            ActivationFunctionEnum::SELU => ActivationFunction::selu_inverse,
+            ActivationFunctionEnum::GELU => ActivationFunction::gelu_inverse,
+            ActivationFunctionEnum::Softplus => ActivationFunction::softplus_inverse,
+            ActivationFunctionEnum::Swish => ActivationFunction::swish_inverse,
+            ActivationFunctionEnum::HardSigmoid => ActivationFunction::hard_sigmoid_inverse,
+            ActivationFunctionEnum::Sine => ActivationFunction::sine_inverse,
+            ActivationFunctionEnum::PReLU => ActivationFunction::leaky_relu_inverse,
+            ActivationFunctionEnum::ScaledTanh => ActivationFunction::scaled_tanh_inverse,
         }
     }
-    pub fn get_derivative(activation_fn_enum: ActivationFunctionEnum) -> fn(f64) -> f64 {
+    pub fn get_derivative(activation_fn_enum: ActivationFunctionEnum) -> fn(f64, &[f64]) -> f64 {
+        // Return the function of $\frac{\partial value}{\partial x}$, x is the value before the activation function.
         match activation_fn_enum {
             ActivationFunctionEnum::DoNothing => ActivationFunction::do_nothing_derivative,
             ActivationFunctionEnum::Sigmoid => ActivationFunction::sigmoid_derivative,
             ActivationFunctionEnum::Tanh => ActivationFunction::tanh_derivative,
             ActivationFunctionEnum::ReLU => ActivationFunction::relu_derivative,
+            ActivationFunctionEnum::LeakyReLU => ActivationFunction::leaky_relu_derivative,
+            ActivationFunctionEnum::ELU => ActivationFunction::elu_derivative,
+            ActivationFunctionEnum::SELU => ActivationFunction::selu_derivative,
+            ActivationFunctionEnum::GELU => ActivationFunction::gelu_derivative,
+            ActivationFunctionEnum::Softplus => ActivationFunction::softplus_derivative,
+            ActivationFunctionEnum::Swish => ActivationFunction::swish_derivative,
+            ActivationFunctionEnum::HardSigmoid => ActivationFunction::hard_sigmoid_derivative,
+            ActivationFunctionEnum::Sine => ActivationFunction::sine_derivative,
+            ActivationFunctionEnum::PReLU => ActivationFunction::leaky_relu_derivative,
+            ActivationFunctionEnum::ScaledTanh => ActivationFunction::scaled_tanh_derivative,
+        }
+    }
+    pub fn get_parameter_derivative(
+        activation_fn_enum: ActivationFunctionEnum,
+    ) -> fn(f64, &[f64]) -> Vec<f64> {
+        // Return the function of $\frac{\partial value}{\partial p}$, it is empty if the parameters are not trainable.
+        match activation_fn_enum {
+            ActivationFunctionEnum::PReLU => ActivationFunction::prelu_parameter_derivative,
+            ActivationFunctionEnum::ScaledTanh => {
+                ActivationFunction::scaled_tanh_parameter_derivative
+            }
+            _ => ActivationFunction::no_parameter_derivative,
+        }
+    }
+    pub fn is_trainable(activation_fn_enum: ActivationFunctionEnum) -> bool {
+        // Whether "fitting" updates the parameters of the activation function.
+        matches!(
+            activation_fn_enum,
+            ActivationFunctionEnum::PReLU | ActivationFunctionEnum::ScaledTanh
+        )
+    }
+    pub fn get_default_parameters(activation_fn_enum: ActivationFunctionEnum) -> Vec<f64> {
+        match activation_fn_enum {
+            ActivationFunctionEnum::LeakyReLU => Vec::from([LEAKY_RELU_SLOPE]),
+            ActivationFunctionEnum::PReLU => Vec::from([PRELU_SLOPE]),
+            ActivationFunctionEnum::ScaledTanh => Vec::from([SCALED_TANH_A, SCALED_TANH_B]),
+            _ => Vec::new(),
         }
     }
 }
@@ -152,20 +411,32 @@ pub struct Node {
     input_id: Vec<usize>,
     input_w: Vec<f64>,
     // Weights of the pipe between this node and it's source node.
+    input_recurrent: Vec<bool>,
+    // The recurrent pipe passes the source node's value of the last time step, a pipe from the node itself is always recurrent.
     input_value: Vec<f64>,
     input_partial: Vec<f64>,
     // $\frac{\partial self.input_value}{\partial lost}$
+    input_w_gradient: Vec<f64>,
+    b_gradient: f64,
+    // Sum of $\frac{\partial lost}{\partial w}$ and $\frac{\partial lost}{\partial b}$ since the last "zero_gradient".
     output_count: usize,
     output_id: Vec<usize>,
     output_index: Vec<usize>,
+    sum: f64,
+    // The value before the activation function, the derivatives take it.
     value: f64,
+    last_value: f64,
+    // The value at the end of the last "next", read by the recurrent pipes.
     anticipated_value: f64,
     partial: f64,
     // $\frac{\partial self.value}{\partial lost}$
-    calc_planned: bool,
-    // Operate by the "next" function of network.
     b: f64,
     activation_fn_enum: ActivationFunctionEnum, // Bias Terms
+    activation_params: Vec<f64>,
+    activation_params_gradient: Vec<f64>,
+    // Parameters of the activation function, the gradient is empty if they are not trainable.
+    optimizer_state: OptimizerState,
+    // Moments and step count of the optimizer for "input_w", "b" and "activation_params".
 }
 #[derive(Clone, Serialize, Deserialize)]
 struct NodeData {
@@ -174,15 +445,15 @@ struct NodeData {
     i_w: Vec<f64>,
     b: f64,
     a_fn: ActivationFunctionEnum,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    a_p: Vec<f64>,
+    // The default parameters of "a_fn" are used if it is missing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    i_r: Vec<usize>,
+    // Indexes of the recurrent input sources, the pipes from the node itself are not listed.
+    #[serde(default, skip_serializing_if = "OptimizerState::is_empty")]
+    o_s: OptimizerState,
 }
-pub struct NodeFetchQueueItem {
-    // Queue Item for FlexibleNetwork Node to Fetch Value
-    from_id: usize,
-    to_id: usize,
-    to_index: usize,
-}
-// When a node request value from it's source, it will return a list of "NodeFetchQueueItem".
-// And the queue items can let the net work to update nodes' input value list.
 #[allow(dead_code)]
 impl Node {
     // FlexibleNetwork Node's Implementation
@@ -193,17 +464,24 @@ impl Node {
             input_count: 0,
             input_id: Vec::new(),
             input_w: Vec::new(),
+            input_recurrent: Vec::new(),
             input_value: Vec::new(),
             input_partial: Vec::new(),
+            input_w_gradient: Vec::new(),
+            b_gradient: 0.0,
             output_count: 0,
             output_id: Vec::new(),
             output_index: Vec::new(),
+            sum: 0.0,
             value: 0.0,
+            last_value: 0.0,
             anticipated_value: 0.0,
             partial: 0.0,
-            calc_planned: false,
             b: 0.0,
             activation_fn_enum: ActivationFunctionEnum::DoNothing,
+            activation_params: Vec::new(),
+            activation_params_gradient: Vec::new(),
+            optimizer_state: OptimizerState::new(),
         }
     }
     pub fn new_input_source(self: &mut Self, id: usize, w: f64) -> usize {
@@ -211,8 +489,10 @@ impl Node {
         self.input_count += 1;
         self.input_id.push(id);
         self.input_w.push(w);
+        self.input_recurrent.push(id == self.id);
         self.input_value.push(0.0);
         self.input_partial.push(0.0);
+        self.input_w_gradient.push(0.0);
         self.input_id.len() - 1
     }
     pub fn new_output_target(self: &mut Self, id: usize, index: usize) {
@@ -237,13 +517,16 @@ impl Node {
                 value_sum += self.input_value[i] * self.input_w[i];
                 // self.input_value[i] = 0.0;
             }
-            self.value = value_sum + self.b;
-            self.value =
-                ActivationFunction::get_function(self.activation_fn_enum.clone())(self.value);
+            self.sum = value_sum + self.b;
+            self.value = ActivationFunction::get_function(self.activation_fn_enum)(
+                self.sum,
+                &self.activation_params,
+            );
         }
         self.value
     }
-    pub fn fitting(self: &mut Self, learning_rate: f64, count_lost: bool) {
+    pub fn accumulate_gradient(self: &mut Self) {
+        // Add the gradient of the weights and bias from the last "calc_value" into the gradient storage, and update "input_partial".
         if self.input_count > 0 {
             // let anticipated_value: f64 = ActivationFunction::get_inverse(self.activation_fn_enum.clone())(anticipated_value);
             // reverse anticipated_value by reversed activation function.
@@ -256,28 +539,24 @@ impl Node {
              * rust-rl/DEVELOP.md > Develop Notes > 20240105 - A000003
              */
             // /* before 20240101 */cost := (self.value - inverse_activation_fn(anticipated_value)).powi(2);
-            // cost := (self.value - anticipated_value).powi(2);
-            let derivative_c_b: f64 = if count_lost {
-                (2.0 * self.value - 2.0 * self.anticipated_value)
-                    * (ActivationFunction::get_derivative(self.activation_fn_enum)(self.value))
-            } else {
-                (self.partial)
-                    * (ActivationFunction::get_derivative(self.activation_fn_enum)(self.value))
-            };
+            // The partial of the lost is given by "fitting" or the output targets of this node.
+            let derivative_c_b: f64 = (self.partial)
+                * (ActivationFunction::get_derivative(self.activation_fn_enum)(
+                    self.sum,
+                    &self.activation_params,
+                ));
 
-            let mut new_w: Vec<f64> = self.input_w.clone();
             for i in 0..self.input_count {
                 let value_i = self.input_value[i];
                 // /* before 20240101 */let gradient: f64 = 2.0*value_i.powi(2)*w_i + 2.0*(self.value-value_i*w_i + self.b - anticipated_value)*value_i;
                 let gradient: f64 = derivative_c_b * (value_i);
                 // check_ian(gradient, format!("2.0*{value_i}.powi(2)*{w_i} + 2.0*({0}-{1}*{w_i} + {2} - {anticipated_value})*{value_i}", self.value, value_i, self.b).to_string());
-                new_w[i] -= gradient * learning_rate;
+                self.input_w_gradient[i] += gradient;
             }
 
-            let mut new_b: f64 = self.b;
             // /* before 20240101 */let gradient: f64 = 2.0*(anticipated_value - self.value);
             let gradient: f64 = derivative_c_b * (1.0);
-            new_b -= gradient * learning_rate;
+            self.b_gradient += gradient;
 
             for i in 0..self.input_count {
                 let w_i = self.input_w[i];
@@ -286,33 +565,165 @@ impl Node {
                 self.input_partial[i] = gradient;
             }
 
-            self.input_w = new_w;
-            self.b = new_b;
+            if !self.activation_params_gradient.is_empty() {
+                let parameter_derivative: Vec<f64> = ActivationFunction::get_parameter_derivative(
+                    self.activation_fn_enum,
+                )(
+                    self.sum, &self.activation_params
+                );
+                for (gradient, derivative) in self
+                    .activation_params_gradient
+                    .iter_mut()
+                    .zip(parameter_derivative.iter())
+                {
+                    *gradient += self.partial * derivative;
+                }
+            }
         }
     }
-    pub fn fetch_value(self: &mut Self) -> Vec<NodeFetchQueueItem> {
-        // Return the list of "NodeFetchQueueItem", and queue to fetch value.
-        self.input_id
-            .iter()
-            .enumerate()
-            .map(|(index, id)| NodeFetchQueueItem {
-                from_id: *id,
-                to_id: self.id,
-                to_index: index,
-            })
-            .collect::<Vec<NodeFetchQueueItem>>()
-    }
-    pub fn fetch_partial(self: &mut Self) -> Vec<NodeFetchQueueItem> {
-        // Return the list of "NodeFetchQueueItem", and queue to fetch value.
-        let mut queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-        for i in 0..self.output_count {
-            queue_list.push(NodeFetchQueueItem {
-                from_id: self.output_id[i],
-                to_id: self.id,
-                to_index: self.output_index[i],
-            });
+    pub fn apply_gradient(
+        self: &mut Self,
+        learning_rate: f64,
+        gradient_count: usize,
+        optimizer_enum: OptimizerEnum,
+    ) {
+        // Update the weights and bias with the average of the accumulated gradients.
+        if self.input_count > 0 && gradient_count > 0 {
+            let w_gradient: Vec<f64> = self
+                .input_w_gradient
+                .iter()
+                .map(|gradient| gradient / gradient_count as f64)
+                .collect::<Vec<f64>>();
+            let b_gradient: f64 = self.b_gradient / gradient_count as f64;
+            Optimizer::step(
+                optimizer_enum,
+                &mut self.optimizer_state,
+                &mut self.input_w,
+                &w_gradient,
+                &mut self.b,
+                b_gradient,
+                learning_rate,
+            );
+            if !self.activation_params_gradient.is_empty() {
+                let p_gradient: Vec<f64> = self
+                    .activation_params_gradient
+                    .iter()
+                    .map(|gradient| gradient / gradient_count as f64)
+                    .collect::<Vec<f64>>();
+                Optimizer::step_parameters(
+                    optimizer_enum,
+                    &mut self.optimizer_state,
+                    &mut self.activation_params,
+                    &p_gradient,
+                    learning_rate,
+                );
+            }
+        }
+    }
+    pub fn zero_gradient(self: &mut Self) {
+        // Clear the accumulated gradients.
+        for i in 0..self.input_count {
+            self.input_w_gradient[i] = 0.0;
+        }
+        self.b_gradient = 0.0;
+        self.activation_params_gradient
+            .iter_mut()
+            .for_each(|gradient| *gradient = 0.0);
+    }
+    pub fn set_activation_function(self: &mut Self, activation_fn_enum: ActivationFunctionEnum) {
+        // Change the activation function, and reset its parameters to the default.
+        self.activation_fn_enum = activation_fn_enum;
+        self.activation_params = ActivationFunction::get_default_parameters(activation_fn_enum);
+        self.activation_params_gradient = if ActivationFunction::is_trainable(activation_fn_enum) {
+            vec![0.0; self.activation_params.len()]
+        } else {
+            Vec::new()
+        };
+    }
+    pub fn fitting(
+        self: &mut Self,
+        learning_rate: f64,
+        loss_fn_enum: Option<LossFunctionEnum>,
+        optimizer_enum: OptimizerEnum,
+    ) {
+        // Fit the node with the gradient of the last "calc_value" only.
+        // Count the lost between "value" and "anticipated_value" if the loss is given, or use the current "partial".
+        if let Some(loss_fn_enum) = loss_fn_enum {
+            self.partial = LossFunction::get_partial(loss_fn_enum)(
+                &Vec::from([self.value]),
+                &Vec::from([self.anticipated_value]),
+            )[0];
+        }
+        self.zero_gradient();
+        self.accumulate_gradient();
+        self.apply_gradient(learning_rate, 1, optimizer_enum);
+        self.zero_gradient();
+    }
+}
+
+struct NodeStep {
+    // Record of a node in one time step, used by the backpropagation through time.
+    input_value: Vec<f64>,
+    sum: f64,
+    value: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    // Problems found by "FlexibleNetwork::validate", every list is sorted.
+    pub dangling_id: Vec<usize>,
+    // Ids in the input, output or source lists which are not referring to any node.
+    pub fed_input_id: Vec<usize>,
+    // Input nodes with input sources, the value given by "set_input" is overwritten in "next".
+    pub cycle_pipe: Vec<[usize; 2]>,
+    // [from id, to id] of the pipes which close a cycle but are not recurrent.
+    pub shape_mismatch: Vec<String>,
+    // Overlapping layers, repeated input or output ids, and nodes with inconsistent pipe lists.
+    pub unreachable_id: Vec<usize>,
+    // Nodes which can not be reached from any input node, their value only depends on the bias terms.
+    pub dead_id: Vec<usize>,
+    // Nodes which no output node depends on.
+}
+#[allow(dead_code)]
+impl ValidationReport {
+    pub fn is_ok(self: &Self) -> bool {
+        // Return false if the network can not run correctly, the unreachable and dead nodes are only warnings.
+        self.dangling_id.is_empty()
+            && self.fed_input_id.is_empty()
+            && self.cycle_pipe.is_empty()
+            && self.shape_mismatch.is_empty()
+    }
+    pub fn is_clean(self: &Self) -> bool {
+        // Return true if there is no error and no warning.
+        self.is_ok() && self.unreachable_id.is_empty() && self.dead_id.is_empty()
+    }
+}
+impl fmt::Display for ValidationReport {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut message_list: Vec<String> = Vec::new();
+        if !self.dangling_id.is_empty() {
+            message_list.push(format!("dangling ids {:?}", self.dangling_id));
+        }
+        if !self.fed_input_id.is_empty() {
+            message_list.push(format!("input nodes with sources {:?}", self.fed_input_id));
+        }
+        if !self.cycle_pipe.is_empty() {
+            message_list.push(format!("non-recurrent cycle pipes {:?}", self.cycle_pipe));
+        }
+        for message in self.shape_mismatch.iter() {
+            message_list.push(message.clone());
+        }
+        if !self.unreachable_id.is_empty() {
+            message_list.push(format!("unreachable nodes {:?}", self.unreachable_id));
+        }
+        if !self.dead_id.is_empty() {
+            message_list.push(format!("dead nodes {:?}", self.dead_id));
+        }
+        if message_list.is_empty() {
+            write!(f, "no problem found")
+        } else {
+            write!(f, "{}", message_list.join("; "))
         }
-        queue_list
     }
 }
 
@@ -321,31 +732,60 @@ pub struct FlexibleNetwork {
     nodes: Vec<Node>,
     input_id: Vec<usize>,
     output_id: Vec<usize>,
-    layer_length: HashMap<usize, usize>,
+    layer_length: BTreeMap<usize, usize>,
+    optimizer_enum: OptimizerEnum,
+    loss_fn_enum: LossFunctionEnum,
+    gradient_count: usize,
+    // Number of the samples accumulated in the nodes' gradient storage.
+    rng: StdRng,
+    // Drive the weight initializers, it is not exported.
+    calc_order: Option<Vec<usize>>,
+    // Execution plan cached by "compile", set back to None whenever the graph changes.
+    history: VecDeque<Vec<NodeStep>>,
+    history_length: usize,
+    // The last "history_length" steps of "next", indexed by [step][node id].
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FlexibleNetworkData {
+    #[serde(default)]
+    v: u32,
     ns: Vec<NodeData>,
     i_id: Vec<usize>,
     o_id: Vec<usize>,
-    l_len: HashMap<usize, usize>,
+    l_len: BTreeMap<usize, usize>,
+    opt: OptimizerEnum,
+    l_fn: LossFunctionEnum,
+}
+const FLEXIBLE_NETWORK_MIGRATION_LIST: [Migration; 1] = [migrate_flexible_network_v0];
+// "FlexibleNetworkData" of version i is upgraded by "FLEXIBLE_NETWORK_MIGRATION_LIST[i]", see "schema::migrate".
+fn migrate_flexible_network_v0(mut data: Map<String, Value>) -> Result<Map<String, Value>> {
+    // The files before the version is added, such as "model/net.json", have no optimizer or loss function.
+    check_required(&data, &["ns", "i_id", "o_id", "l_len"])?;
+    insert_default(
+        &mut data,
+        "opt",
+        serde_json::to_value(OptimizerEnum::default())?,
+    );
+    insert_default(
+        &mut data,
+        "l_fn",
+        serde_json::to_value(LossFunctionEnum::default())?,
+    );
+    Ok(data)
 }
 impl FlexibleNetworkData {
     fn new() -> Self {
         Self {
+            v: FLEXIBLE_NETWORK_MIGRATION_LIST.len() as u32,
             ns: Vec::new(),
             i_id: Vec::new(),
             o_id: Vec::new(),
-            l_len: HashMap::new(),
+            l_len: BTreeMap::new(),
+            opt: OptimizerEnum::default(),
+            l_fn: LossFunctionEnum::default(),
         }
     }
 }
-struct FlexibleNetworkUpdateItem {
-    // Item to Update FlexibleNetwork Node
-    to_id: usize,
-    to_index: usize,
-    new_value: f64,
-}
 #[allow(dead_code)]
 impl Segment for FlexibleNetwork {
     fn new() -> Self {
@@ -354,18 +794,22 @@ impl Segment for FlexibleNetwork {
             nodes: Vec::new(),
             input_id: Vec::new(),
             output_id: Vec::new(),
-            layer_length: HashMap::new(),
+            layer_length: BTreeMap::new(),
+            optimizer_enum: OptimizerEnum::default(),
+            loss_fn_enum: LossFunctionEnum::default(),
+            gradient_count: 0,
+            rng: StdRng::seed_from_u64(0),
+            calc_order: None,
+            history: VecDeque::new(),
+            history_length: 0,
         }
     }
-    fn get_type(self: &Self) -> SegmentTypes {
-        SegmentTypes::FlexibleNetwork
+    fn get_type(self: &Self) -> String {
+        String::from("FlexibleNetwork")
     }
-    fn set_input(self: &mut Self, input_value: Vec<f64>) {
+    fn set_input(self: &mut Self, input_value: Vec<f64>) -> Result<()> {
         // Set input values.
-        for i in 0..self.input_id.len() {
-            let node: &mut Node = &mut self.nodes[self.input_id[i]];
-            node.value = input_value[i];
-        }
+        self.set_input_value(&input_value)
     }
     fn get_output(self: &mut Self) -> Vec<f64> {
         // Return output value list.
@@ -377,6 +821,12 @@ impl Segment for FlexibleNetwork {
         }
         output_value
     }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from([self.input_id.len()]))
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from([self.output_id.len()]))
+    }
     fn export_data(self: &mut Self) -> String {
         let mut node_data_array: Vec<NodeData> = Vec::new();
         for id in 0..self.nodes.len() {
@@ -387,165 +837,308 @@ impl Segment for FlexibleNetwork {
                 i_w: node.input_w.clone(),
                 b: node.b,
                 a_fn: node.activation_fn_enum,
+                a_p: node.activation_params.clone(),
+                i_r: (0..node.input_count)
+                    .filter(|&index| node.input_recurrent[index] && node.input_id[index] != id)
+                    .collect::<Vec<usize>>(),
+                o_s: node.optimizer_state.clone(),
             };
             node_data_array.push(node_data);
         }
         let data: FlexibleNetworkData = FlexibleNetworkData {
+            v: FLEXIBLE_NETWORK_MIGRATION_LIST.len() as u32,
             ns: node_data_array,
             i_id: self.input_id.clone(),
             o_id: self.output_id.clone(),
             l_len: self.layer_length.clone(),
+            opt: self.optimizer_enum,
+            l_fn: self.loss_fn_enum,
         };
         serde_json::to_string(&data).unwrap()
     }
-    fn import_data(self: &mut Self, data: String) {
-        let data: FlexibleNetworkData = serde_json::from_str(&data.as_str()).unwrap();
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: FlexibleNetworkData =
+            serde_json::from_value(migrate(&data, &FLEXIBLE_NETWORK_MIGRATION_LIST)?)?;
+        let node_count: usize = data.ns.len();
+        let mut nodes: Vec<Node> = Vec::new();
         for node_data in data.ns {
+            if node_data.id != nodes.len() {
+                return Err(NetworkError::MalformedData(format!(
+                    "node at position {} has id {}",
+                    nodes.len(),
+                    node_data.id
+                )));
+            }
+            if node_data.i_id.len() != node_data.i_w.len() {
+                return Err(NetworkError::MalformedData(format!(
+                    "node {} has {} input ids but {} weights",
+                    node_data.id,
+                    node_data.i_id.len(),
+                    node_data.i_w.len()
+                )));
+            }
             let mut node: Node = Node::new(node_data.id);
-            node.activation_fn_enum = node_data.a_fn;
-            node.input_count = node_data.i_id.len();
-            node.input_id = node_data.i_id.clone();
-            node.input_w = node_data.i_w.clone();
-            node.input_value = node.input_id.iter().map(|&x| 0.0).collect::<Vec<f64>>();
-            node.input_partial = node.input_value.clone();
-            self.nodes.push(node);
+            node.b = node_data.b;
+            node.set_activation_function(node_data.a_fn);
+            if !node_data.a_p.is_empty() {
+                node.activation_params = node_data.a_p;
+            }
+            node.optimizer_state = node_data.o_s;
+            for i in 0..node_data.i_id.len() {
+                if node_data.i_id[i] >= node_count {
+                    return Err(NetworkError::NodeNotFound(node_data.i_id[i]));
+                }
+                node.new_input_source(node_data.i_id[i], node_data.i_w[i]);
+            }
+            for &index in node_data.i_r.iter() {
+                if index >= node.input_count {
+                    return Err(NetworkError::MalformedData(format!(
+                        "node {} has no input source at index {}",
+                        node_data.id, index
+                    )));
+                }
+                node.input_recurrent[index] = true;
+            }
+            nodes.push(node);
+        }
+        // Rebuild nodes and their input sources.
+        for id in 0..nodes.len() {
+            for index in 0..nodes[id].input_count {
+                let from_id: usize = nodes[id].input_id[index];
+                nodes[from_id].new_output_target(id, index);
+            }
+        }
+        // Rebuild the output targets, which are not stored in the data.
+        for &id in data.i_id.iter().chain(data.o_id.iter()) {
+            if id >= node_count {
+                return Err(NetworkError::NodeNotFound(id));
+            }
         }
-        self.input_id = data.i_id;
-        self.output_id = data.o_id;
-        self.layer_length = data.l_len;
+        for (&layer_id, &layer_length) in data.l_len.iter() {
+            if layer_id + layer_length > node_count {
+                return Err(NetworkError::LayerNotFound(layer_id));
+            }
+        }
+        let mut imported_net: FlexibleNetwork = FlexibleNetwork::new();
+        imported_net.nodes = nodes;
+        imported_net.input_id = data.i_id;
+        imported_net.output_id = data.o_id;
+        imported_net.layer_length = data.l_len;
+        imported_net.optimizer_enum = data.opt;
+        imported_net.loss_fn_enum = data.l_fn;
+        let report: ValidationReport = imported_net.validate();
+        if !report.is_ok() {
+            return Err(NetworkError::MalformedData(report.to_string()));
+        }
+        // Unreachable and dead nodes are allowed, call "validate" to see them.
+        imported_net.rng = self.rng.clone();
+        imported_net.history_length = self.history_length;
+        *self = imported_net;
+        Ok(())
     }
-    fn next(self: &mut Self) {
+    fn next(self: &mut Self) -> Result<()> {
         // Next step of this network.
-        // Update value from top to bottom in the node chain.
-
-        for id in 0..self.nodes.len() {
-            self.nodes[id].calc_planned = false;
-        }
-        // Set all of the node to not calculated yet.
-
-        let mut queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-        let output_id: Vec<usize> = self.output_id.clone();
-        for id in output_id {
-            queue_list.append(&mut self.nodes[id].fetch_value());
-        }
-        // Append all of the fetch queue item into queue list.
-
-        let mut still_queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-        loop {
-            if queue_list.len() == 0 {
-                break;
-            }
-            let mut new_queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-            let mut update_data: Vec<FlexibleNetworkUpdateItem> = Vec::new();
-            for queue_item in queue_list {
-                let from_id: usize = queue_item.from_id;
-                if self.nodes[from_id].input_count == 0 || from_id == queue_item.to_id {
-                    update_data.push(FlexibleNetworkUpdateItem {
-                        to_id: queue_item.to_id,
-                        to_index: queue_item.to_index,
-                        new_value: self.nodes[from_id].get_value(),
-                    });
-                } else {
-                    still_queue_list.push(queue_item);
-                    if !self.nodes[from_id].calc_planned {
-                        // Use "calc_planned" flag to prevent a node chain fetch value so more times.
-                        new_queue_list.append(&mut self.nodes[from_id].fetch_value());
-                        self.nodes[from_id].calc_planned = true;
-                    }
-                }
+        // Update value from top to bottom in the execution plan, every source node is calculated before the nodes it feeds.
+        self.compile();
+        let calc_order: &Vec<usize> = self.calc_order.as_ref().unwrap();
+        for &id in calc_order.iter() {
+            if self.nodes[id].input_count == 0 {
+                continue;
             }
-            for item in &update_data {
-                self.get_node(item.to_id).input_value[item.to_index] = item.new_value;
+            // Input nodes keep the value given by "set_input".
+            for index in 0..self.nodes[id].input_count {
+                let from_id: usize = self.nodes[id].input_id[index];
+                self.nodes[id].input_value[index] = if self.nodes[id].input_recurrent[index] {
+                    self.nodes[from_id].last_value
+                } else {
+                    self.nodes[from_id].value
+                };
             }
-            queue_list = new_queue_list;
+            self.nodes[id].calc_value();
         }
-        // To check if the source node is input node one by one, then set it's value back to the fetching node.
-
-        for queue_item in still_queue_list.iter().rev() {
-            let value: f64 = self.nodes[queue_item.from_id].calc_value();
-            self.nodes[queue_item.to_id].input_value[queue_item.to_index] = value;
+        for node in self.nodes.iter_mut() {
+            node.last_value = node.value;
         }
-        // Calculate node's value and transfer it in reverse.
-
-        let output_id: Vec<usize> = self.output_id.clone();
-        for id in output_id {
-            self.nodes[id].calc_value();
+        // Keep the values of this step for the recurrent pipes of the next step.
+        if self.history_length > 0 {
+            self.history.push_back(
+                self.nodes
+                    .iter()
+                    .map(|node| NodeStep {
+                        input_value: node.input_value.clone(),
+                        sum: node.sum,
+                        value: node.value,
+                    })
+                    .collect::<Vec<NodeStep>>(),
+            );
+            if self.history.len() > self.history_length {
+                self.history.pop_front();
+            }
         }
-        // Calculate output nodes' value.
+        Ok(())
     }
-    fn can_fitting(self: &Self) -> bool {
-        true
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Run the inputs as steps in order, the recurrent pipes pass the values from one sample to the next.
+        let mut output_batch: Vec<Vec<f64>> = Vec::with_capacity(input_batch.len());
+        for input in input_batch.iter() {
+            self.set_input_value(input)?;
+            self.next()?;
+            output_batch.push(self.get_output());
+        }
+        Ok(output_batch)
     }
-    fn fitting(self: &mut Self, anticipated_output: Vec<f64>, learning_rate: f64) {
-        for id in 0..self.nodes.len() {
-            self.nodes[id].calc_planned = false;
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        self.loss_fn_enum
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.output_id.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_id.len(),
+                actual: output_partial.len(),
+            });
         }
-        // Set all of the node to not calculated yet.
         for id in 0..self.nodes.len() {
             self.nodes[id].partial = 0.0;
         }
         // Set all of the node value partial to zero.
         for i in 0..self.output_id.len() {
-            self.nodes[self.output_id[i]].anticipated_value = anticipated_output[i];
-            self.nodes[self.output_id[i]].fitting(learning_rate, true);
-        }
-
-        let mut queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-        let input_id: Vec<usize> = self.input_id.clone();
-        for id in input_id {
-            queue_list.append(&mut self.nodes[id].fetch_partial());
-        }
-        // Append all of the fetch queue item into queue list.
-
-        let mut still_queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-        loop {
-            if queue_list.len() == 0 {
-                break;
-            }
-            let mut new_queue_list: Vec<NodeFetchQueueItem> = Vec::new();
-            let mut update_data: Vec<FlexibleNetworkUpdateItem> = Vec::new();
-            for queue_item in queue_list {
-                let from_id: usize = queue_item.from_id;
-                if self.nodes[from_id].output_count == 0 || from_id == queue_item.to_id {
-                    update_data.push(FlexibleNetworkUpdateItem {
-                        to_id: queue_item.to_id,
-                        to_index: 0,
-                        new_value: self.nodes[from_id].get_partial(queue_item.to_index),
-                    });
-                } else {
-                    still_queue_list.push(queue_item);
-                    if !self.nodes[from_id].calc_planned {
-                        // Use "calc_planned" flag to prevent a node chain fetch value so more times.
-                        new_queue_list.append(&mut self.nodes[from_id].fetch_partial());
-                        self.nodes[from_id].calc_planned = true;
-                    }
+            self.nodes[self.output_id[i]].partial += output_partial[i];
+        }
+
+        self.compile();
+        let calc_order: &Vec<usize> = self.calc_order.as_ref().unwrap();
+        for &id in calc_order.iter().rev() {
+            self.nodes[id].accumulate_gradient();
+            for index in 0..self.nodes[id].input_count {
+                let from_id: usize = self.nodes[id].input_id[index];
+                if !self.nodes[id].input_recurrent[index] {
+                    self.nodes[from_id].partial += self.nodes[id].get_partial(index);
                 }
             }
-            for item in &update_data {
-                self.get_node(item.to_id).partial += item.new_value;
-            }
-            queue_list = new_queue_list;
         }
-        // To check if the source node is input node one by one, then set it's value back to the fetching node.
+        // Walk the nodes in reverse order, so every node has collected all of the partial from its output targets first.
+        // The partial through the recurrent pipes belongs to the last step, see "accumulate_gradients_through_time".
+        self.gradient_count += 1;
 
-        for queue_item in still_queue_list.iter().rev() {
-            self.nodes[queue_item.from_id].fitting(learning_rate, false);
-            self.nodes[queue_item.to_id].partial +=
-                self.nodes[queue_item.from_id].get_partial(queue_item.to_index);
+        Ok(self
+            .input_id
+            .iter()
+            .map(|&id| self.nodes[id].partial)
+            .collect::<Vec<f64>>())
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        // The weights, bias and trainable activation parameters of every node with input sources, in the order of the id.
+        let mut parameters: Vec<f64> = Vec::new();
+        for node in self.nodes.iter().filter(|node| node.input_count > 0) {
+            parameters.extend(node.input_w.iter());
+            parameters.push(node.b);
+            parameters
+                .extend(node.activation_params[..node.activation_params_gradient.len()].iter());
+        }
+        parameters
+    }
+    fn parameter_count(self: &Self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| node.input_count > 0)
+            .map(|node| node.input_count + 1 + node.activation_params_gradient.len())
+            .sum()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), parameters.len())?;
+        let mut start: usize = 0;
+        for node in self.nodes.iter_mut().filter(|node| node.input_count > 0) {
+            let parameter_count: usize = node.activation_params_gradient.len();
+            node.input_w
+                .copy_from_slice(&parameters[start..start + node.input_count]);
+            start += node.input_count;
+            node.b = parameters[start];
+            start += 1;
+            node.activation_params[..parameter_count]
+                .copy_from_slice(&parameters[start..start + parameter_count]);
+            start += parameter_count;
         }
-        // Calculate node's value and transfer it in reverse.
+        Ok(())
+    }
+    fn gradients(self: &Self) -> Vec<f64> {
+        let gradient_count: f64 = self.gradient_count.max(1) as f64;
+        // The gradients are all zero before any "accumulate_gradients".
+        let mut gradients: Vec<f64> = Vec::new();
+        for node in self.nodes.iter().filter(|node| node.input_count > 0) {
+            gradients.extend(node.input_w_gradient.iter());
+            gradients.push(node.b_gradient);
+            gradients.extend(node.activation_params_gradient.iter());
+        }
+        gradients
+            .into_iter()
+            .map(|gradient| gradient / gradient_count)
+            .collect::<Vec<f64>>()
+    }
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), gradients.len())?;
+        let mut start: usize = 0;
+        for node in self.nodes.iter_mut().filter(|node| node.input_count > 0) {
+            let parameter_count: usize = node.activation_params_gradient.len();
+            node.input_w_gradient
+                .copy_from_slice(&gradients[start..start + node.input_count]);
+            start += node.input_count;
+            node.b_gradient = gradients[start];
+            start += 1;
+            node.activation_params_gradient
+                .copy_from_slice(&gradients[start..start + parameter_count]);
+            start += parameter_count;
+        }
+        self.gradient_count = 1;
+        Ok(())
+    }
+    fn apply_gradients(self: &mut Self, learning_rate: f64) {
+        self.compile();
+        let calc_order: &Vec<usize> = self.calc_order.as_ref().unwrap();
+        for &id in calc_order.iter() {
+            self.nodes[id].apply_gradient(learning_rate, self.gradient_count, self.optimizer_enum);
+        }
+    }
+    fn zero_grad(self: &mut Self) {
+        for id in 0..self.nodes.len() {
+            self.nodes[id].zero_gradient();
+        }
+        self.gradient_count = 0;
+    }
+    fn reset_state(self: &mut Self) {
+        // Forget the values of the past steps, call it at the start of every sequence.
+        for node in self.nodes.iter_mut() {
+            node.sum = 0.0;
+            node.value = 0.0;
+            node.last_value = 0.0;
+            node.input_value.iter_mut().for_each(|value| *value = 0.0);
+        }
+        self.history.clear();
     }
 }
 #[allow(dead_code)]
 impl FlexibleNetwork {
     // FlexibleNetwork's Implementation
+    fn set_input_value(self: &mut Self, input_value: &[f64]) -> Result<()> {
+        // Write the input values into the input nodes, without taking the list.
+        if input_value.len() != self.input_id.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.input_id.len(),
+                actual: input_value.len(),
+            });
+        }
+        for (&id, &value) in self.input_id.iter().zip(input_value) {
+            self.nodes[id].value = value;
+        }
+        Ok(())
+    }
     pub fn new_node(self: &mut Self, b: f64, activation_fn_enum: ActivationFunctionEnum) -> usize {
         // Create new node and initialize it.
         let id = self.nodes.len();
         let mut node = Node::new(id);
         node.b = b;
-        node.activation_fn_enum = activation_fn_enum;
+        node.set_activation_function(activation_fn_enum);
         self.nodes.push(node);
+        self.calc_order = None;
         id
     }
     pub fn new_layer(
@@ -559,61 +1152,531 @@ impl FlexibleNetwork {
         for i in 0..node_number {
             let mut node = Node::new(id_start + i);
             node.b = b;
-            node.activation_fn_enum = activation_fn_enum;
+            node.set_activation_function(activation_fn_enum);
             self.nodes.push(node);
         }
         self.layer_length.insert(id_start, node_number);
+        self.calc_order = None;
         id_start
     }
-    pub fn connect(self: &mut Self, from_id: usize, to_id: usize, w: f64) {
+    pub fn connect(self: &mut Self, from_id: usize, to_id: usize, w: f64) -> Result<()> {
         // Connect two of the node that is in this network.
+        self.check_id(from_id)?;
+        self.check_id(to_id)?;
+        self.connect_pipe(from_id, to_id, w, false);
+        Ok(())
+    }
+    pub fn connect_recurrent(self: &mut Self, from_id: usize, to_id: usize, w: f64) -> Result<()> {
+        // Connect two of the node, the target node takes the source node's value of the last step.
+        self.check_id(from_id)?;
+        self.check_id(to_id)?;
+        self.connect_pipe(from_id, to_id, w, true);
+        Ok(())
+    }
+    fn connect_pipe(self: &mut Self, from_id: usize, to_id: usize, w: f64, recurrent: bool) {
         let index: usize = self.nodes[to_id].new_input_source(from_id, w);
+        self.nodes[to_id].input_recurrent[index] |= recurrent;
         self.nodes[from_id].new_output_target(to_id, index);
+        self.calc_order = None;
+    }
+    fn check_id(self: &Self, id: usize) -> Result<usize> {
+        // To check the id is referring to a node in this network.
+        if id < self.nodes.len() {
+            Ok(id)
+        } else {
+            Err(NetworkError::NodeNotFound(id))
+        }
+    }
+    fn get_id_array_from_layer(self: &Self, layer_id: usize) -> Result<Vec<usize>> {
+        match self.layer_length.get(&layer_id) {
+            Some(layer_length) => Ok((layer_id..layer_id + layer_length).collect::<Vec<usize>>()),
+            None => Err(NetworkError::LayerNotFound(layer_id)),
+        }
+    }
+    fn compile(self: &mut Self) {
+        // Build the execution plan if the graph has changed since the last one.
+        if self.calc_order.is_none() {
+            self.calc_order = Some(self.get_calc_order());
+        }
     }
-    // fn get_id_array_from_layer(self: &mut Self, layer_id: usize) -> Vec<usize> {
-    //     match self.layer_length.get(&layer_id) {
-    //         Some(layer_length) => (layer_id..layer_id+layer_length).collect::<Vec<usize>>(),
-    //         None => panic!("[{}]: Layer not found!", "get_id_array_from_layer")
-    //     }
-    // }
-    pub fn connect_layer(self: &mut Self, from_layer: usize, to_layer: usize, w: f64) {
-        let from_layer_length: &usize = self.layer_length.get(&from_layer).unwrap();
-        let to_layer_length: &usize = self.layer_length.get(&to_layer).unwrap();
-        for f in from_layer..from_layer + from_layer_length {
-            for t in to_layer..to_layer + to_layer_length {
-                let index: usize = self.nodes[t].new_input_source(f, w);
-                self.nodes[f].new_output_target(t, index);
+    fn get_calc_order(self: &Self) -> Vec<usize> {
+        // Return the ids of the nodes which the output nodes depend on, every node is placed after all of its source nodes.
+        // The source of a recurrent pipe is only required to be calculated in the step, not before the target node.
+        let mut calc_order: Vec<usize> = Vec::new();
+        let mut visited: Vec<bool> = vec![false; self.nodes.len()];
+        let mut root_list: Vec<usize> = self.output_id.iter().rev().cloned().collect();
+        while let Some(root_id) = root_list.pop() {
+            if visited[root_id] {
+                continue;
+            }
+            visited[root_id] = true;
+            let mut stack: Vec<(usize, usize)> = Vec::from([(root_id, 0)]);
+            // (node id, index of the next input source to visit)
+            while let Some((id, index)) = stack.pop() {
+                if index < self.nodes[id].input_count {
+                    stack.push((id, index + 1));
+                    let from_id: usize = self.nodes[id].input_id[index];
+                    if self.nodes[id].input_recurrent[index] {
+                        root_list.push(from_id);
+                    } else if !visited[from_id] {
+                        visited[from_id] = true;
+                        stack.push((from_id, 0));
+                    }
+                } else {
+                    calc_order.push(id);
+                }
             }
         }
+        calc_order
+    }
+    pub fn connect_layer(
+        self: &mut Self,
+        from_layer: usize,
+        to_layer: usize,
+        initializer_enum: InitializerEnum,
+    ) -> Result<()> {
+        // Fully connect two layers, the weights are drawn by the initializer.
+        self.connect_layer_pipe(from_layer, to_layer, initializer_enum, false)
     }
-    pub fn set_input_id(self: &mut Self, input_id: Vec<usize>) {
+    pub fn connect_layer_recurrent(
+        self: &mut Self,
+        from_layer: usize,
+        to_layer: usize,
+        initializer_enum: InitializerEnum,
+    ) -> Result<()> {
+        // Fully connect two layers with recurrent pipes, connect a layer to itself to give it memory.
+        self.connect_layer_pipe(from_layer, to_layer, initializer_enum, true)
+    }
+    fn connect_layer_pipe(
+        self: &mut Self,
+        from_layer: usize,
+        to_layer: usize,
+        initializer_enum: InitializerEnum,
+        recurrent: bool,
+    ) -> Result<()> {
+        let from_id_array: Vec<usize> = self.get_id_array_from_layer(from_layer)?;
+        let to_id_array: Vec<usize> = self.get_id_array_from_layer(to_layer)?;
+        let w_matrix: Vec<Vec<f64>> = Initializer::get_matrix(
+            initializer_enum,
+            from_id_array.len(),
+            to_id_array.len(),
+            &mut self.rng,
+        );
+        for (i, &f) in from_id_array.iter().enumerate() {
+            for (j, &t) in to_id_array.iter().enumerate() {
+                self.connect_pipe(f, t, w_matrix[j][i], recurrent);
+            }
+        }
+        Ok(())
+    }
+    pub fn set_input_id(self: &mut Self, input_id: Vec<usize>) -> Result<()> {
         // Set the node ids corresponding to the input values.
+        for &id in input_id.iter() {
+            self.check_id(id)?;
+        }
         let _ = self.input_id.try_reserve(self.input_id.len());
         let _ = self.input_id.extend(input_id);
+        Ok(())
     }
-    pub fn set_input_layer(self: &mut Self, input_layer: usize) {
-        match self.layer_length.get(&input_layer) {
-            Some(layer_length) => {
-                self.input_id = (input_layer..input_layer + layer_length).collect::<Vec<usize>>()
-            }
-            None => panic!("[{}]: Layer not found!", "set_input_layer"),
-        }
+    pub fn set_input_layer(self: &mut Self, input_layer: usize) -> Result<()> {
+        self.input_id = self.get_id_array_from_layer(input_layer)?;
+        Ok(())
     }
-    pub fn set_output_id(self: &mut Self, output_id: Vec<usize>) {
+    pub fn set_output_id(self: &mut Self, output_id: Vec<usize>) -> Result<()> {
         // Set the node ids corresponding to the output values.
+        for &id in output_id.iter() {
+            self.check_id(id)?;
+        }
         let _ = self.output_id.try_reserve(self.output_id.len());
         let _ = self.output_id.extend(output_id);
+        self.calc_order = None;
+        Ok(())
+    }
+    pub fn set_output_layer(self: &mut Self, output_layer: usize) -> Result<()> {
+        self.output_id = self.get_id_array_from_layer(output_layer)?;
+        self.calc_order = None;
+        Ok(())
+    }
+    pub fn validate(self: &Self) -> ValidationReport {
+        // Check the graph, and report the problems instead of stopping at the first one.
+        let node_count: usize = self.nodes.len();
+        let mut report: ValidationReport = ValidationReport::default();
+
+        for &id in self.input_id.iter().chain(self.output_id.iter()) {
+            if id >= node_count {
+                report.dangling_id.push(id);
+            }
+        }
+        for node in self.nodes.iter() {
+            for &from_id in node.input_id.iter().chain(node.output_id.iter()) {
+                if from_id >= node_count {
+                    report.dangling_id.push(from_id);
+                }
+            }
+            if node.input_id.len() != node.input_count
+                || node.input_w.len() != node.input_count
+                || node.input_recurrent.len() != node.input_count
+                || node.input_value.len() != node.input_count
+                || node.output_id.len() != node.output_count
+                || node.output_index.len() != node.output_count
+            {
+                report
+                    .shape_mismatch
+                    .push(format!("node {} has inconsistent pipe lists", node.id));
+            }
+            let parameter_count: usize =
+                ActivationFunction::get_default_parameters(node.activation_fn_enum).len();
+            if node.activation_params.len() != parameter_count {
+                report.shape_mismatch.push(format!(
+                    "node {} has {} activation parameters but {:?} takes {}",
+                    node.id,
+                    node.activation_params.len(),
+                    node.activation_fn_enum,
+                    parameter_count
+                ));
+            }
+        }
+        report.dangling_id.sort();
+        report.dangling_id.dedup();
+        if !report.dangling_id.is_empty() || !report.shape_mismatch.is_empty() {
+            return report;
+        }
+        // The graph can not be walked safely with dangling ids.
+
+        let mut last_layer: Option<(usize, usize)> = None;
+        for (&layer_id, &layer_length) in self.layer_length.iter() {
+            if layer_id + layer_length > node_count {
+                report.shape_mismatch.push(format!(
+                    "layer {} with {} nodes exceeds the {} nodes",
+                    layer_id, layer_length, node_count
+                ));
+            }
+            if let Some((last_layer_id, last_layer_length)) = last_layer {
+                if last_layer_id + last_layer_length > layer_id {
+                    report.shape_mismatch.push(format!(
+                        "layer {} overlaps layer {}",
+                        last_layer_id, layer_id
+                    ));
+                }
+            }
+            last_layer = Some((layer_id, layer_length));
+        }
+        for (name, id_list) in [("input", &self.input_id), ("output", &self.output_id)] {
+            let mut sorted_id_list: Vec<usize> = id_list.clone();
+            sorted_id_list.sort();
+            sorted_id_list.dedup();
+            if sorted_id_list.len() != id_list.len() {
+                report
+                    .shape_mismatch
+                    .push(format!("{} ids {:?} are repeated", name, id_list));
+            }
+        }
+
+        for &id in self.input_id.iter() {
+            if self.nodes[id].input_count > 0 && !report.fed_input_id.contains(&id) {
+                report.fed_input_id.push(id);
+            }
+        }
+        report.fed_input_id.sort();
+
+        let mut state: Vec<u8> = vec![0; node_count];
+        // 0: not visited, 1: on the current path, 2: finished
+        for root_id in 0..node_count {
+            if state[root_id] != 0 {
+                continue;
+            }
+            state[root_id] = 1;
+            let mut stack: Vec<(usize, usize)> = Vec::from([(root_id, 0)]);
+            while let Some((id, index)) = stack.pop() {
+                if index < self.nodes[id].input_count {
+                    stack.push((id, index + 1));
+                    if self.nodes[id].input_recurrent[index] {
+                        continue;
+                    }
+                    let from_id: usize = self.nodes[id].input_id[index];
+                    match state[from_id] {
+                        0 => {
+                            state[from_id] = 1;
+                            stack.push((from_id, 0));
+                        }
+                        1 => report.cycle_pipe.push([from_id, id]),
+                        _ => {}
+                    }
+                } else {
+                    state[id] = 2;
+                }
+            }
+        }
+        report.cycle_pipe.sort();
+        // Walk the non-recurrent pipes backward, a pipe from a node on the current path closes a cycle.
+
+        let mut reachable: Vec<bool> = vec![false; node_count];
+        let mut stack: Vec<usize> = self.input_id.clone();
+        while let Some(id) = stack.pop() {
+            if reachable[id] {
+                continue;
+            }
+            reachable[id] = true;
+            stack.extend(self.nodes[id].output_id.iter());
+        }
+        report.unreachable_id = (0..node_count).filter(|&id| !reachable[id]).collect();
+        let mut alive: Vec<bool> = vec![false; node_count];
+        for &id in self.get_calc_order().iter() {
+            alive[id] = true;
+        }
+        report.dead_id = (0..node_count).filter(|&id| !alive[id]).collect();
+        report
+    }
+    pub fn set_activation_parameters(
+        self: &mut Self,
+        id: usize,
+        activation_params: Vec<f64>,
+    ) -> Result<()> {
+        // Set the parameters of a node's activation function, such as the slope of LeakyReLU.
+        self.check_id(id)?;
+        let node: &mut Node = &mut self.nodes[id];
+        if activation_params.len() != node.activation_params.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: node.activation_params.len(),
+                actual: activation_params.len(),
+            });
+        }
+        node.activation_params = activation_params;
+        Ok(())
+    }
+    pub fn gradient_check(
+        self: &mut Self,
+        input: &[f64],
+        anticipated_data: &[f64],
+        epsilon: f64,
+    ) -> Result<GradientCheckReport> {
+        // Perturb every weight, bias and trainable activation parameter, and compare the central differences of the lost
+        // with the gradients of "accumulate_gradients". The state of the recurrent pipes is kept, the accumulated gradients are cleared.
+        let history_length: usize = self.history_length;
+        self.history_length = 0;
+        let last_value_list: Vec<f64> = self.nodes.iter().map(|node| node.last_value).collect();
+        let lost = |net: &mut FlexibleNetwork, input: &[f64]| -> Result<f64> {
+            for (node, &last_value) in net.nodes.iter_mut().zip(last_value_list.iter()) {
+                node.last_value = last_value;
+            }
+            get_lost(net, input, anticipated_data)
+        };
+
+        self.zero_grad();
+        lost(self, input)?;
+        let output_partial: Vec<f64> =
+            get_lost_partial(self.loss_fn_enum, &self.get_output(), anticipated_data)?;
+        let input_partial: Vec<f64> = self.accumulate_gradients(output_partial)?;
+        self.compile();
+        let calc_order: Vec<usize> = self.calc_order.clone().unwrap();
+
+        let mut report: GradientCheckReport = GradientCheckReport::default();
+        for i in 0..input.len() {
+            let mut input_plus: Vec<f64> = input.to_vec();
+            input_plus[i] += epsilon;
+            let mut input_minus: Vec<f64> = input.to_vec();
+            input_minus[i] -= epsilon;
+            let numerical_partial: f64 =
+                (lost(self, &input_plus)? - lost(self, &input_minus)?) / (2.0 * epsilon);
+            report
+                .input_error
+                .push(relative_error(input_partial[i], numerical_partial));
+        }
+        for &id in calc_order.iter() {
+            if self.nodes[id].input_count == 0 {
+                continue;
+            }
+            let mut worst_error: f64 = 0.0;
+            for index in 0..self.nodes[id].input_count {
+                let analytic_gradient: f64 = self.nodes[id].input_w_gradient[index];
+                let original_w: f64 = self.nodes[id].input_w[index];
+                self.nodes[id].input_w[index] = original_w + epsilon;
+                let lost_plus: f64 = lost(self, input)?;
+                self.nodes[id].input_w[index] = original_w - epsilon;
+                let lost_minus: f64 = lost(self, input)?;
+                self.nodes[id].input_w[index] = original_w;
+                worst_error = worst_error.max(relative_error(
+                    analytic_gradient,
+                    (lost_plus - lost_minus) / (2.0 * epsilon),
+                ));
+            }
+            let analytic_gradient: f64 = self.nodes[id].b_gradient;
+            let original_b: f64 = self.nodes[id].b;
+            self.nodes[id].b = original_b + epsilon;
+            let lost_plus: f64 = lost(self, input)?;
+            self.nodes[id].b = original_b - epsilon;
+            let lost_minus: f64 = lost(self, input)?;
+            self.nodes[id].b = original_b;
+            worst_error = worst_error.max(relative_error(
+                analytic_gradient,
+                (lost_plus - lost_minus) / (2.0 * epsilon),
+            ));
+            for k in 0..self.nodes[id].activation_params_gradient.len() {
+                let analytic_gradient: f64 = self.nodes[id].activation_params_gradient[k];
+                let original_p: f64 = self.nodes[id].activation_params[k];
+                self.nodes[id].activation_params[k] = original_p + epsilon;
+                let lost_plus: f64 = lost(self, input)?;
+                self.nodes[id].activation_params[k] = original_p - epsilon;
+                let lost_minus: f64 = lost(self, input)?;
+                self.nodes[id].activation_params[k] = original_p;
+                worst_error = worst_error.max(relative_error(
+                    analytic_gradient,
+                    (lost_plus - lost_minus) / (2.0 * epsilon),
+                ));
+            }
+            report.node_error.insert(id, worst_error);
+        }
+
+        lost(self, input)?;
+        for (node, &last_value) in self.nodes.iter_mut().zip(last_value_list.iter()) {
+            node.last_value = last_value;
+        }
+        // Leave the network with the values of the given input, as if the step had not been taken.
+        self.zero_grad();
+        self.history_length = history_length;
+        Ok(report)
+    }
+    pub fn set_history_length(self: &mut Self, history_length: usize) {
+        // Keep the last "history_length" steps of "next" for "accumulate_gradients_through_time", 0 turns the record off.
+        self.history_length = history_length;
+        while self.history.len() > history_length {
+            self.history.pop_front();
+        }
+    }
+    pub fn accumulate_gradients_through_time(
+        self: &mut Self,
+        output_partial_list: Vec<Vec<f64>>,
+    ) -> Result<Vec<Vec<f64>>> {
+        // Backpropagation through time, take $\frac{\partial lost}{\partial output}$ of the last recorded steps in order,
+        // add the gradients of the whole sequence into the nodes, and return $\frac{\partial lost}{\partial input}$ of every step.
+        let step_count: usize = output_partial_list.len();
+        if step_count > self.history.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.history.len(),
+                actual: step_count,
+            });
+        }
+        for output_partial in output_partial_list.iter() {
+            if output_partial.len() != self.output_id.len() {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.output_id.len(),
+                    actual: output_partial.len(),
+                });
+            }
+        }
+        self.compile();
+        let calc_order: &Vec<usize> = self.calc_order.as_ref().unwrap();
+        let offset: usize = self.history.len() - step_count;
+        let mut recurrent_partial: Vec<f64> = vec![0.0; self.nodes.len()];
+        let mut input_partial_list: Vec<Vec<f64>> = vec![Vec::new(); step_count];
+        for t in (0..step_count).rev() {
+            let mut last_recurrent_partial: Vec<f64> = vec![0.0; self.nodes.len()];
+            for (node, &partial) in self.nodes.iter_mut().zip(recurrent_partial.iter()) {
+                node.partial = partial;
+            }
+            for (&id, &partial) in self.output_id.iter().zip(output_partial_list[t].iter()) {
+                self.nodes[id].partial += partial;
+            }
+            // Every node starts with the partial from the next step and the output.
+            for &id in calc_order.iter().rev() {
+                let node_step: &NodeStep = &self.history[offset + t][id];
+                self.nodes[id]
+                    .input_value
+                    .clone_from(&node_step.input_value);
+                self.nodes[id].sum = node_step.sum;
+                self.nodes[id].value = node_step.value;
+                self.nodes[id].accumulate_gradient();
+                for index in 0..self.nodes[id].input_count {
+                    let from_id: usize = self.nodes[id].input_id[index];
+                    let partial: f64 = self.nodes[id].get_partial(index);
+                    if self.nodes[id].input_recurrent[index] {
+                        last_recurrent_partial[from_id] += partial;
+                    } else {
+                        self.nodes[from_id].partial += partial;
+                    }
+                }
+            }
+            input_partial_list[t] = self
+                .input_id
+                .iter()
+                .map(|&id| self.nodes[id].partial)
+                .collect::<Vec<f64>>();
+            recurrent_partial = last_recurrent_partial;
+        }
+        // The partial through the recurrent pipes before the first recorded step is dropped.
+        if let Some(last_step) = self.history.back() {
+            for (node, node_step) in self.nodes.iter_mut().zip(last_step.iter()) {
+                node.input_value.clone_from(&node_step.input_value);
+                node.sum = node_step.sum;
+                node.value = node_step.value;
+            }
+        }
+        // Put the values of the current step back.
+        self.gradient_count += 1;
+        Ok(input_partial_list)
     }
-    pub fn set_output_layer(self: &mut Self, output_layer: usize) {
-        match self.layer_length.get(&output_layer) {
-            Some(layer_length) => {
-                self.output_id = (output_layer..output_layer + layer_length).collect::<Vec<usize>>()
+    pub fn fitting_sequence(
+        self: &mut Self,
+        input_list: &[Vec<f64>],
+        anticipated_list: &[Vec<f64>],
+        learning_rate: f64,
+    ) -> Result<()> {
+        // Run the sequence from a reset state, and fit with the gradient through time in one step.
+        if input_list.len() != anticipated_list.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: input_list.len(),
+                actual: anticipated_list.len(),
+            });
+        }
+        for (input, anticipated) in input_list.iter().zip(anticipated_list.iter()) {
+            if input.len() != self.input_id.len() {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.input_id.len(),
+                    actual: input.len(),
+                });
+            }
+            if anticipated.len() != self.output_id.len() {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: self.output_id.len(),
+                    actual: anticipated.len(),
+                });
             }
-            None => panic!("[{}]: Layer not found!", "set_output_layer"),
         }
+        self.reset_state();
+        let history_length: usize = self.history_length;
+        self.history_length = history_length.max(input_list.len());
+        let mut output_partial_list: Vec<Vec<f64>> = Vec::new();
+        for (input, anticipated) in input_list.iter().zip(anticipated_list.iter()) {
+            self.set_input(input.clone())?;
+            self.next()?;
+            output_partial_list.push(get_lost_partial(
+                self.loss_fn_enum,
+                &self.get_output(),
+                anticipated,
+            )?);
+        }
+        self.zero_grad();
+        self.accumulate_gradients_through_time(output_partial_list)?;
+        self.apply_gradients(learning_rate);
+        self.zero_grad();
+        self.set_history_length(history_length);
+        Ok(())
+    }
+    pub fn set_seed(self: &mut Self, seed: u64) {
+        // Reset the random number generator used by "connect_layer", the same seed gives the same weights.
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+    pub fn set_optimizer(self: &mut Self, optimizer_enum: OptimizerEnum) {
+        // Select the optimizer used by "fitting_batch", the state kept in nodes is reused if the optimizer changes.
+        self.optimizer_enum = optimizer_enum;
+    }
+    pub fn set_loss_function(self: &mut Self, loss_fn_enum: LossFunctionEnum) {
+        // Select the loss of the output nodes used by "fitting_batch".
+        self.loss_fn_enum = loss_fn_enum;
     }
     pub fn get_node(self: &mut Self, id: usize) -> &mut Node {
         // Return nth node in this network's node list.
+        // Connect nodes through the network builders, the execution plan does not notice the changes made on the node directly.
         &mut self.nodes[id]
     }
 }
@@ -621,7 +1684,327 @@ impl FlexibleNetwork {
 #[cfg(test)]
 mod tests {
     use super::Segment;
-    use super::{ActivationFunctionEnum, FlexibleNetwork};
+    use super::{ActivationFunction, ActivationFunctionEnum, FlexibleNetwork, GELU_MINIMUM_X};
+    use crate::multi_seg_network::error::NetworkError;
+    use crate::multi_seg_network::get_lost_partial;
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::loss_function::LossFunctionEnum;
+    use crate::multi_seg_network::optimizer::OptimizerEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const ACTIVATION_FN_LIST: [ActivationFunctionEnum; 14] = [
+        ActivationFunctionEnum::DoNothing,
+        ActivationFunctionEnum::Sigmoid,
+        ActivationFunctionEnum::Tanh,
+        ActivationFunctionEnum::ReLU,
+        ActivationFunctionEnum::LeakyReLU,
+        ActivationFunctionEnum::ELU,
+        ActivationFunctionEnum::SELU,
+        ActivationFunctionEnum::GELU,
+        ActivationFunctionEnum::Softplus,
+        ActivationFunctionEnum::Swish,
+        ActivationFunctionEnum::HardSigmoid,
+        ActivationFunctionEnum::Sine,
+        ActivationFunctionEnum::PReLU,
+        ActivationFunctionEnum::ScaledTanh,
+    ];
+
+    fn random_network(rng: &mut StdRng) -> FlexibleNetwork {
+        // Build a random acyclic graph, every node only takes input from the nodes before it,
+        // then add a few recurrent pipes between any two nodes.
+        let mut net = FlexibleNetwork::new();
+        let input_number: usize = rng.gen_range(1..4);
+        let input_layer: usize =
+            net.new_layer(input_number, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_number: usize = rng.gen_range(1..10);
+        for _ in 0..hidden_number {
+            let activation_fn_enum = ACTIVATION_FN_LIST[rng.gen_range(0..ACTIVATION_FN_LIST.len())];
+            let id: usize = net.new_node(rng.gen_range(-1.0..1.0), activation_fn_enum);
+            net.connect(rng.gen_range(0..input_number), id, rng.gen_range(-1.0..1.0))
+                .unwrap();
+            for from_id in 0..id {
+                if rng.gen_bool(0.3) {
+                    net.connect(from_id, id, rng.gen_range(-1.0..1.0)).unwrap();
+                }
+            }
+        }
+        let output_number: usize = rng.gen_range(1..4);
+        let output_layer: usize = net.new_layer(
+            output_number,
+            rng.gen_range(-1.0..1.0),
+            ActivationFunctionEnum::Sigmoid,
+        );
+        for id in output_layer..output_layer + output_number {
+            net.connect(
+                rng.gen_range(input_number..output_layer),
+                id,
+                rng.gen_range(-1.0..1.0),
+            )
+            .unwrap();
+        }
+        for id in 0..net.nodes.len() {
+            let activation_params: Vec<f64> = net.nodes[id]
+                .activation_params
+                .iter()
+                .map(|_| rng.gen_range(0.1..2.0))
+                .collect::<Vec<f64>>();
+            net.set_activation_parameters(id, activation_params)
+                .unwrap();
+        }
+        for _ in 0..rng.gen_range(0..3) {
+            let node_number: usize = net.nodes.len();
+            net.connect_recurrent(
+                rng.gen_range(0..node_number),
+                rng.gen_range(input_number..node_number),
+                rng.gen_range(-1.0..1.0),
+            )
+            .unwrap();
+        }
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
+        net
+    }
+
+    #[test]
+    fn test_activation_derivative_matches_finite_difference() {
+        const H: f64 = 1e-6;
+        for activation_fn_enum in ACTIVATION_FN_LIST {
+            let function = ActivationFunction::get_function(activation_fn_enum);
+            let derivative = ActivationFunction::get_derivative(activation_fn_enum);
+            let p: Vec<f64> = ActivationFunction::get_default_parameters(activation_fn_enum);
+            for x in [-4.2, -2.5, -0.7, -0.05, 0.3, 1.9, 3.6, 40.0] {
+                // Away from the kinks of ReLU, LeakyReLU, SELU and HardSigmoid.
+                let numerical_derivative: f64 =
+                    (function(x + H, &p) - function(x - H, &p)) / (2.0 * H);
+                assert!(
+                    (numerical_derivative - derivative(x, &p)).abs() < 1e-6,
+                    "{:?}'({}): {} != {}",
+                    activation_fn_enum,
+                    x,
+                    numerical_derivative,
+                    derivative(x, &p)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_activation_parameter_derivative_matches_finite_difference() {
+        const H: f64 = 1e-6;
+        let parameter_list: [(ActivationFunctionEnum, Vec<f64>); 2] = [
+            (ActivationFunctionEnum::PReLU, Vec::from([0.3])),
+            (ActivationFunctionEnum::ScaledTanh, Vec::from([1.4, 0.8])),
+        ];
+        for (activation_fn_enum, p) in parameter_list {
+            let function = ActivationFunction::get_function(activation_fn_enum);
+            let parameter_derivative =
+                ActivationFunction::get_parameter_derivative(activation_fn_enum);
+            assert!(ActivationFunction::is_trainable(activation_fn_enum));
+            for x in [-1.7, -0.2, 0.6, 2.3] {
+                for k in 0..p.len() {
+                    let mut p_plus: Vec<f64> = p.clone();
+                    p_plus[k] += H;
+                    let mut p_minus: Vec<f64> = p.clone();
+                    p_minus[k] -= H;
+                    let numerical_derivative: f64 =
+                        (function(x, &p_plus) - function(x, &p_minus)) / (2.0 * H);
+                    assert!(
+                        (numerical_derivative - parameter_derivative(x, &p)[k]).abs() < 1e-6,
+                        "{:?} p[{}] at {}",
+                        activation_fn_enum,
+                        k,
+                        x
+                    );
+                }
+            }
+        }
+        assert!(!ActivationFunction::is_trainable(
+            ActivationFunctionEnum::LeakyReLU
+        ));
+    }
+
+    #[test]
+    fn test_activation_inverse_undoes_the_function() {
+        for activation_fn_enum in ACTIVATION_FN_LIST {
+            let function = ActivationFunction::get_function(activation_fn_enum);
+            let inverse = ActivationFunction::get_inverse(activation_fn_enum);
+            let p: Vec<f64> = ActivationFunction::get_default_parameters(activation_fn_enum);
+            for x in [-1.2, -0.4, 0.3, 1.1] {
+                if (activation_fn_enum == ActivationFunctionEnum::ReLU && x <= 0.0)
+                    || (activation_fn_enum == ActivationFunctionEnum::GELU && x < GELU_MINIMUM_X)
+                {
+                    continue;
+                }
+                // Only the increasing side is inverted.
+                let y: f64 = function(x, &p);
+                assert!(
+                    (inverse(y, &p).unwrap() - x).abs() < 1e-9,
+                    "{:?}: inverse({}) = {} != {}",
+                    activation_fn_enum,
+                    y,
+                    inverse(y, &p).unwrap(),
+                    x
+                );
+            }
+        }
+        assert!(
+            ActivationFunction::get_inverse(ActivationFunctionEnum::Sigmoid)(1.5, &Vec::new())
+                .is_err()
+        );
+        assert!(
+            ActivationFunction::get_inverse(ActivationFunctionEnum::Swish)(-1.0, &Vec::new())
+                .is_err()
+        );
+        for activation_fn_enum in [ActivationFunctionEnum::GELU, ActivationFunctionEnum::Swish] {
+            for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                assert!(matches!(
+                    ActivationFunction::get_inverse(activation_fn_enum)(x, &Vec::new()),
+                    Err(NetworkError::OutOfDomain { .. })
+                ));
+            }
+        }
+        let leaky_relu_inverse = ActivationFunction::get_inverse(ActivationFunctionEnum::PReLU);
+        assert!(matches!(
+            leaky_relu_inverse(-0.5, &[0.0]),
+            Err(NetworkError::OutOfDomain { .. })
+        ));
+        assert_eq!(leaky_relu_inverse(0.0, &[0.0]).unwrap(), 0.0);
+        // A trained slope can reach 0, the negative values are not reachable then.
+    }
+
+    #[test]
+    fn test_prelu_slope_is_trained_and_exported() {
+        // y = x on the positive side and 0.5x on the negative side, only the slope can fit it.
+        let mut net = FlexibleNetwork::new();
+        let i_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let o_id: usize = net.new_node(0.0, ActivationFunctionEnum::PReLU);
+        net.connect(i_id, o_id, 1.0).unwrap();
+        net.set_input_id(Vec::from([i_id])).unwrap();
+        net.set_output_id(Vec::from([o_id])).unwrap();
+        assert_eq!(
+            net.set_activation_parameters(o_id, Vec::from([0.1, 0.2])),
+            Err(NetworkError::InputSizeMismatch {
+                expected: 1,
+                actual: 2
+            })
+        );
+
+        for _ in 0..2000 {
+            for x in [-2.0, -1.0, 1.0, 2.0] {
+                net.set_input(Vec::from([x])).unwrap();
+                net.next().unwrap();
+                let node = net.get_node(o_id);
+                node.anticipated_value = if x < 0.0 { 0.5 * x } else { x };
+                node.fitting(
+                    0.01,
+                    Some(LossFunctionEnum::MeanSquaredError),
+                    OptimizerEnum::default(),
+                );
+            }
+        }
+        assert!((net.nodes[o_id].activation_params[0] - 0.5).abs() < 1e-3);
+
+        let mut imported_net = FlexibleNetwork::new();
+        imported_net.import_data(net.export_data()).unwrap();
+        assert_eq!(
+            imported_net.nodes[o_id].activation_params,
+            net.nodes[o_id].activation_params
+        );
+    }
+
+    #[test]
+    fn test_gradient_check_of_random_networks() {
+        let mut rng = StdRng::seed_from_u64(20240113);
+        for _ in 0..20 {
+            let mut net = random_network(&mut rng);
+            let input_value: Vec<f64> = (0..net.input_id.len())
+                .map(|_| rng.gen_range(-2.0..2.0))
+                .collect::<Vec<f64>>();
+            let anticipated: Vec<f64> = (0..net.output_id.len())
+                .map(|_| rng.gen_range(0.0..1.0))
+                .collect::<Vec<f64>>();
+            net.set_input(input_value.clone()).unwrap();
+            net.next().unwrap();
+            // Give the recurrent pipes a state.
+
+            let report = net
+                .gradient_check(&input_value, &anticipated, 1e-6)
+                .unwrap();
+            assert!(report.get_worst_error() < 1e-4, "{:?}", report);
+            assert!(!report.node_error.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_export_import_export_is_identical() {
+        let mut rng = StdRng::seed_from_u64(20240108);
+        for _ in 0..20 {
+            let mut net = random_network(&mut rng);
+            let data: String = net.export_data();
+            let mut imported_net = FlexibleNetwork::new();
+            imported_net.import_data(data.clone()).unwrap();
+            assert_eq!(imported_net.export_data(), data);
+        }
+    }
+
+    #[test]
+    fn test_imported_network_gives_the_same_output() {
+        let mut rng = StdRng::seed_from_u64(20240109);
+        for _ in 0..20 {
+            let mut net = random_network(&mut rng);
+            let mut imported_net = FlexibleNetwork::new();
+            imported_net.import_data(net.export_data()).unwrap();
+            for _ in 0..5 {
+                let input_value: Vec<f64> = (0..net.input_id.len())
+                    .map(|_| rng.gen_range(-2.0..2.0))
+                    .collect::<Vec<f64>>();
+                net.set_input(input_value.clone()).unwrap();
+                net.next().unwrap();
+                imported_net.set_input(input_value).unwrap();
+                imported_net.next().unwrap();
+                assert_eq!(net.get_output(), imported_net.get_output());
+            }
+        }
+    }
+
+    #[test]
+    fn test_imported_network_can_be_trained() {
+        let test_data: [[f64; 3]; 3] = [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [1.5, 1.2, 2.7]];
+        let mut net = FlexibleNetwork::new();
+        let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = net.new_layer(5, 0.5, ActivationFunctionEnum::DoNothing);
+        let output_layer: usize = net.new_layer(1, 0.5, ActivationFunctionEnum::ReLU);
+        net.connect_layer(
+            input_layer,
+            hidden_layer,
+            InitializerEnum::Constant { value: 1.0 },
+        )
+        .unwrap();
+        net.connect_layer(
+            hidden_layer,
+            output_layer,
+            InitializerEnum::Constant { value: 1.0 },
+        )
+        .unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
+
+        let mut imported_net = FlexibleNetwork::new();
+        imported_net.import_data(net.export_data()).unwrap();
+        for _ in 1..=100 {
+            for test_pair in test_data {
+                for n in [&mut net, &mut imported_net] {
+                    n.fitting_batch(
+                        &[[Vec::from(&test_pair[0..=1]), Vec::from([test_pair[2]])]],
+                        0.001,
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        assert_eq!(net.export_data(), imported_net.export_data());
+    }
 
     #[test]
     fn test_node_fitting() {
@@ -629,25 +2012,29 @@ mod tests {
         let mut net = FlexibleNetwork::new();
         let i_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
         let o_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
-        net.connect(i_id, o_id, 1.0);
-        net.set_input_id(Vec::from([i_id]));
-        net.set_output_id(Vec::from([o_id]));
+        net.connect(i_id, o_id, 1.0).unwrap();
+        net.set_input_id(Vec::from([i_id])).unwrap();
+        net.set_output_id(Vec::from([o_id])).unwrap();
 
         for rate in [0.001, 0.0001] {
             for _ in 1..=10000 {
                 for test_pair in test_data {
-                    net.set_input(Vec::from([test_pair[0]]));
-                    net.next();
+                    net.set_input(Vec::from([test_pair[0]])).unwrap();
+                    net.next().unwrap();
                     let node = net.get_node(o_id);
                     node.anticipated_value = test_pair[1];
-                    node.fitting(rate, true);
+                    node.fitting(
+                        rate,
+                        Some(LossFunctionEnum::MeanSquaredError),
+                        OptimizerEnum::default(),
+                    );
                 }
             }
         }
 
         for test_pair in test_data {
-            net.set_input(Vec::from([test_pair[0]]));
-            net.next();
+            net.set_input(Vec::from([test_pair[0]])).unwrap();
+            net.next().unwrap();
             assert!((net.get_output()[0] - test_pair[1]).abs() < 1.0);
         }
     }
@@ -659,25 +2046,632 @@ mod tests {
         let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
         let hidden_layer: usize = net.new_layer(5, 0.0, ActivationFunctionEnum::DoNothing);
         let output_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::ReLU);
-        net.connect_layer(input_layer, hidden_layer, 1.0);
-        net.connect_layer(hidden_layer, output_layer, 1.0);
-        net.set_input_layer(input_layer);
-        net.set_output_layer(output_layer);
+        net.connect_layer(
+            input_layer,
+            hidden_layer,
+            InitializerEnum::Constant { value: 1.0 },
+        )
+        .unwrap();
+        net.connect_layer(
+            hidden_layer,
+            output_layer,
+            InitializerEnum::Constant { value: 1.0 },
+        )
+        .unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
 
         for rate in [0.001, 0.0001] {
             for _ in 1..=10000 {
                 for test_pair in test_data {
-                    net.set_input(Vec::from(&test_pair[0..=1]));
-                    net.next();
-                    net.fitting(Vec::from([test_pair[2]]), rate);
+                    net.fitting_batch(
+                        &[[Vec::from(&test_pair[0..=1]), Vec::from([test_pair[2]])]],
+                        rate,
+                    )
+                    .unwrap();
                 }
             }
         }
 
         for test_pair in test_data {
-            net.set_input(Vec::from(&test_pair[0..=1]));
-            net.next();
+            net.set_input(Vec::from(&test_pair[0..=1])).unwrap();
+            net.next().unwrap();
             assert!((net.get_output()[0] - test_pair[2]).abs() < 1.0);
         }
     }
+
+    #[test]
+    fn test_set_input_with_wrong_size_is_an_error() {
+        let mut net = FlexibleNetwork::new();
+        let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        net.set_input_layer(input_layer).unwrap();
+
+        assert_eq!(
+            net.set_input(Vec::from([1.0])),
+            Err(NetworkError::InputSizeMismatch {
+                expected: 2,
+                actual: 1
+            })
+        );
+        assert_eq!(
+            net.fitting_batch(&[[Vec::from([1.0, 1.0]), Vec::from([1.0])]], 0.1),
+            Err(NetworkError::InputSizeMismatch {
+                expected: 0,
+                actual: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_connect_layer_initializer_is_seeded_and_breaks_symmetry() {
+        fn build(seed: u64) -> FlexibleNetwork {
+            let mut net = FlexibleNetwork::new();
+            net.set_seed(seed);
+            let input_layer: usize = net.new_layer(3, 0.0, ActivationFunctionEnum::DoNothing);
+            let hidden_layer: usize = net.new_layer(4, 0.0, ActivationFunctionEnum::ReLU);
+            net.connect_layer(input_layer, hidden_layer, InitializerEnum::HeNormal)
+                .unwrap();
+            net
+        }
+        let net_a = build(42);
+        let net_b = build(42);
+        let net_c = build(43);
+        let w_list = |net: &FlexibleNetwork| -> Vec<Vec<f64>> {
+            (3..7).map(|id| net.nodes[id].input_w.clone()).collect()
+        };
+
+        assert_eq!(w_list(&net_a), w_list(&net_b));
+        assert_ne!(w_list(&net_a), w_list(&net_c));
+        let w_a: Vec<Vec<f64>> = w_list(&net_a);
+        for i in 0..w_a.len() {
+            for j in 0..i {
+                assert_ne!(w_a[i], w_a[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_follows_skip_connections_and_graph_changes() {
+        let mut net = FlexibleNetwork::new();
+        let input_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_id: usize = net.new_node(0.5, ActivationFunctionEnum::Tanh);
+        let output_id: usize = net.new_node(-0.2, ActivationFunctionEnum::Sigmoid);
+        net.connect(input_id, hidden_id, 0.7).unwrap();
+        net.connect(hidden_id, output_id, 1.3).unwrap();
+        net.connect(input_id, output_id, -0.4).unwrap();
+        // The output node takes the input node directly and through the hidden node.
+        net.set_input_id(Vec::from([input_id])).unwrap();
+        net.set_output_id(Vec::from([output_id])).unwrap();
+        let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+
+        net.set_input(Vec::from([0.9])).unwrap();
+        net.next().unwrap();
+        let hidden_value: f64 = (0.9 * 0.7 + 0.5_f64).tanh();
+        assert_eq!(
+            net.get_output(),
+            Vec::from([sigmoid(hidden_value * 1.3 + 0.9 * -0.4 - 0.2)])
+        );
+
+        let extra_id: usize = net.new_node(0.1, ActivationFunctionEnum::DoNothing);
+        net.connect(input_id, extra_id, 2.0).unwrap();
+        net.connect(extra_id, output_id, 0.5).unwrap();
+        net.next().unwrap();
+        assert_eq!(
+            net.get_output(),
+            Vec::from([sigmoid(
+                hidden_value * 1.3 + 0.9 * -0.4 + (0.9 * 2.0 + 0.1) * 0.5 - 0.2
+            )])
+        );
+    }
+
+    #[test]
+    fn test_recurrent_pipe_reads_the_last_step() {
+        let mut net = FlexibleNetwork::new();
+        let input_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let output_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        net.connect(input_id, hidden_id, 1.0).unwrap();
+        net.connect(hidden_id, hidden_id, 0.5).unwrap();
+        net.connect(hidden_id, output_id, 2.0).unwrap();
+        net.connect_recurrent(output_id, hidden_id, 0.1).unwrap();
+        // The output node is calculated after the hidden node, but feeds it with the value of the last step.
+        net.set_input_id(Vec::from([input_id])).unwrap();
+        net.set_output_id(Vec::from([output_id])).unwrap();
+        let run = |net: &mut FlexibleNetwork| -> Vec<f64> {
+            [1.0, 0.0, 0.0]
+                .iter()
+                .map(|&x| {
+                    net.set_input(Vec::from([x])).unwrap();
+                    net.next().unwrap();
+                    net.get_output()[0]
+                })
+                .collect::<Vec<f64>>()
+        };
+
+        let h_1: f64 = 1.0;
+        let h_2: f64 = 0.5 * h_1 + 0.1 * (2.0 * h_1);
+        let h_3: f64 = 0.5 * h_2 + 0.1 * (2.0 * h_2);
+        assert_eq!(run(&mut net), Vec::from([2.0 * h_1, 2.0 * h_2, 2.0 * h_3]));
+        net.reset_state();
+        assert_eq!(run(&mut net), Vec::from([2.0 * h_1, 2.0 * h_2, 2.0 * h_3]));
+        net.reset_state();
+        assert_eq!(
+            net.forward_batch(&[Vec::from([1.0]), Vec::from([0.0]), Vec::from([0.0])])
+                .unwrap(),
+            Vec::from([
+                Vec::from([2.0 * h_1]),
+                Vec::from([2.0 * h_2]),
+                Vec::from([2.0 * h_3])
+            ])
+        );
+        // The samples of a batch are steps in order.
+    }
+
+    #[test]
+    fn test_backpropagation_through_time_matches_finite_difference() {
+        const H: f64 = 1e-6;
+        let input_list: Vec<Vec<f64>> = Vec::from([
+            Vec::from([0.5, -0.3]),
+            Vec::from([0.1, 0.8]),
+            Vec::from([-0.7, 0.2]),
+            Vec::from([0.4, 0.4]),
+        ]);
+        let anticipated_list: Vec<Vec<f64>> = Vec::from([
+            Vec::from([0.2]),
+            Vec::from([0.9]),
+            Vec::from([0.1]),
+            Vec::from([0.6]),
+        ]);
+        let mut net = FlexibleNetwork::new();
+        net.set_seed(9);
+        let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = net.new_layer(3, 0.1, ActivationFunctionEnum::Tanh);
+        let output_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::Sigmoid);
+        net.connect_layer(input_layer, hidden_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.connect_layer_recurrent(hidden_layer, hidden_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.connect_layer(hidden_layer, output_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.connect_recurrent(output_layer, hidden_layer, 0.3)
+            .unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
+        net.set_history_length(input_list.len());
+        let sequence_lost = |net: &mut FlexibleNetwork| -> f64 {
+            net.reset_state();
+            let mut lost: f64 = 0.0;
+            for (input, anticipated) in input_list.iter().zip(anticipated_list.iter()) {
+                net.set_input(input.clone()).unwrap();
+                net.next().unwrap();
+                lost += (net.get_output()[0] - anticipated[0]).powi(2);
+            }
+            lost
+        };
+
+        sequence_lost(&mut net);
+        let output_partial_list: Vec<Vec<f64>> = net
+            .history
+            .iter()
+            .zip(anticipated_list.iter())
+            .map(|(step, anticipated)| {
+                Vec::from([2.0 * (step[output_layer].value - anticipated[0])])
+            })
+            .collect::<Vec<Vec<f64>>>();
+        net.zero_grad();
+        net.accumulate_gradients_through_time(output_partial_list)
+            .unwrap();
+        for id in hidden_layer..output_layer + 1 {
+            for index in 0..net.nodes[id].input_count {
+                let analytic_gradient: f64 = net.nodes[id].input_w_gradient[index];
+                net.nodes[id].input_w[index] += H;
+                let lost_plus: f64 = sequence_lost(&mut net);
+                net.nodes[id].input_w[index] -= 2.0 * H;
+                let lost_minus: f64 = sequence_lost(&mut net);
+                net.nodes[id].input_w[index] += H;
+                let numerical_gradient: f64 = (lost_plus - lost_minus) / (2.0 * H);
+                assert!(
+                    (analytic_gradient - numerical_gradient).abs() < 1e-6,
+                    "node {} input {}: {} != {}",
+                    id,
+                    index,
+                    analytic_gradient,
+                    numerical_gradient
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fitting_sequence_learns_to_remember_the_last_input() {
+        // The output should be the input of the last step, which needs the recurrent pipes.
+        let mut net = FlexibleNetwork::new();
+        net.set_seed(3);
+        let input_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = net.new_layer(4, 0.0, ActivationFunctionEnum::Tanh);
+        let output_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::DoNothing);
+        net.connect_layer(input_layer, hidden_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.connect_layer_recurrent(hidden_layer, hidden_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.connect_layer(hidden_layer, output_layer, InitializerEnum::XavierUniform)
+            .unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
+        net.set_optimizer(OptimizerEnum::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+        });
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut random_sequence = || -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+            let x: Vec<f64> = (0..6)
+                .map(|_| rng.gen_range(-0.5..0.5))
+                .collect::<Vec<f64>>();
+            let input_list: Vec<Vec<f64>> = x.iter().map(|&x| Vec::from([x])).collect();
+            let anticipated_list: Vec<Vec<f64>> = (0..x.len())
+                .map(|t| Vec::from([if t > 0 { x[t - 1] } else { 0.0 }]))
+                .collect();
+            (input_list, anticipated_list)
+        };
+        let (test_input_list, test_anticipated_list) = random_sequence();
+        let test_lost = |net: &mut FlexibleNetwork| -> f64 {
+            net.reset_state();
+            let mut lost: f64 = 0.0;
+            for (input, anticipated) in test_input_list.iter().zip(test_anticipated_list.iter()) {
+                net.set_input(input.clone()).unwrap();
+                net.next().unwrap();
+                lost += (net.get_output()[0] - anticipated[0]).powi(2);
+            }
+            lost
+        };
+
+        let lost_before: f64 = test_lost(&mut net);
+        for _ in 0..2000 {
+            let (input_list, anticipated_list) = random_sequence();
+            net.fitting_sequence(&input_list, &anticipated_list, 0.01)
+                .unwrap();
+        }
+        let lost_after: f64 = test_lost(&mut net);
+        assert!(
+            lost_after < lost_before * 0.1,
+            "{} -> {}",
+            lost_before,
+            lost_after
+        );
+        assert_eq!(net.history.len(), 0);
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut net = FlexibleNetwork::new();
+        let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::ReLU);
+        let output_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let constant_id: usize = net.new_node(1.0, ActivationFunctionEnum::DoNothing);
+        let unused_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        net.connect_layer(
+            input_layer,
+            hidden_layer,
+            InitializerEnum::Constant { value: 0.5 },
+        )
+        .unwrap();
+        net.connect(hidden_layer, output_id, 1.0).unwrap();
+        net.connect(constant_id, output_id, 1.0).unwrap();
+        net.connect(input_layer, unused_id, 1.0).unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_id(Vec::from([output_id])).unwrap();
+        let report = net.validate();
+        assert!(report.is_ok());
+        assert_eq!(report.unreachable_id, Vec::from([constant_id]));
+        assert_eq!(report.dead_id, Vec::from([hidden_layer + 1, unused_id]));
+
+        net.connect(output_id, hidden_layer, 1.0).unwrap();
+        net.connect(hidden_layer, input_layer, 1.0).unwrap();
+        net.connect_recurrent(output_id, hidden_layer + 1, 1.0)
+            .unwrap();
+        // The recurrent pipe closes a cycle as well, but it is expected.
+        net.layer_length.insert(hidden_layer - 1, 2);
+        net.set_output_id(Vec::from([output_id])).unwrap();
+        let report = net.validate();
+        assert!(!report.is_ok());
+        assert_eq!(report.fed_input_id, Vec::from([input_layer]));
+        assert_eq!(report.cycle_pipe.len(), 2);
+        // One through the input and the hidden node, one through the hidden and the output node.
+        assert_eq!(report.shape_mismatch.len(), 3);
+        // The inserted layer overlaps both of its neighbours, and the output id is repeated.
+        assert_eq!(report.dead_id, Vec::from([hidden_layer + 1, unused_id]));
+    }
+
+    #[test]
+    fn test_import_rejects_an_invalid_graph() {
+        let mut net = FlexibleNetwork::new();
+        let input_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        let output_id: usize = net.new_node(0.0, ActivationFunctionEnum::DoNothing);
+        net.connect(input_id, output_id, 1.0).unwrap();
+        net.connect(output_id, input_id, 1.0).unwrap();
+        net.set_input_id(Vec::from([input_id])).unwrap();
+        net.set_output_id(Vec::from([output_id])).unwrap();
+        let data: String = net.export_data();
+
+        let mut imported_net = FlexibleNetwork::new();
+        match imported_net.import_data(data) {
+            Err(NetworkError::MalformedData(message)) => {
+                assert!(message.contains("input nodes with sources [0]"));
+                assert!(message.contains("non-recurrent cycle pipes"));
+            }
+            _ => panic!("the invalid graph is imported"),
+        }
+        assert_eq!(imported_net.nodes.len(), 0);
+    }
+
+    #[test]
+    fn test_saved_models_are_valid() {
+        for path in ["model/net.json", "model/net001.json"] {
+            let data: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+            let mut net = FlexibleNetwork::new();
+            net.import_data(data["data"][0].as_str().unwrap().to_string())
+                .unwrap();
+            assert!(net.validate().is_ok(), "{}: {}", path, net.validate());
+        }
+    }
+
+    #[test]
+    fn test_builder_reports_unknown_layer_and_node() {
+        let mut net = FlexibleNetwork::new();
+        let layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+
+        assert_eq!(
+            net.connect(layer, 5, 1.0),
+            Err(NetworkError::NodeNotFound(5))
+        );
+        assert_eq!(
+            net.connect_layer(layer, 1, InitializerEnum::Constant { value: 1.0 }),
+            Err(NetworkError::LayerNotFound(1))
+        );
+        assert_eq!(net.set_output_layer(3), Err(NetworkError::LayerNotFound(3)));
+        assert_eq!(
+            net.set_input_id(Vec::from([0, 2])),
+            Err(NetworkError::NodeNotFound(2))
+        );
+    }
+
+    #[test]
+    fn test_import_malformed_data_is_an_error() {
+        let mut net = FlexibleNetwork::new();
+
+        assert!(matches!(
+            net.import_data(String::from("{\"ns\":")),
+            Err(NetworkError::MalformedData(_))
+        ));
+        assert_eq!(
+            net.import_data(String::from(
+                r#"{"ns":[{"id":0,"i_id":[3],"i_w":[1.0],"b":0.0,"a_fn":"DoNothing"}],"i_id":[],"o_id":[0],"l_len":{}}"#
+            )),
+            Err(NetworkError::NodeNotFound(3))
+        );
+        assert_eq!(
+            net.import_data(String::from(
+                r#"{"ns":[{"id":0,"i_id":[],"i_w":[],"b":0.0,"a_fn":"DoNothing"}],"i_id":[0],"o_id":[0],"l_len":{"0":2}}"#
+            )),
+            Err(NetworkError::LayerNotFound(0))
+        );
+    }
+
+    #[test]
+    fn test_optimizer_state_resumes_exactly_after_import() {
+        let test_data: [[f64; 3]; 3] = [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [1.5, 1.2, 2.7]];
+        let mut net = FlexibleNetwork::new();
+        let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = net.new_layer(3, 0.0, ActivationFunctionEnum::ReLU);
+        let output_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::DoNothing);
+        net.connect_layer(
+            input_layer,
+            hidden_layer,
+            InitializerEnum::Constant { value: 0.5 },
+        )
+        .unwrap();
+        net.connect_layer(
+            hidden_layer,
+            output_layer,
+            InitializerEnum::Constant { value: 0.5 },
+        )
+        .unwrap();
+        net.set_input_layer(input_layer).unwrap();
+        net.set_output_layer(output_layer).unwrap();
+        net.set_optimizer(OptimizerEnum::AdamW {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            weight_decay: 0.01,
+        });
+        let train = |net: &mut FlexibleNetwork, times: usize| {
+            for _ in 0..times {
+                for test_pair in test_data {
+                    net.fitting_batch(
+                        &[[Vec::from(&test_pair[0..=1]), Vec::from([test_pair[2]])]],
+                        0.01,
+                    )
+                    .unwrap();
+                }
+            }
+        };
+
+        train(&mut net, 500);
+        let mut imported_net = FlexibleNetwork::new();
+        imported_net.import_data(net.export_data()).unwrap();
+        train(&mut net, 500);
+        train(&mut imported_net, 500);
+
+        assert_eq!(net.export_data(), imported_net.export_data());
+        for test_pair in test_data {
+            net.set_input(Vec::from(&test_pair[0..=1])).unwrap();
+            net.next().unwrap();
+            assert!((net.get_output()[0] - test_pair[2]).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_fitting_batch_applies_one_averaged_step() {
+        let test_data: [[f64; 3]; 3] = [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [1.5, 1.2, 2.7]];
+        let build = || {
+            let mut net = FlexibleNetwork::new();
+            let input_layer: usize = net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+            let hidden_layer: usize = net.new_layer(3, 0.1, ActivationFunctionEnum::ReLU);
+            let output_layer: usize = net.new_layer(1, 0.0, ActivationFunctionEnum::DoNothing);
+            net.connect_layer(
+                input_layer,
+                hidden_layer,
+                InitializerEnum::Constant { value: 0.5 },
+            )
+            .unwrap();
+            net.connect_layer(
+                hidden_layer,
+                output_layer,
+                InitializerEnum::Constant { value: 0.5 },
+            )
+            .unwrap();
+            net.set_input_layer(input_layer).unwrap();
+            net.set_output_layer(output_layer).unwrap();
+            net
+        };
+        let data_pair =
+            |test_pair: [f64; 3]| [Vec::from(&test_pair[0..=1]), Vec::from([test_pair[2]])];
+
+        let mut net = build();
+        let mut batch_net = build();
+        net.fitting_batch(&[data_pair(test_data[0])], 0.01).unwrap();
+        batch_net
+            .fitting_batch(
+                &Vec::from([data_pair(test_data[0]), data_pair(test_data[0])]),
+                0.01,
+            )
+            .unwrap();
+        assert_eq!(net.export_data(), batch_net.export_data());
+        // The same sample twice in a batch is one step of that sample.
+
+        let data_pair_list: Vec<[Vec<f64>; 2]> = test_data.iter().map(|&t| data_pair(t)).collect();
+        for _ in 0..5000 {
+            batch_net.fitting_batch(&data_pair_list, 0.01).unwrap();
+        }
+        for test_pair in test_data {
+            batch_net.set_input(Vec::from(&test_pair[0..=1])).unwrap();
+            batch_net.next().unwrap();
+            assert!((batch_net.get_output()[0] - test_pair[2]).abs() < 0.1);
+        }
+    }
+
+    fn next_by_fetch_queue(net: &mut FlexibleNetwork) {
+        // The queue walk used by "next" before the execution plan is cached, kept as the baseline of the benchmark.
+        // Every call walks from the output nodes back to the input nodes, then calculates the fetched nodes in reverse.
+        let mut calc_planned: Vec<bool> = vec![false; net.nodes.len()];
+        let fetch_value = |net: &FlexibleNetwork, id: usize| -> Vec<[usize; 3]> {
+            (0..net.nodes[id].input_count)
+                .map(|index| [net.nodes[id].input_id[index], id, index])
+                .collect::<Vec<[usize; 3]>>()
+        };
+        // [from_id, to_id, to_index]
+        let mut queue_list: Vec<[usize; 3]> = Vec::new();
+        for &id in net.output_id.iter() {
+            queue_list.append(&mut fetch_value(net, id));
+        }
+        let mut still_queue_list: Vec<[usize; 3]> = Vec::new();
+        while !queue_list.is_empty() {
+            let mut new_queue_list: Vec<[usize; 3]> = Vec::new();
+            for [from_id, to_id, to_index] in queue_list {
+                if net.nodes[from_id].input_count == 0 || from_id == to_id {
+                    net.nodes[to_id].input_value[to_index] = net.nodes[from_id].value;
+                } else {
+                    still_queue_list.push([from_id, to_id, to_index]);
+                    if !calc_planned[from_id] {
+                        new_queue_list.append(&mut fetch_value(net, from_id));
+                        calc_planned[from_id] = true;
+                    }
+                }
+            }
+            queue_list = new_queue_list;
+        }
+        for &[from_id, to_id, to_index] in still_queue_list.iter().rev() {
+            let value: f64 = net.nodes[from_id].calc_value();
+            net.nodes[to_id].input_value[to_index] = value;
+        }
+        for id in net.output_id.clone() {
+            net.nodes[id].calc_value();
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_next_and_fitting_on_a_few_thousand_node_network() {
+        // cargo test --release bench_ -- --ignored --nocapture
+        const LAYER_NUMBER: usize = 30;
+        const LAYER_WIDTH: usize = 100;
+        const REPEAT: u32 = 10;
+        let mut net = FlexibleNetwork::new();
+        let layer_list: Vec<usize> = (0..LAYER_NUMBER)
+            .map(|i| {
+                let activation_fn_enum = if i == 0 {
+                    ActivationFunctionEnum::DoNothing
+                } else {
+                    ActivationFunctionEnum::Tanh
+                };
+                net.new_layer(LAYER_WIDTH, 0.0, activation_fn_enum)
+            })
+            .collect::<Vec<usize>>();
+        for i in 0..LAYER_NUMBER - 1 {
+            net.connect_layer(
+                layer_list[i],
+                layer_list[i + 1],
+                InitializerEnum::XavierUniform,
+            )
+            .unwrap();
+        }
+        net.set_input_layer(layer_list[0]).unwrap();
+        net.set_output_layer(layer_list[LAYER_NUMBER - 1]).unwrap();
+        let input_value: Vec<f64> = (0..LAYER_WIDTH)
+            .map(|i| i as f64 / LAYER_WIDTH as f64)
+            .collect::<Vec<f64>>();
+        let anticipated: Vec<f64> = vec![0.5; LAYER_WIDTH];
+
+        let start = std::time::Instant::now();
+        for _ in 0..REPEAT {
+            net.set_input(input_value.clone()).unwrap();
+            next_by_fetch_queue(&mut net);
+        }
+        let queue_next_time = start.elapsed() / REPEAT;
+        let queue_output: Vec<f64> = net.get_output();
+        let start = std::time::Instant::now();
+        for _ in 0..REPEAT {
+            net.set_input(input_value.clone()).unwrap();
+            net.next().unwrap();
+        }
+        let next_time = start.elapsed() / REPEAT;
+        assert_eq!(net.get_output(), queue_output);
+
+        let output_partial: Vec<f64> =
+            get_lost_partial(net.get_loss_function(), &net.get_output(), &anticipated).unwrap();
+        let start = std::time::Instant::now();
+        for _ in 0..REPEAT {
+            net.calc_order = None;
+            net.backward(output_partial.clone(), 0.001).unwrap();
+        }
+        let uncached_fitting_time = start.elapsed() / REPEAT;
+        // The order was worked out again for every fitting before it is cached.
+        let start = std::time::Instant::now();
+        for _ in 0..REPEAT {
+            net.backward(output_partial.clone(), 0.001).unwrap();
+        }
+        let fitting_time = start.elapsed() / REPEAT;
+        println!(
+            "{} nodes: next {:?} -> {:?} ({:.1}x), fitting {:?} -> {:?} ({:.1}x)",
+            LAYER_NUMBER * LAYER_WIDTH,
+            queue_next_time,
+            next_time,
+            queue_next_time.as_secs_f64() / next_time.as_secs_f64(),
+            uncached_fitting_time,
+            fitting_time,
+            uncached_fitting_time.as_secs_f64() / fitting_time.as_secs_f64()
+        );
+    }
 }
@@ -5,79 +5,468 @@
 
 use serde::{Deserialize, Serialize};
 // Make the customize struct be able to json stringify
+use serde_json::{Map, Value};
+// Old data is upgraded as a json tree by the migrations.
 
-use crate::multi_seg_network::{Segment, SegmentTypes};
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::schema::{check_required, migrate, Migration};
+use crate::multi_seg_network::Segment;
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+const NORM_EPSILON: f64 = 1e-12;
+// Smallest norm divided by "l2_normalize", so a zero input does not give NaN.
+
+pub type BackwardFunction = fn(&[f64], &[f64], Vec<f64>, &[f64]) -> Vec<f64>;
+// Take the input, the output, $\frac{\partial lost}{\partial output}$ and the parameters, return $\frac{\partial lost}{\partial input}$.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum FunctionSegmentFunctionEnum {
     DoNothing,
     Fraction,
     SoftMax,
+    LogSoftMax,
+    // $\ln(SoftMax(input))$, pairs with a loss which takes log probabilities.
+    TemperatureSoftMax { temperature: f64 },
+    // $SoftMax(\frac{input}{temperature})$, a higher temperature gives a flatter distribution.
+    L2Normalize,
+    // Divide the input by its L2 norm.
+    Clamp { min: f64, max: f64 },
+    // The partial does not pass the clamped values.
+    ArgMaxOneHot,
+    // One-hot of the max value, the partial passes straight through as if it were "DoNothing".
+    Scale { factor: f64 },
+    // Multiply every value by the factor.
 }
 pub struct FunctionSegment {
-    function: fn(Vec<f64>) -> Vec<f64>,
+    function: fn(&[f64], &[f64]) -> Vec<f64>,
+    backward_function: BackwardFunction,
     function_enum: FunctionSegmentFunctionEnum,
+    function_params: Vec<f64>,
+    // Parameters of the function, taken from the fields of "function_enum".
     input_value: Vec<f64>,
     output_value: Vec<f64>,
 }
 #[derive(Serialize, Deserialize)]
 struct FunctionSegmentData {
+    #[serde(default)]
+    v: u32,
     fs_fn: FunctionSegmentFunctionEnum,
 }
-fn do_nothing(input: Vec<f64>) -> Vec<f64> {
-    input
+const FUNCTION_SEGMENT_MIGRATION_LIST: [Migration; 1] = [migrate_function_segment_v0];
+// "FunctionSegmentData" of version i is upgraded by "FUNCTION_SEGMENT_MIGRATION_LIST[i]", see "schema::migrate".
+fn migrate_function_segment_v0(data: Map<String, Value>) -> Result<Map<String, Value>> {
+    // The function names of version 0 are kept.
+    check_required(&data, &["fs_fn"])?;
+    Ok(data)
+}
+fn do_nothing(input: &[f64], _p: &[f64]) -> Vec<f64> {
+    input.to_vec()
 }
-fn fraction(input: Vec<f64>) -> Vec<f64> {
+fn fraction(input: &[f64], _p: &[f64]) -> Vec<f64> {
     let sum: f64 = input.iter().sum();
     input.iter().map(|n| n / sum).collect::<Vec<f64>>()
 }
-fn soft_max(input: Vec<f64>) -> Vec<f64> {
-    let natural_exponential_input: Vec<f64> = input.iter().map(|&n| std::f64::consts::E.powf(n)).collect::<Vec<f64>>();
-    fraction(natural_exponential_input)
+fn soft_max(input: &[f64], p: &[f64]) -> Vec<f64> {
+    // Subtract the max first, so the exponential does not overflow.
+    let max: f64 = input.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let natural_exponential_input: Vec<f64> =
+        input.iter().map(|&n| (n - max).exp()).collect::<Vec<f64>>();
+    fraction(&natural_exponential_input, p)
+}
+fn log_soft_max(input: &[f64], _p: &[f64]) -> Vec<f64> {
+    // $input_i - max - \ln{\sum{e^{input_j - max}}}$
+    let max: f64 = input.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let log_sum: f64 = input.iter().map(|&n| (n - max).exp()).sum::<f64>().ln();
+    input
+        .iter()
+        .map(|n| n - max - log_sum)
+        .collect::<Vec<f64>>()
+}
+fn temperature_soft_max(input: &[f64], p: &[f64]) -> Vec<f64> {
+    soft_max(&input.iter().map(|n| n / p[0]).collect::<Vec<f64>>(), p)
+}
+fn l2_normalize(input: &[f64], _p: &[f64]) -> Vec<f64> {
+    let norm: f64 = input.iter().map(|n| n.powi(2)).sum::<f64>().sqrt();
+    input
+        .iter()
+        .map(|n| n / norm.max(NORM_EPSILON))
+        .collect::<Vec<f64>>()
+}
+fn clamp(input: &[f64], p: &[f64]) -> Vec<f64> {
+    input
+        .iter()
+        .map(|n| n.max(p[0]).min(p[1]))
+        .collect::<Vec<f64>>()
+}
+fn arg_max_one_hot(input: &[f64], _p: &[f64]) -> Vec<f64> {
+    // The first one wins if there are several max values.
+    let mut max_index: usize = 0;
+    for i in 0..input.len() {
+        if input[i] > input[max_index] {
+            max_index = i;
+        }
+    }
+    (0..input.len())
+        .map(|i| if i == max_index { 1.0 } else { 0.0 })
+        .collect::<Vec<f64>>()
+}
+fn scale(input: &[f64], p: &[f64]) -> Vec<f64> {
+    input.iter().map(|n| n * p[0]).collect::<Vec<f64>>()
+}
+fn do_nothing_backward(_input: &[f64], _output: &[f64], partial: Vec<f64>, _p: &[f64]) -> Vec<f64> {
+    partial
+}
+fn fraction_backward(input: &[f64], output: &[f64], partial: Vec<f64>, _p: &[f64]) -> Vec<f64> {
+    // $\frac{\partial output_i}{\partial input_j} = \frac{\delta_{ij} - output_i}{sum}$
+    let sum: f64 = input.iter().sum();
+    let weighted_partial: f64 = partial.iter().zip(output.iter()).map(|(p, o)| p * o).sum();
+    partial
+        .iter()
+        .map(|p| (p - weighted_partial) / sum)
+        .collect::<Vec<f64>>()
+}
+fn soft_max_backward(_input: &[f64], output: &[f64], partial: Vec<f64>, _p: &[f64]) -> Vec<f64> {
+    // $\frac{\partial output_i}{\partial input_j} = output_i \times (\delta_{ij} - output_j)$
+    let weighted_partial: f64 = partial.iter().zip(output.iter()).map(|(p, o)| p * o).sum();
+    partial
+        .iter()
+        .zip(output.iter())
+        .map(|(p, o)| o * (p - weighted_partial))
+        .collect::<Vec<f64>>()
+}
+fn log_soft_max_backward(
+    _input: &[f64],
+    output: &[f64],
+    partial: Vec<f64>,
+    _p: &[f64],
+) -> Vec<f64> {
+    // $\frac{\partial output_i}{\partial input_j} = \delta_{ij} - e^{output_j}$
+    let partial_sum: f64 = partial.iter().sum();
+    partial
+        .iter()
+        .zip(output.iter())
+        .map(|(p, o)| p - o.exp() * partial_sum)
+        .collect::<Vec<f64>>()
+}
+fn temperature_soft_max_backward(
+    input: &[f64],
+    output: &[f64],
+    partial: Vec<f64>,
+    p: &[f64],
+) -> Vec<f64> {
+    soft_max_backward(input, output, partial, p)
+        .iter()
+        .map(|partial| partial / p[0])
+        .collect::<Vec<f64>>()
+}
+fn l2_normalize_backward(input: &[f64], output: &[f64], partial: Vec<f64>, _p: &[f64]) -> Vec<f64> {
+    // $\frac{\partial output_i}{\partial input_j} = \frac{\delta_{ij} - output_i \times output_j}{norm}$
+    let norm: f64 = input.iter().map(|n| n.powi(2)).sum::<f64>().sqrt();
+    if norm < NORM_EPSILON {
+        return partial
+            .iter()
+            .map(|p| p / NORM_EPSILON)
+            .collect::<Vec<f64>>();
+    }
+    let weighted_partial: f64 = partial.iter().zip(output.iter()).map(|(p, o)| p * o).sum();
+    partial
+        .iter()
+        .zip(output.iter())
+        .map(|(p, o)| (p - o * weighted_partial) / norm)
+        .collect::<Vec<f64>>()
+}
+fn clamp_backward(input: &[f64], _output: &[f64], partial: Vec<f64>, p: &[f64]) -> Vec<f64> {
+    partial
+        .iter()
+        .zip(input.iter())
+        .map(|(partial, &n)| if p[0] < n && n < p[1] { *partial } else { 0.0 })
+        .collect::<Vec<f64>>()
+}
+fn scale_backward(_input: &[f64], _output: &[f64], partial: Vec<f64>, p: &[f64]) -> Vec<f64> {
+    partial
+        .iter()
+        .map(|partial| partial * p[0])
+        .collect::<Vec<f64>>()
 }
 #[allow(dead_code)]
 impl Segment for FunctionSegment {
     fn new() -> Self {
         Self {
             function: do_nothing,
+            backward_function: do_nothing_backward,
             function_enum: FunctionSegmentFunctionEnum::DoNothing,
+            function_params: Vec::new(),
             input_value: Vec::new(),
             output_value: Vec::new(),
         }
     }
-    fn get_type(self: &Self) -> SegmentTypes {
-        SegmentTypes::FunctionSegment
+    fn get_type(self: &Self) -> String {
+        String::from("FunctionSegment")
     }
-    fn set_input(self: &mut Self, input: Vec<f64>) {
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
         self.input_value = input;
+        Ok(())
     }
     fn get_output(self: &mut Self) -> Vec<f64> {
         self.output_value.clone()
     }
     fn export_data(self: &mut Self) -> String {
         let mut data = FunctionSegmentData {
+            v: FUNCTION_SEGMENT_MIGRATION_LIST.len() as u32,
             fs_fn: self.function_enum,
         };
         serde_json::to_string(&data).unwrap()
     }
-    fn import_data(self: &mut Self, data: String) {
-        let data: FunctionSegmentData = serde_json::from_str(&data.as_str()).unwrap();
-        self.set_function(data.fs_fn);
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: FunctionSegmentData =
+            serde_json::from_value(migrate(&data, &FUNCTION_SEGMENT_MIGRATION_LIST)?)?;
+        self.set_function(data.fs_fn)
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        self.output_value = (self.function)(&self.input_value, &self.function_params);
+        Ok(())
     }
-    fn next(self: &mut Self) {
-        self.output_value = (self.function)(self.input_value.clone());
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Run the function on every input in place, only the last one is kept for "accumulate_gradients".
+        let output_batch: Vec<Vec<f64>> = input_batch
+            .iter()
+            .map(|input| (self.function)(input, &self.function_params))
+            .collect::<Vec<Vec<f64>>>();
+        if let (Some(input), Some(output)) = (input_batch.last(), output_batch.last()) {
+            self.input_value = input.clone();
+            self.output_value = output.clone();
+        }
+        Ok(output_batch)
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_value.len(),
+                actual: output_partial.len(),
+            });
+        }
+        Ok((self.backward_function)(
+            &self.input_value,
+            &self.output_value,
+            output_partial,
+            &self.function_params,
+        ))
     }
 }
 impl FunctionSegment {
-    fn get_function(function_enum: FunctionSegmentFunctionEnum) -> fn(Vec<f64>) -> Vec<f64> {
+    fn get_function(function_enum: FunctionSegmentFunctionEnum) -> fn(&[f64], &[f64]) -> Vec<f64> {
         match function_enum {
             FunctionSegmentFunctionEnum::DoNothing => do_nothing,
             FunctionSegmentFunctionEnum::Fraction => fraction,
             FunctionSegmentFunctionEnum::SoftMax => soft_max,
+            FunctionSegmentFunctionEnum::LogSoftMax => log_soft_max,
+            FunctionSegmentFunctionEnum::TemperatureSoftMax { .. } => temperature_soft_max,
+            FunctionSegmentFunctionEnum::L2Normalize => l2_normalize,
+            FunctionSegmentFunctionEnum::Clamp { .. } => clamp,
+            FunctionSegmentFunctionEnum::ArgMaxOneHot => arg_max_one_hot,
+            FunctionSegmentFunctionEnum::Scale { .. } => scale,
         }
     }
-    pub fn set_function(self: &mut Self, function_enum: FunctionSegmentFunctionEnum) {
+    fn get_backward(function_enum: FunctionSegmentFunctionEnum) -> BackwardFunction {
+        match function_enum {
+            FunctionSegmentFunctionEnum::DoNothing => do_nothing_backward,
+            FunctionSegmentFunctionEnum::Fraction => fraction_backward,
+            FunctionSegmentFunctionEnum::SoftMax => soft_max_backward,
+            FunctionSegmentFunctionEnum::LogSoftMax => log_soft_max_backward,
+            FunctionSegmentFunctionEnum::TemperatureSoftMax { .. } => temperature_soft_max_backward,
+            FunctionSegmentFunctionEnum::L2Normalize => l2_normalize_backward,
+            FunctionSegmentFunctionEnum::Clamp { .. } => clamp_backward,
+            FunctionSegmentFunctionEnum::ArgMaxOneHot => do_nothing_backward,
+            FunctionSegmentFunctionEnum::Scale { .. } => scale_backward,
+        }
+    }
+    fn get_parameters(function_enum: FunctionSegmentFunctionEnum) -> Vec<f64> {
+        match function_enum {
+            FunctionSegmentFunctionEnum::TemperatureSoftMax { temperature } => {
+                Vec::from([temperature])
+            }
+            FunctionSegmentFunctionEnum::Clamp { min, max } => Vec::from([min, max]),
+            FunctionSegmentFunctionEnum::Scale { factor } => Vec::from([factor]),
+            _ => Vec::new(),
+        }
+    }
+    fn check_function(function_enum: FunctionSegmentFunctionEnum) -> Result<()> {
+        // Reject the parameters which make the output NaN.
+        match function_enum {
+            FunctionSegmentFunctionEnum::TemperatureSoftMax { temperature }
+                if !(temperature > 0.0 && temperature.is_finite()) =>
+            {
+                Err(NetworkError::OutOfDomain {
+                    x: temperature,
+                    min: f64::MIN_POSITIVE,
+                    max: f64::MAX,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn set_function(self: &mut Self, function_enum: FunctionSegmentFunctionEnum) -> Result<()> {
+        FunctionSegment::check_function(function_enum)?;
         self.function_enum = function_enum;
         self.function = Self::get_function(self.function_enum);
+        self.backward_function = Self::get_backward(self.function_enum);
+        self.function_params = Self::get_parameters(self.function_enum);
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionSegment, FunctionSegmentFunctionEnum};
+    use crate::multi_seg_network::error::NetworkError;
+    use crate::multi_seg_network::gradient_check::gradient_check;
+    use crate::multi_seg_network::Segment;
+
+    #[test]
+    fn test_backward_matches_finite_difference() {
+        let function_list: [FunctionSegmentFunctionEnum; 8] = [
+            FunctionSegmentFunctionEnum::DoNothing,
+            FunctionSegmentFunctionEnum::Fraction,
+            FunctionSegmentFunctionEnum::SoftMax,
+            FunctionSegmentFunctionEnum::LogSoftMax,
+            FunctionSegmentFunctionEnum::TemperatureSoftMax { temperature: 2.5 },
+            FunctionSegmentFunctionEnum::L2Normalize,
+            FunctionSegmentFunctionEnum::Clamp {
+                min: -1.0,
+                max: 1.5,
+            },
+            FunctionSegmentFunctionEnum::Scale { factor: -0.5 },
+        ];
+        for function_enum in function_list {
+            let mut function_segment: FunctionSegment = FunctionSegment::new();
+            function_segment.set_function(function_enum).unwrap();
+            let report = gradient_check(
+                &mut function_segment,
+                &Vec::from([0.3, -1.2, 2.0, 0.8]),
+                &Vec::from([0.5, -0.5, 1.0, 0.0]),
+                1e-6,
+            )
+            .unwrap();
+            assert!(
+                report.get_worst_error() < 1e-6,
+                "{:?}: {:?}",
+                function_enum,
+                report
+            );
+        }
+    }
+
+    #[test]
+    fn test_functions_with_large_or_special_input() {
+        let mut function_segment: FunctionSegment = FunctionSegment::new();
+        let mut output = |function_enum: FunctionSegmentFunctionEnum, input: [f64; 3]| {
+            function_segment.set_function(function_enum).unwrap();
+            function_segment.set_input(Vec::from(input)).unwrap();
+            function_segment.next().unwrap();
+            function_segment.get_output()
+        };
+
+        let soft_max_output: Vec<f64> =
+            output(FunctionSegmentFunctionEnum::SoftMax, [1000.0, 0.0, 1000.0]);
+        assert_eq!(soft_max_output, Vec::from([0.5, 0.0, 0.5]));
+        let log_soft_max_output: Vec<f64> = output(
+            FunctionSegmentFunctionEnum::LogSoftMax,
+            [1000.0, 0.0, -1000.0],
+        );
+        assert!(log_soft_max_output[0].abs() < 1e-12);
+        assert!((log_soft_max_output[2] + 2000.0).abs() < 1e-9);
+        let flat_output: Vec<f64> = output(
+            FunctionSegmentFunctionEnum::TemperatureSoftMax {
+                temperature: 1000.0,
+            },
+            [1.0, 0.0, -1.0],
+        );
+        assert!(flat_output.iter().all(|o| (o - 1.0 / 3.0).abs() < 1e-3));
+        assert_eq!(
+            output(FunctionSegmentFunctionEnum::L2Normalize, [3.0, 0.0, -4.0]),
+            Vec::from([0.6, 0.0, -0.8])
+        );
+        assert_eq!(
+            output(FunctionSegmentFunctionEnum::L2Normalize, [0.0, 0.0, 0.0]),
+            Vec::from([0.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            output(
+                FunctionSegmentFunctionEnum::Clamp { min: 0.0, max: 1.0 },
+                [-2.0, 0.5, 2.0]
+            ),
+            Vec::from([0.0, 0.5, 1.0])
+        );
+        assert_eq!(
+            output(FunctionSegmentFunctionEnum::ArgMaxOneHot, [0.2, 0.7, 0.7]),
+            Vec::from([0.0, 1.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_arg_max_passes_the_partial_straight_through() {
+        let mut function_segment: FunctionSegment = FunctionSegment::new();
+        function_segment
+            .set_function(FunctionSegmentFunctionEnum::ArgMaxOneHot)
+            .unwrap();
+        function_segment
+            .set_input(Vec::from([0.2, 0.9, 0.4]))
+            .unwrap();
+        function_segment.next().unwrap();
+
+        assert_eq!(
+            function_segment
+                .accumulate_gradients(Vec::from([1.0, -2.0, 3.0]))
+                .unwrap(),
+            Vec::from([1.0, -2.0, 3.0])
+        );
+    }
+
+    #[test]
+    fn test_function_parameters_are_exported() {
+        let mut function_segment: FunctionSegment = FunctionSegment::new();
+        function_segment
+            .set_function(FunctionSegmentFunctionEnum::Clamp {
+                min: -0.5,
+                max: 0.5,
+            })
+            .unwrap();
+        let data: String = function_segment.export_data();
+
+        let mut imported_segment: FunctionSegment = FunctionSegment::new();
+        imported_segment.import_data(data.clone()).unwrap();
+        imported_segment
+            .set_input(Vec::from([-1.0, 0.25, 1.0]))
+            .unwrap();
+        imported_segment.next().unwrap();
+
+        assert_eq!(imported_segment.get_output(), Vec::from([-0.5, 0.25, 0.5]));
+        assert_eq!(imported_segment.export_data(), data);
+        imported_segment
+            .import_data(String::from(r#"{"fs_fn":"SoftMax"}"#))
+            .unwrap();
+        // The data saved before the functions have parameters.
+    }
+
+    #[test]
+    fn test_zero_temperature_is_rejected() {
+        let mut function_segment: FunctionSegment = FunctionSegment::new();
+        for temperature in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                function_segment
+                    .set_function(FunctionSegmentFunctionEnum::TemperatureSoftMax { temperature }),
+                Err(NetworkError::OutOfDomain { .. })
+            ));
+        }
+        assert!(matches!(
+            function_segment.import_data(String::from(
+                r#"{"v":1,"fs_fn":{"TemperatureSoftMax":{"temperature":0.0}}}"#
+            )),
+            Err(NetworkError::OutOfDomain { .. })
+        ));
+        assert_eq!(
+            function_segment.export_data(),
+            FunctionSegment::new().export_data()
+        );
+        // The rejected function is not set.
+    }
+}
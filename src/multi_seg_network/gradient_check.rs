@@ -0,0 +1,153 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/gradient_check.rs
+ */
+
+use std::collections::BTreeMap;
+// BTreeMap Type, list the nodes in order
+
+use crate::multi_seg_network::error::Result;
+use crate::multi_seg_network::loss_function::LossFunction;
+use crate::multi_seg_network::{get_lost_partial, Segment};
+
+const RELATIVE_ERROR_FLOOR: f64 = 1e-6;
+// Below the floor the gradients are treated as zero, so the rounding noise of tiny gradients is not reported.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GradientCheckReport {
+    // Relative error between the numerical gradient and the gradient of the backward pass.
+    pub node_error: BTreeMap<usize, f64>,
+    // Worst error of the weights, bias and trainable activation parameters of every node with input sources.
+    pub input_error: Vec<f64>,
+    // Error of $\frac{\partial lost}{\partial input}$ returned by "accumulate_gradients".
+    pub parameter_error: Vec<f64>,
+    // Error of every value of "Segment::gradients", in the order of "Segment::parameters".
+}
+#[allow(dead_code)]
+impl GradientCheckReport {
+    pub fn get_worst_error(self: &Self) -> f64 {
+        self.node_error
+            .values()
+            .chain(self.input_error.iter())
+            .chain(self.parameter_error.iter())
+            .cloned()
+            .fold(0.0, f64::max)
+    }
+    pub fn get_worst_node(self: &Self) -> Option<(usize, f64)> {
+        // Return the id and the error of the node with the worst error.
+        self.node_error
+            .iter()
+            .map(|(&id, &error)| (id, error))
+            .fold(None, |worst, (id, error)| match worst {
+                Some((_, worst_error)) if worst_error >= error => worst,
+                _ => Some((id, error)),
+            })
+    }
+}
+pub fn relative_error(analytic_gradient: f64, numerical_gradient: f64) -> f64 {
+    // $\frac{|a - n|}{max(|a| + |n|, floor)}$
+    (analytic_gradient - numerical_gradient).abs()
+        / (analytic_gradient.abs() + numerical_gradient.abs()).max(RELATIVE_ERROR_FLOOR)
+}
+pub fn get_lost(segment: &mut dyn Segment, input: &[f64], anticipated_data: &[f64]) -> Result<f64> {
+    // Run one step, and return the lost of the output with the loss function of the segment.
+    segment.set_input(input.to_vec())?;
+    segment.next()?;
+    let output: Vec<f64> = segment.get_output();
+    get_lost_partial(segment.get_loss_function(), &output, anticipated_data)?;
+    // Check the size of the anticipated data.
+    Ok(LossFunction::get_function(segment.get_loss_function())(
+        &output,
+        anticipated_data,
+    ))
+}
+#[allow(dead_code)]
+pub fn gradient_check(
+    segment: &mut dyn Segment,
+    input: &[f64],
+    anticipated_data: &[f64],
+    epsilon: f64,
+) -> Result<GradientCheckReport> {
+    // Compare $\frac{\partial lost}{\partial input}$ and the parameter gradients of the backward pass with central differences of the lost.
+    // The accumulated gradients of the segment are cleared, and a recurrent segment should be reset before.
+    segment.zero_grad();
+    get_lost(segment, input, anticipated_data)?;
+    let output_partial: Vec<f64> = get_lost_partial(
+        segment.get_loss_function(),
+        &segment.get_output(),
+        anticipated_data,
+    )?;
+    let input_partial: Vec<f64> = segment.accumulate_gradients(output_partial)?;
+    let parameter_gradient: Vec<f64> = segment.gradients();
+    segment.zero_grad();
+
+    let mut report: GradientCheckReport = GradientCheckReport::default();
+    for i in 0..input.len() {
+        let mut input_plus: Vec<f64> = input.to_vec();
+        input_plus[i] += epsilon;
+        let mut input_minus: Vec<f64> = input.to_vec();
+        input_minus[i] -= epsilon;
+        let numerical_partial: f64 = (get_lost(segment, &input_plus, anticipated_data)?
+            - get_lost(segment, &input_minus, anticipated_data)?)
+            / (2.0 * epsilon);
+        report
+            .input_error
+            .push(relative_error(input_partial[i], numerical_partial));
+    }
+    let parameters: Vec<f64> = segment.parameters();
+    for i in 0..parameters.len() {
+        let mut parameters_plus: Vec<f64> = parameters.clone();
+        parameters_plus[i] += epsilon;
+        let mut parameters_minus: Vec<f64> = parameters.clone();
+        parameters_minus[i] -= epsilon;
+        segment.set_parameters(&parameters_plus)?;
+        let lost_plus: f64 = get_lost(segment, input, anticipated_data)?;
+        segment.set_parameters(&parameters_minus)?;
+        let lost_minus: f64 = get_lost(segment, input, anticipated_data)?;
+        report.parameter_error.push(relative_error(
+            parameter_gradient[i],
+            (lost_plus - lost_minus) / (2.0 * epsilon),
+        ));
+    }
+    segment.set_parameters(&parameters)?;
+    get_lost(segment, input, anticipated_data)?;
+    // Leave the segment with the parameters and the values of the given input.
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gradient_check, relative_error};
+    use crate::multi_seg_network::function_segment::{
+        FunctionSegment, FunctionSegmentFunctionEnum,
+    };
+    use crate::multi_seg_network::Segment;
+
+    #[test]
+    fn test_relative_error_ignores_the_noise_of_tiny_gradients() {
+        assert_eq!(relative_error(2.0, 2.0), 0.0);
+        assert!((relative_error(1.0, 3.0) - 0.5).abs() < 1e-12);
+        assert!(relative_error(1e-12, -1e-12) < 1e-5);
+    }
+
+    #[test]
+    fn test_gradient_check_of_a_function_segment() {
+        let mut soft_max: FunctionSegment = FunctionSegment::new();
+        soft_max
+            .set_function(FunctionSegmentFunctionEnum::SoftMax)
+            .unwrap();
+
+        let report = gradient_check(
+            &mut soft_max,
+            &Vec::from([0.3, -1.2, 2.0]),
+            &Vec::from([0.0, 1.0, 0.0]),
+            1e-6,
+        )
+        .unwrap();
+
+        assert_eq!(report.input_error.len(), 3);
+        assert!(report.parameter_error.is_empty());
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+        assert_eq!(report.get_worst_node(), None);
+    }
+}
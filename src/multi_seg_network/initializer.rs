@@ -0,0 +1,219 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/initializer.rs
+ */
+
+use rand::rngs::StdRng;
+use rand::Rng;
+// Seedable random number generator, so the initialized weights are reproducible.
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+pub struct Initializer();
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum InitializerEnum {
+    Constant {
+        value: f64,
+    },
+    // Every weight is the same, the hidden nodes will stay symmetric.
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Normal {
+        mean: f64,
+        std: f64,
+    },
+    #[default]
+    XavierUniform,
+    XavierNormal,
+    // Glorot, $Var(w) = \frac{2}{fan_{in} + fan_{out}}$, suits Sigmoid and Tanh.
+    HeUniform,
+    HeNormal,
+    // Kaiming, $Var(w) = \frac{2}{fan_{in}}$, suits ReLU.
+    Orthogonal {
+        gain: f64,
+    },
+    // The rows (or the columns if there are more rows) of the weight matrix are orthonormal, then multiplied by gain.
+}
+#[allow(dead_code)]
+impl Initializer {
+    fn uniform(rng: &mut StdRng, min: f64, max: f64) -> f64 {
+        if min < max {
+            rng.gen_range(min..max)
+        } else {
+            min
+        }
+    }
+    fn normal(rng: &mut StdRng, mean: f64, std: f64) -> f64 {
+        // Box-Muller transform, 1.0 - u1 keeps the logarithm away from 0.
+        let u1: f64 = rng.gen::<f64>();
+        let u2: f64 = rng.gen::<f64>();
+        mean + std * (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+    fn orthonormalize(matrix: &mut [Vec<f64>]) {
+        // Gram-Schmidt on the rows, the matrix should not have more rows than columns.
+        for i in 0..matrix.len() {
+            for j in 0..i {
+                let dot: f64 = matrix[i]
+                    .iter()
+                    .zip(matrix[j].iter())
+                    .map(|(a, b)| a * b)
+                    .sum();
+                let row_j: Vec<f64> = matrix[j].clone();
+                for (a, b) in matrix[i].iter_mut().zip(row_j.iter()) {
+                    *a -= dot * b;
+                }
+            }
+            let norm: f64 = matrix[i].iter().map(|a| a.powi(2)).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                matrix[i].iter_mut().for_each(|a| *a /= norm);
+            }
+        }
+    }
+    fn transpose(matrix: &[Vec<f64>], column_count: usize) -> Vec<Vec<f64>> {
+        (0..column_count)
+            .map(|c| matrix.iter().map(|row| row[c]).collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>()
+    }
+    pub fn get_matrix(
+        initializer_enum: InitializerEnum,
+        fan_in: usize,
+        fan_out: usize,
+        rng: &mut StdRng,
+    ) -> Vec<Vec<f64>> {
+        // Return the weights from fan_in nodes to fan_out nodes, indexed by [to][from].
+        let (n_in, n_out) = (fan_in.max(1) as f64, fan_out.max(1) as f64);
+        let sample = |rng: &mut StdRng| -> f64 {
+            match initializer_enum {
+                InitializerEnum::Constant { value } => value,
+                InitializerEnum::Uniform { min, max } => Initializer::uniform(rng, min, max),
+                InitializerEnum::Normal { mean, std } => Initializer::normal(rng, mean, std),
+                InitializerEnum::XavierUniform => {
+                    let limit: f64 = (6.0 / (n_in + n_out)).sqrt();
+                    Initializer::uniform(rng, -limit, limit)
+                }
+                InitializerEnum::XavierNormal => {
+                    Initializer::normal(rng, 0.0, (2.0 / (n_in + n_out)).sqrt())
+                }
+                InitializerEnum::HeUniform => {
+                    let limit: f64 = (6.0 / n_in).sqrt();
+                    Initializer::uniform(rng, -limit, limit)
+                }
+                InitializerEnum::HeNormal => Initializer::normal(rng, 0.0, (2.0 / n_in).sqrt()),
+                InitializerEnum::Orthogonal { .. } => Initializer::normal(rng, 0.0, 1.0),
+            }
+        };
+        let mut matrix: Vec<Vec<f64>> = (0..fan_out)
+            .map(|_| (0..fan_in).map(|_| sample(rng)).collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>();
+        if let InitializerEnum::Orthogonal { gain } = initializer_enum {
+            if fan_out <= fan_in {
+                Initializer::orthonormalize(&mut matrix);
+            } else {
+                let mut transposed: Vec<Vec<f64>> = Initializer::transpose(&matrix, fan_in);
+                Initializer::orthonormalize(&mut transposed);
+                matrix = Initializer::transpose(&transposed, fan_out);
+            }
+            matrix
+                .iter_mut()
+                .for_each(|row| row.iter_mut().for_each(|w| *w *= gain));
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Initializer, InitializerEnum};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_same_seed_gives_the_same_weights() {
+        let initializer_list: [InitializerEnum; 5] = [
+            InitializerEnum::Uniform {
+                min: -1.0,
+                max: 1.0,
+            },
+            InitializerEnum::Normal {
+                mean: 0.0,
+                std: 1.0,
+            },
+            InitializerEnum::XavierNormal,
+            InitializerEnum::HeUniform,
+            InitializerEnum::Orthogonal { gain: 1.0 },
+        ];
+        for initializer_enum in initializer_list {
+            let matrix_a =
+                Initializer::get_matrix(initializer_enum, 4, 3, &mut StdRng::seed_from_u64(7));
+            let matrix_b =
+                Initializer::get_matrix(initializer_enum, 4, 3, &mut StdRng::seed_from_u64(7));
+            let matrix_c =
+                Initializer::get_matrix(initializer_enum, 4, 3, &mut StdRng::seed_from_u64(8));
+            assert_eq!(matrix_a, matrix_b);
+            assert_ne!(matrix_a, matrix_c);
+        }
+    }
+
+    #[test]
+    fn test_variance_follows_the_fan() {
+        const FAN_IN: usize = 200;
+        const FAN_OUT: usize = 100;
+        let mut rng: StdRng = StdRng::seed_from_u64(0);
+        let variance_list: [(InitializerEnum, f64); 4] = [
+            (
+                InitializerEnum::XavierUniform,
+                2.0 / (FAN_IN + FAN_OUT) as f64,
+            ),
+            (
+                InitializerEnum::XavierNormal,
+                2.0 / (FAN_IN + FAN_OUT) as f64,
+            ),
+            (InitializerEnum::HeUniform, 2.0 / FAN_IN as f64),
+            (InitializerEnum::HeNormal, 2.0 / FAN_IN as f64),
+        ];
+        for (initializer_enum, expected_variance) in variance_list {
+            let w: Vec<f64> =
+                Initializer::get_matrix(initializer_enum, FAN_IN, FAN_OUT, &mut rng).concat();
+            let mean: f64 = w.iter().sum::<f64>() / w.len() as f64;
+            let variance: f64 = w.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / w.len() as f64;
+            assert!(mean.abs() < 0.01, "{:?}: mean = {}", initializer_enum, mean);
+            assert!(
+                (variance / expected_variance - 1.0).abs() < 0.05,
+                "{:?}: variance = {}",
+                initializer_enum,
+                variance
+            );
+        }
+    }
+
+    #[test]
+    fn test_orthogonal_rows_or_columns_are_orthonormal() {
+        let mut rng: StdRng = StdRng::seed_from_u64(0);
+        for (fan_in, fan_out) in [(5, 3), (3, 5), (4, 4)] {
+            let matrix = Initializer::get_matrix(
+                InitializerEnum::Orthogonal { gain: 2.0 },
+                fan_in,
+                fan_out,
+                &mut rng,
+            );
+            let vector_list: Vec<Vec<f64>> = if fan_out <= fan_in {
+                matrix.clone()
+            } else {
+                Initializer::transpose(&matrix, fan_in)
+            };
+            for i in 0..vector_list.len() {
+                for j in 0..vector_list.len() {
+                    let dot: f64 = vector_list[i]
+                        .iter()
+                        .zip(vector_list[j].iter())
+                        .map(|(a, b)| a * b)
+                        .sum();
+                    let expected: f64 = if i == j { 4.0 } else { 0.0 };
+                    assert!((dot - expected).abs() < 1e-9);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/layer_parameters.rs
+ */
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+// Seedable random number generator for the weight initializers.
+
+use crate::multi_seg_network::check_parameter_count;
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::flexible_network::{ActivationFunction, ActivationFunctionEnum};
+use crate::multi_seg_network::initializer::{Initializer, InitializerEnum};
+use crate::multi_seg_network::loss_function::LossFunctionEnum;
+use crate::multi_seg_network::optimizer::{Optimizer, OptimizerEnum, OptimizerState};
+
+pub struct LayerParameters {
+    // Trainable values of the layers with one bias term and one activation function per output node, such as DenseLayer and Conv2D.
+    pub w: Vec<f64>,
+    // Weights in the order given by the layer, "init" fills "fan_in" weights for every node.
+    pub b: Vec<f64>,
+    // One bias term for every output node, or every output channel of a convolution.
+    pub activation_fn_enum: ActivationFunctionEnum,
+    pub activation_params: Vec<f64>,
+    // Parameters of the activation function of every node, indexed by [node * parameter count + k].
+    pub w_gradient: Vec<f64>,
+    pub b_gradient: Vec<f64>,
+    pub activation_params_gradient: Vec<f64>,
+    // Sum of the gradients since the last "zero_grad", the parameter gradient is empty if they are not trainable.
+    pub gradient_count: usize,
+    pub optimizer_enum: OptimizerEnum,
+    pub optimizer_state: OptimizerState,
+    // The bias terms and the activation parameters are updated as one "step_parameters" list.
+    pub loss_fn_enum: LossFunctionEnum,
+    pub rng: StdRng,
+    // Drive the weight initializers, it is not exported.
+}
+#[allow(dead_code)]
+impl LayerParameters {
+    pub fn new() -> Self {
+        Self {
+            w: Vec::new(),
+            b: Vec::new(),
+            activation_fn_enum: ActivationFunctionEnum::DoNothing,
+            activation_params: Vec::new(),
+            w_gradient: Vec::new(),
+            b_gradient: Vec::new(),
+            activation_params_gradient: Vec::new(),
+            gradient_count: 0,
+            optimizer_enum: OptimizerEnum::default(),
+            optimizer_state: OptimizerState::new(),
+            loss_fn_enum: LossFunctionEnum::default(),
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+    pub fn init(
+        self: &mut Self,
+        initializer_enum: InitializerEnum,
+        fan_in: usize,
+        node_count: usize,
+    ) {
+        // Initialize the weights of "node_count" nodes with "fan_in" inputs, set the bias terms to zero and reset the optimizer.
+        self.w =
+            Initializer::get_matrix(initializer_enum, fan_in, node_count, &mut self.rng).concat();
+        self.b = vec![0.0; node_count];
+        self.set_activation_function(self.activation_fn_enum);
+        self.optimizer_state = OptimizerState::new();
+    }
+    pub fn set_activation_function(self: &mut Self, activation_fn_enum: ActivationFunctionEnum) {
+        // Change the activation function of every node, and reset its parameters to the default.
+        self.activation_fn_enum = activation_fn_enum;
+        self.activation_params =
+            ActivationFunction::get_default_parameters(activation_fn_enum).repeat(self.b.len());
+        self.zero_grad();
+    }
+    pub fn import_activation_parameters(
+        self: &mut Self,
+        activation_params: Vec<f64>,
+    ) -> Result<()> {
+        // Take the exported parameters of every node, the default parameters are kept if it is empty.
+        if activation_params.is_empty() {
+            return Ok(());
+        }
+        if activation_params.len() != self.activation_params.len() {
+            return Err(NetworkError::MalformedData(format!(
+                "layer has {} activation parameters but {:?} takes {}",
+                activation_params.len(),
+                self.activation_fn_enum,
+                self.activation_params.len()
+            )));
+        }
+        self.activation_params = activation_params;
+        Ok(())
+    }
+    pub fn get_activation_parameter_count(self: &Self) -> usize {
+        // Count of the activation parameters of one node.
+        ActivationFunction::get_default_parameters(self.activation_fn_enum).len()
+    }
+    pub fn get_activation_parameters(self: &Self, node: usize) -> &[f64] {
+        let parameter_count: usize = self.get_activation_parameter_count();
+        &self.activation_params[node * parameter_count..(node + 1) * parameter_count]
+    }
+    pub fn parameters(self: &Self) -> Vec<f64> {
+        // The weights, the bias terms and the trainable activation parameters, in the order of "Segment::parameters".
+        let mut parameters: Vec<f64> = [self.w.clone(), self.b.clone()].concat();
+        if ActivationFunction::is_trainable(self.activation_fn_enum) {
+            parameters.extend(self.activation_params.iter());
+        }
+        parameters
+    }
+    pub fn parameter_count(self: &Self) -> usize {
+        self.w.len()
+            + self.b.len()
+            + if ActivationFunction::is_trainable(self.activation_fn_enum) {
+                self.activation_params.len()
+            } else {
+                0
+            }
+    }
+    pub fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), parameters.len())?;
+        let (w, rest) = parameters.split_at(self.w.len());
+        let (b, p) = rest.split_at(self.b.len());
+        self.w.copy_from_slice(w);
+        self.b.copy_from_slice(b);
+        self.activation_params[..p.len()].copy_from_slice(p);
+        Ok(())
+    }
+    pub fn gradients(self: &Self) -> Vec<f64> {
+        // The average gradients since the last "zero_grad".
+        if self.gradient_count == 0 {
+            return vec![0.0; self.parameter_count()];
+        }
+        let gradient_count: f64 = self.gradient_count as f64;
+        self.w_gradient
+            .iter()
+            .chain(self.b_gradient.iter())
+            .chain(self.activation_params_gradient.iter())
+            .map(|gradient| gradient / gradient_count)
+            .collect::<Vec<f64>>()
+    }
+    pub fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), gradients.len())?;
+        self.zero_grad();
+        let (w_gradient, rest) = gradients.split_at(self.w.len());
+        let (b_gradient, p_gradient) = rest.split_at(self.b.len());
+        self.w_gradient.copy_from_slice(w_gradient);
+        self.b_gradient.copy_from_slice(b_gradient);
+        self.activation_params_gradient.copy_from_slice(p_gradient);
+        self.gradient_count = 1;
+        Ok(())
+    }
+    pub fn apply_gradients(self: &mut Self, learning_rate: f64) {
+        // Step the optimizer with the average gradients.
+        if self.gradient_count == 0 {
+            return;
+        }
+        let gradient_count: f64 = self.gradient_count as f64;
+        let w_gradient: Vec<f64> = self
+            .w_gradient
+            .iter()
+            .map(|gradient| gradient / gradient_count)
+            .collect::<Vec<f64>>();
+        Optimizer::step_weights(
+            self.optimizer_enum,
+            &mut self.optimizer_state,
+            &mut self.w,
+            &w_gradient,
+            learning_rate,
+        );
+        let parameter_count: usize = self.activation_params_gradient.len();
+        let mut p: Vec<f64> = [
+            self.b.clone(),
+            self.activation_params[..parameter_count].to_vec(),
+        ]
+        .concat();
+        let p_gradient: Vec<f64> = self
+            .b_gradient
+            .iter()
+            .chain(self.activation_params_gradient.iter())
+            .map(|gradient| gradient / gradient_count)
+            .collect::<Vec<f64>>();
+        // The bias terms are followed by the trainable activation parameters.
+        Optimizer::step_parameters(
+            self.optimizer_enum,
+            &mut self.optimizer_state,
+            &mut p,
+            &p_gradient,
+            learning_rate,
+        );
+        self.activation_params[..parameter_count].copy_from_slice(&p[self.b.len()..]);
+        p.truncate(self.b.len());
+        self.b = p;
+    }
+    pub fn zero_grad(self: &mut Self) {
+        self.w_gradient = vec![0.0; self.w.len()];
+        self.b_gradient = vec![0.0; self.b.len()];
+        self.activation_params_gradient =
+            if ActivationFunction::is_trainable(self.activation_fn_enum) {
+                vec![0.0; self.activation_params.len()]
+            } else {
+                Vec::new()
+            };
+        self.gradient_count = 0;
+    }
+    pub fn set_seed(self: &mut Self, seed: u64) {
+        // Reset the random number generator used by "init", the same seed gives the same weights.
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/loss_function.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+const HUBER_DELTA: f64 = 1.0;
+// Where the Huber loss turns from quadratic to linear.
+const PROBABILITY_EPSILON: f64 = 1e-12;
+// Keep the probabilities away from 0 and 1 before taking the logarithm.
+
+pub struct LossFunction();
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum LossFunctionEnum {
+    #[default]
+    MeanSquaredError,
+    MeanAbsoluteError,
+    Huber,
+    BinaryCrossEntropy,
+    // The output should be probabilities, such as the value of Sigmoid nodes.
+    SoftMaxCrossEntropy,
+    // Categorical cross-entropy fused with soft max, the output should be the logits before soft max.
+    PolicyGradient,
+    // $-\sum{anticipated_i \times \ln(output_i)}$, the output should be the action probabilities,
+    // and the anticipated data should be the one-hot of the taken action multiplied by its advantage.
+}
+#[allow(dead_code)]
+impl LossFunction {
+    fn clamp_probability(x: f64) -> f64 {
+        x.clamp(PROBABILITY_EPSILON, 1.0 - PROBABILITY_EPSILON)
+    }
+    fn soft_max(output: &[f64]) -> Vec<f64> {
+        let max: f64 = output.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let exponential: Vec<f64> = output.iter().map(|o| (o - max).exp()).collect::<Vec<f64>>();
+        let sum: f64 = exponential.iter().sum();
+        exponential.iter().map(|e| e / sum).collect::<Vec<f64>>()
+    }
+    fn mean_squared_error(output: &[f64], anticipated: &[f64]) -> f64 {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| (o - a).powi(2))
+            .sum::<f64>()
+            / n
+    }
+    fn mean_squared_error_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| 2.0 * (o - a) / n)
+            .collect::<Vec<f64>>()
+    }
+    fn mean_absolute_error(output: &[f64], anticipated: &[f64]) -> f64 {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| (o - a).abs())
+            .sum::<f64>()
+            / n
+    }
+    fn mean_absolute_error_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| {
+                if o > a {
+                    1.0 / n
+                } else if o < a {
+                    -1.0 / n
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<f64>>()
+    }
+    fn huber(output: &[f64], anticipated: &[f64]) -> f64 {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| {
+                let d: f64 = (o - a).abs();
+                if d <= HUBER_DELTA {
+                    0.5 * d.powi(2)
+                } else {
+                    HUBER_DELTA * (d - 0.5 * HUBER_DELTA)
+                }
+            })
+            .sum::<f64>()
+            / n
+    }
+    fn huber_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(o, a)| (o - a).clamp(-HUBER_DELTA, HUBER_DELTA) / n)
+            .collect::<Vec<f64>>()
+    }
+    fn binary_cross_entropy(output: &[f64], anticipated: &[f64]) -> f64 {
+        let n: f64 = output.len() as f64;
+        -output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(&o, a)| {
+                let o: f64 = LossFunction::clamp_probability(o);
+                a * o.ln() + (1.0 - a) * (1.0 - o).ln()
+            })
+            .sum::<f64>()
+            / n
+    }
+    fn binary_cross_entropy_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        let n: f64 = output.len() as f64;
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(&o, a)| {
+                let o: f64 = LossFunction::clamp_probability(o);
+                (o - a) / (o * (1.0 - o)) / n
+            })
+            .collect::<Vec<f64>>()
+    }
+    fn soft_max_cross_entropy(output: &[f64], anticipated: &[f64]) -> f64 {
+        -LossFunction::soft_max(output)
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(&p, a)| a * LossFunction::clamp_probability(p).ln())
+            .sum::<f64>()
+    }
+    fn soft_max_cross_entropy_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        // $\frac{\partial lost}{\partial output_j} = p_j \times \sum{anticipated_i} - anticipated_j$
+        let anticipated_sum: f64 = anticipated.iter().sum();
+        LossFunction::soft_max(output)
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(p, a)| p * anticipated_sum - a)
+            .collect::<Vec<f64>>()
+    }
+    fn policy_gradient(output: &[f64], anticipated: &[f64]) -> f64 {
+        -output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(&o, a)| a * LossFunction::clamp_probability(o).ln())
+            .sum::<f64>()
+    }
+    fn policy_gradient_partial(output: &[f64], anticipated: &[f64]) -> Vec<f64> {
+        output
+            .iter()
+            .zip(anticipated.iter())
+            .map(|(&o, a)| -a / LossFunction::clamp_probability(o))
+            .collect::<Vec<f64>>()
+    }
+    pub fn get_function(loss_fn_enum: LossFunctionEnum) -> fn(&[f64], &[f64]) -> f64 {
+        match loss_fn_enum {
+            LossFunctionEnum::MeanSquaredError => LossFunction::mean_squared_error,
+            LossFunctionEnum::MeanAbsoluteError => LossFunction::mean_absolute_error,
+            LossFunctionEnum::Huber => LossFunction::huber,
+            LossFunctionEnum::BinaryCrossEntropy => LossFunction::binary_cross_entropy,
+            LossFunctionEnum::SoftMaxCrossEntropy => LossFunction::soft_max_cross_entropy,
+            LossFunctionEnum::PolicyGradient => LossFunction::policy_gradient,
+        }
+    }
+    pub fn get_partial(loss_fn_enum: LossFunctionEnum) -> fn(&[f64], &[f64]) -> Vec<f64> {
+        // Return the function of $\frac{\partial lost}{\partial output}$.
+        match loss_fn_enum {
+            LossFunctionEnum::MeanSquaredError => LossFunction::mean_squared_error_partial,
+            LossFunctionEnum::MeanAbsoluteError => LossFunction::mean_absolute_error_partial,
+            LossFunctionEnum::Huber => LossFunction::huber_partial,
+            LossFunctionEnum::BinaryCrossEntropy => LossFunction::binary_cross_entropy_partial,
+            LossFunctionEnum::SoftMaxCrossEntropy => LossFunction::soft_max_cross_entropy_partial,
+            LossFunctionEnum::PolicyGradient => LossFunction::policy_gradient_partial,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LossFunction, LossFunctionEnum};
+
+    #[test]
+    fn test_loss_partial_matches_finite_difference() {
+        const H: f64 = 1e-6;
+        let loss_fn_list: [(LossFunctionEnum, [f64; 3], [f64; 3]); 6] = [
+            (
+                LossFunctionEnum::MeanSquaredError,
+                [0.3, -1.2, 2.0],
+                [0.5, 0.5, 1.0],
+            ),
+            (
+                LossFunctionEnum::MeanAbsoluteError,
+                [0.3, -1.2, 2.0],
+                [0.5, 0.5, 1.0],
+            ),
+            (LossFunctionEnum::Huber, [0.3, -1.2, 2.0], [0.5, 0.5, 0.5]),
+            (
+                LossFunctionEnum::BinaryCrossEntropy,
+                [0.3, 0.9, 0.5],
+                [1.0, 0.0, 1.0],
+            ),
+            (
+                LossFunctionEnum::SoftMaxCrossEntropy,
+                [0.3, -1.2, 2.0],
+                [0.0, 1.0, 0.0],
+            ),
+            (
+                LossFunctionEnum::PolicyGradient,
+                [0.2, 0.5, 0.3],
+                [0.0, -1.5, 0.0],
+            ),
+        ];
+        for (loss_fn_enum, output, anticipated) in loss_fn_list {
+            let function = LossFunction::get_function(loss_fn_enum);
+            let partial: Vec<f64> = LossFunction::get_partial(loss_fn_enum)(
+                &Vec::from(output),
+                &Vec::from(anticipated),
+            );
+            for i in 0..output.len() {
+                let mut output_plus: Vec<f64> = Vec::from(output);
+                output_plus[i] += H;
+                let mut output_minus: Vec<f64> = Vec::from(output);
+                output_minus[i] -= H;
+                let numerical_partial: f64 = (function(&output_plus, &Vec::from(anticipated))
+                    - function(&output_minus, &Vec::from(anticipated)))
+                    / (2.0 * H);
+                assert!(
+                    (numerical_partial - partial[i]).abs() < 1e-5,
+                    "{:?}: {} != {}",
+                    loss_fn_enum,
+                    numerical_partial,
+                    partial[i]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_soft_max_cross_entropy_is_stable_for_large_logits() {
+        let output: Vec<f64> = Vec::from([1000.0, 0.0, -1000.0]);
+        let anticipated: Vec<f64> = Vec::from([1.0, 0.0, 0.0]);
+
+        let lost: f64 = LossFunction::get_function(LossFunctionEnum::SoftMaxCrossEntropy)(
+            &output,
+            &anticipated,
+        );
+        let partial: Vec<f64> =
+            LossFunction::get_partial(LossFunctionEnum::SoftMaxCrossEntropy)(&output, &anticipated);
+
+        assert!(lost.is_finite() && lost < 1e-6);
+        assert!(partial.iter().all(|p| p.is_finite() && p.abs() < 1e-6));
+    }
+}
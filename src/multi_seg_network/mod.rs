@@ -1,46 +1,195 @@
 // 2024 (c) MaoHuPi
 // rust-rl/src/multi_seg_network/mod.rs
 
-use crate::flexible_network::FlexibleNetwork;
-use crate::function_segment::FunctionSegment;
+use crate::binary_format::{binary_to_json, json_to_binary, PrecisionEnum};
+use crate::error::{NetworkError, Result};
+use crate::loss_function::{LossFunction, LossFunctionEnum};
+use crate::model_file::{
+    get_checkpoint_list, get_checkpoint_path, remove_old_checkpoints, write_atomically,
+};
+use crate::registry::new_segment;
+use crate::schema::{check_required, insert_default, migrate, Migration};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Clone, Serialize, Deserialize)]
-pub enum SegmentTypes {
-    MultiSegNetwork,
-    FlexibleNetwork,
-    FunctionSegment,
-}
 pub trait Segment {
     fn new() -> Self
     where
         Self: Sized;
-    fn get_type(self: &Self) -> SegmentTypes;
-    fn set_input(self: &mut Self, input: Vec<f64>);
+    fn get_type(self: &Self) -> String;
+    // Name of the segment type, "MultiSegNetwork::import_data" builds the segment by the constructor registered under it.
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()>;
     fn get_output(self: &mut Self) -> Vec<f64>;
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        None
+    }
+    // Shape of the values taken by "set_input", such as [channel count, height, width], None takes values of any shape.
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        None
+    }
+    // Shape of the values given by "get_output", None keeps the shape of the input.
     fn export_data(self: &mut Self) -> String;
-    fn import_data(self: &mut Self, data: String);
-    fn next(self: &mut Self);
-    fn can_fitting(self: &Self) -> bool {
-        false
+    fn import_data(self: &mut Self, data: String) -> Result<()>;
+    fn next(self: &mut Self) -> Result<()>;
+    // Fails instead of panicking when the value can not pass a segment, such as a wrong size between segments of unknown shapes.
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Run "set_input" and "next" on every input in order and return their outputs, "get_output" gives the output of the last one.
+        // It copies every input for "set_input", the segments which can run a sample from its slice override it.
+        let mut output_batch: Vec<Vec<f64>> = Vec::with_capacity(input_batch.len());
+        for input in input_batch.iter() {
+            self.set_input(input.clone())?;
+            self.next()?;
+            output_batch.push(self.get_output());
+        }
+        Ok(output_batch)
+    }
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        LossFunctionEnum::default()
+    }
+    // The loss used by "fitting_batch".
+    fn fitting_batch(self: &mut Self, data_pair_list: &[[Vec<f64>; 2]], rate: f64) -> Result<()> {
+        // Fit with the average gradient of the [input, anticipated_data] pairs in one step.
+        self.zero_grad();
+        for data_pair in data_pair_list.iter() {
+            self.set_input(data_pair[0].clone())?;
+            self.next()?;
+            let output_partial: Vec<f64> =
+                get_lost_partial(self.get_loss_function(), &self.get_output(), &data_pair[1])?;
+            self.accumulate_gradients(output_partial)?;
+        }
+        self.apply_gradients(rate);
+        self.zero_grad();
+        Ok(())
+    }
+    fn backward(self: &mut Self, output_partial: Vec<f64>, rate: f64) -> Result<Vec<f64>> {
+        // Fit the segment with the gradient of the last "next" only, and return $\frac{\partial lost}{\partial input}$.
+        let input_partial: Vec<f64> = self.accumulate_gradients(output_partial)?;
+        self.apply_gradients(rate);
+        self.zero_grad();
+        Ok(input_partial)
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        Vec::new()
+    }
+    // The trainable parameters in a fixed order, such as the weights, the bias terms and the trainable activation parameters.
+    fn parameter_count(self: &Self) -> usize {
+        self.parameters().len()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), parameters.len())
     }
-    fn fitting(self: &mut Self, anticipated_data: Vec<f64>, rate: f64) {}
-    fn can_inverse(self: &Self) -> bool {
-        false
+    // Replace the parameters with the values in the order of "parameters", the optimizer state is kept.
+    fn gradients(self: &Self) -> Vec<f64> {
+        vec![0.0; self.parameter_count()]
     }
-    fn inverse(self: &mut Self, data: Vec<f64>, rate: f64) -> Vec<f64> {
-        data
+    // Average of the gradients accumulated since the last "zero_grad" in the order of "parameters", the ones used by "apply_gradients".
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), gradients.len())
     }
+    // Replace the accumulated gradients, so the next "apply_gradients" steps with them.
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>>;
+    // Take $\frac{\partial lost}{\partial output}$ of the last "next", add the gradients of the parameters into the segment, and return $\frac{\partial lost}{\partial input}$.
+    fn apply_gradients(self: &mut Self, _rate: f64) {}
+    // Update the parameters with the average of the accumulated gradients.
+    fn zero_grad(self: &mut Self) {}
+    fn reset_state(self: &mut Self) {}
+    // Forget the values kept from the past steps by the recurrent connections.
+}
+pub fn get_lost_partial(
+    loss_fn_enum: LossFunctionEnum,
+    output: &[f64],
+    anticipated_data: &[f64],
+) -> Result<Vec<f64>> {
+    // Return $\frac{\partial lost}{\partial output}$ after checking the size of the anticipated data.
+    if anticipated_data.len() != output.len() {
+        return Err(NetworkError::InputSizeMismatch {
+            expected: output.len(),
+            actual: anticipated_data.len(),
+        });
+    }
+    Ok(LossFunction::get_partial(loss_fn_enum)(
+        output,
+        anticipated_data,
+    ))
+}
+pub fn check_parameter_count(expected: usize, actual: usize) -> Result<()> {
+    if expected != actual {
+        return Err(NetworkError::InputSizeMismatch { expected, actual });
+    }
+    Ok(())
+}
+pub fn is_shape_compatible(output_shape: &Vec<usize>, input_shape: &Vec<usize>) -> bool {
+    // The values are always stored flat, so a list takes values of any shape with the same size, and the other way round.
+    output_shape == input_shape
+        || ((output_shape.len() == 1 || input_shape.len() == 1)
+            && output_shape.iter().product::<usize>() == input_shape.iter().product::<usize>())
+}
+fn check_next_shape(
+    output_shape: &Option<Vec<usize>>,
+    segment: &dyn Segment,
+    index: usize,
+) -> Result<()> {
+    // Check if the segment at the index can take the output of the segments before it.
+    if let (Some(output_shape), Some(input_shape)) = (output_shape, segment.get_input_shape()) {
+        if !is_shape_compatible(output_shape, &input_shape) {
+            return Err(NetworkError::ShapeMismatch {
+                segment: index,
+                expected: input_shape,
+                actual: output_shape.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputHead {
+    // A named slice of the output, such as the "policy" and the "value" of an actor-critic agent.
+    pub name: String,
+    pub size: usize,
+    pub loss_fn_enum: LossFunctionEnum,
+    pub weight: f64,
+    // Scale of the partial of its loss, when the heads share the segments before them.
+}
+pub type NamedDataPair = (Vec<f64>, BTreeMap<String, Vec<f64>>);
+// An input with the anticipated data of some heads, keyed by the head name.
+#[derive(Serialize, Deserialize)]
+struct OutputHeadData {
+    n: String,
+    len: usize,
+    l_fn: LossFunctionEnum,
+    w: f64,
 }
 pub struct MultiSegNetwork {
     segments: Vec<Box<(dyn Segment + 'static)>>,
     input_value: Vec<f64>,
     output_value: Vec<f64>,
+    loss_fn_enum: LossFunctionEnum,
+    output_heads: Vec<OutputHead>,
+    // The heads take the output one after another from the first value.
 }
 #[derive(Serialize, Deserialize)]
 struct MultiSegNetworkData {
-    types: Vec<SegmentTypes>,
+    #[serde(default)]
+    v: u32,
+    types: Vec<String>,
     data: Vec<String>,
+    l_fn: LossFunctionEnum,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    heads: Vec<OutputHeadData>,
+}
+const MULTI_SEG_NETWORK_MIGRATION_LIST: [Migration; 1] = [migrate_multi_seg_network_v0];
+// "MultiSegNetworkData" of version i is upgraded by "MULTI_SEG_NETWORK_MIGRATION_LIST[i]", see "schema::migrate".
+fn migrate_multi_seg_network_v0(mut data: Map<String, Value>) -> Result<Map<String, Value>> {
+    // The files before the version is added may have no loss function.
+    check_required(&data, &["types", "data"])?;
+    insert_default(
+        &mut data,
+        "l_fn",
+        serde_json::to_value(LossFunctionEnum::default())?,
+    );
+    Ok(data)
 }
 #[allow(dead_code)]
 impl Segment for MultiSegNetwork {
@@ -49,21 +198,46 @@ impl Segment for MultiSegNetwork {
             segments: Vec::new(),
             input_value: Vec::new(),
             output_value: Vec::new(),
+            loss_fn_enum: LossFunctionEnum::default(),
+            output_heads: Vec::new(),
         }
     }
-    fn get_type(self: &Self) -> SegmentTypes {
-        SegmentTypes::MultiSegNetwork
+    fn get_type(self: &Self) -> String {
+        String::from("MultiSegNetwork")
     }
-    fn set_input(self: &mut Self, input: Vec<f64>) {
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        if let Some(input_shape) = self.segments.first().and_then(|seg| seg.get_input_shape()) {
+            let input_size: usize = input_shape.iter().product();
+            if input.len() != input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: input_size,
+                    actual: input.len(),
+                });
+            }
+        }
+        // Check the size against the first segment, "next" passes the input to it.
         self.input_value = input;
+        Ok(())
     }
     fn get_output(self: &mut Self) -> Vec<f64> {
         self.output_value.clone()
     }
     fn export_data(self: &mut Self) -> String {
         let mut data = MultiSegNetworkData {
+            v: MULTI_SEG_NETWORK_MIGRATION_LIST.len() as u32,
             types: Vec::new(),
             data: Vec::new(),
+            l_fn: self.loss_fn_enum,
+            heads: self
+                .output_heads
+                .iter()
+                .map(|head| OutputHeadData {
+                    n: head.name.clone(),
+                    len: head.size,
+                    l_fn: head.loss_fn_enum,
+                    w: head.weight,
+                })
+                .collect(),
         };
         for i in 0..self.segments.len() {
             data.types.push(self.segments[i].get_type());
@@ -71,38 +245,192 @@ impl Segment for MultiSegNetwork {
         }
         serde_json::to_string(&data).unwrap()
     }
-    fn import_data(self: &mut Self, data: String) {
-        let data: MultiSegNetworkData = serde_json::from_str(&data.as_str()).unwrap();
-        let mut segments: Vec<Box<(dyn Segment + 'static)>> = Vec::new();
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: MultiSegNetworkData =
+            serde_json::from_value(migrate(&data, &MULTI_SEG_NETWORK_MIGRATION_LIST)?)?;
+        if data.types.len() != data.data.len() {
+            return Err(NetworkError::MalformedData(format!(
+                "{} segment types but {} segment data",
+                data.types.len(),
+                data.data.len()
+            )));
+        }
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
         for i in 0..data.types.len() {
-            let mut seg = MultiSegNetwork::new_seg(data.types[i].clone());
-            (*seg).import_data(data.data[i].clone());
-            segments.push(seg);
+            let mut seg = MultiSegNetwork::new_seg(&data.types[i])?;
+            (*seg).import_data(data.data[i].clone())?;
+            check_next_shape(&imported_seg.get_output_shape(), &*seg, i)?;
+            imported_seg.segments.push(seg);
+        }
+        for head in data.heads.into_iter() {
+            imported_seg.add_output_head(&head.n, head.len, head.l_fn, head.w)?;
         }
-        self.segments = segments;
+        self.segments = imported_seg.segments;
+        self.loss_fn_enum = data.l_fn;
+        self.output_heads = imported_seg.output_heads;
+        Ok(())
     }
-    fn next(self: &mut Self) {
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        // The first shape taken by the segments which keep the shape of their input.
+        for segment in self.segments.iter() {
+            if let Some(input_shape) = segment.get_input_shape() {
+                return Some(input_shape);
+            }
+            if segment.get_output_shape().is_some() {
+                return None;
+            }
+        }
+        None
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        let mut output_shape: Option<Vec<usize>> = None;
+        for segment in self.segments.iter() {
+            output_shape = segment
+                .get_output_shape()
+                .or(output_shape.or(segment.get_input_shape()));
+        }
+        output_shape
+    }
+    fn next(self: &mut Self) -> Result<()> {
         let mut value: Vec<f64> = self.input_value.clone();
         for i in 0..self.segments.len() {
-            self.segments[i].set_input(value);
-            self.segments[i].next();
+            if let Err(error) = self.segments[i].set_input(value) {
+                return Err(self.get_shape_mismatch(i, error));
+            }
+            // The shapes between segments are checked by "push_seg", so only a wrong input size of the network stops here.
+            self.segments[i].next()?;
             value = self.segments[i].get_output();
         }
         self.output_value = value;
+        Ok(())
+    }
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        let mut value_batch: Option<Vec<Vec<f64>>> = None;
+        for i in 0..self.segments.len() {
+            match self.segments[i].forward_batch(value_batch.as_deref().unwrap_or(input_batch)) {
+                Ok(output_batch) => value_batch = Some(output_batch),
+                Err(error) => return Err(self.get_shape_mismatch(i, error)),
+            }
+        }
+        // Pass the whole batch from segment to segment, instead of every sample.
+        let output_batch: Vec<Vec<f64>> = value_batch.unwrap_or_else(|| input_batch.to_vec());
+        if let (Some(input), Some(output)) = (input_batch.last(), output_batch.last()) {
+            self.input_value = input.clone();
+            self.output_value = output.clone();
+        }
+        Ok(output_batch)
+    }
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        self.loss_fn_enum
+    }
+    fn fitting_batch(self: &mut Self, data_pair_list: &[[Vec<f64>; 2]], rate: f64) -> Result<()> {
+        self.zero_grad();
+        for data_pair in data_pair_list.iter() {
+            self.set_input(data_pair[0].clone())?;
+            self.next()?;
+            let output_partial: Vec<f64> = self.get_output_partial(&data_pair[1])?;
+            self.accumulate_gradients(output_partial)?;
+        }
+        self.apply_gradients(rate);
+        self.zero_grad();
+        Ok(())
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        let mut partial: Vec<f64> = output_partial;
+        for i in (0..self.segments.len()).rev() {
+            partial = self.segments[i].accumulate_gradients(partial)?;
+        }
+        // Pass the partial from the last segment back to the first one.
+        Ok(partial)
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.parameters())
+            .collect::<Vec<f64>>()
+    }
+    fn parameter_count(self: &Self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.parameter_count())
+            .sum()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), parameters.len())?;
+        let mut start: usize = 0;
+        for segment in self.segments.iter_mut() {
+            let count: usize = segment.parameter_count();
+            segment.set_parameters(&parameters[start..start + count])?;
+            start += count;
+        }
+        Ok(())
+    }
+    fn gradients(self: &Self) -> Vec<f64> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.gradients())
+            .collect::<Vec<f64>>()
+    }
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), gradients.len())?;
+        let mut start: usize = 0;
+        for segment in self.segments.iter_mut() {
+            let count: usize = segment.parameter_count();
+            segment.set_gradients(&gradients[start..start + count])?;
+            start += count;
+        }
+        Ok(())
+    }
+    fn apply_gradients(self: &mut Self, rate: f64) {
+        for i in 0..self.segments.len() {
+            self.segments[i].apply_gradients(rate);
+        }
+    }
+    fn zero_grad(self: &mut Self) {
+        for i in 0..self.segments.len() {
+            self.segments[i].zero_grad();
+        }
+    }
+    fn reset_state(self: &mut Self) {
+        for i in 0..self.segments.len() {
+            self.segments[i].reset_state();
+        }
     }
 }
 #[allow(dead_code)]
 impl MultiSegNetwork {
-    pub fn new_seg(seg_type_name: SegmentTypes) -> Box<dyn Segment + 'static> {
-        match seg_type_name {
-            SegmentTypes::MultiSegNetwork => Box::new(MultiSegNetwork::new()),
-            SegmentTypes::FlexibleNetwork => Box::new(FlexibleNetwork::new()),
-            SegmentTypes::FunctionSegment => Box::new(FunctionSegment::new()),
+    pub fn fitting(self: &mut Self, anticipated_data: Vec<f64>, rate: f64) -> Result<()> {
+        // Fit with the output of the last "next", by the loss of the network or of its output heads.
+        let output_partial: Vec<f64> = self.get_output_partial(&anticipated_data)?;
+        self.backward(output_partial, rate)?;
+        Ok(())
+    }
+    fn get_shape_mismatch(self: &Self, segment: usize, error: NetworkError) -> NetworkError {
+        // Report a wrong input size of a segment with its index and input shape, the other errors are kept.
+        match error {
+            NetworkError::InputSizeMismatch { expected, actual } => NetworkError::ShapeMismatch {
+                segment,
+                expected: self.segments[segment]
+                    .get_input_shape()
+                    .unwrap_or(Vec::from([expected])),
+                actual: Vec::from([actual]),
+            },
+            error => error,
         }
     }
-    pub fn push_seg(self: &mut Self, segment: (impl Segment + 'static)) -> usize {
+    pub fn new_seg(seg_type_name: &str) -> Result<Box<dyn Segment + 'static>> {
+        // Build an empty segment by the constructor registered under the type name, see "registry::register_segment".
+        new_segment(seg_type_name)
+    }
+    pub fn set_loss_function(self: &mut Self, loss_fn_enum: LossFunctionEnum) {
+        // Select the loss of the final output used by "fitting".
+        self.loss_fn_enum = loss_fn_enum;
+    }
+    pub fn push_seg(self: &mut Self, segment: (impl Segment + 'static)) -> Result<usize> {
+        // Append the segment and return its index, if it can take the output of the segments before.
+        check_next_shape(&self.get_output_shape(), &segment, self.segments.len())?;
         self.segments.push(Box::new(segment));
-        self.segments.len() - 1
+        Ok(self.segments.len() - 1)
     }
     pub fn operate_seg(
         self: &mut Self,
@@ -111,13 +439,677 @@ impl MultiSegNetwork {
     ) {
         call_back(&mut self.segments[id]);
     }
+    pub fn export_binary(self: &mut Self, precision_enum: PrecisionEnum) -> Result<Vec<u8>> {
+        // The same data as "export_data" in the binary model format, see "binary_format::json_to_binary".
+        json_to_binary(&self.export_data(), precision_enum)
+    }
+    pub fn import_binary(self: &mut Self, bytes: &[u8]) -> Result<()> {
+        self.import_data(binary_to_json(bytes)?)
+    }
+    pub fn save_binary(
+        self: &mut Self,
+        path: impl AsRef<Path>,
+        precision_enum: PrecisionEnum,
+    ) -> Result<()> {
+        write_atomically(path, &self.export_binary(precision_enum)?)
+    }
+    pub fn load_binary(self: &mut Self, path: impl AsRef<Path>) -> Result<()> {
+        let bytes: Vec<u8> = std::fs::read(path)?;
+        self.import_binary(&bytes)
+    }
+    pub fn save(self: &mut Self, path: impl AsRef<Path>) -> Result<()> {
+        // Write "export_data" into the json file atomically, see "model_file::write_atomically".
+        write_atomically(path, self.export_data().as_bytes())
+    }
+    pub fn load(self: &mut Self, path: impl AsRef<Path>) -> Result<()> {
+        let data: String = std::fs::read_to_string(path)?;
+        self.import_data(data)
+    }
+    pub fn save_checkpoint(
+        self: &mut Self,
+        path: impl AsRef<Path>,
+        step: usize,
+        keep_count: usize,
+    ) -> Result<PathBuf> {
+        // Save beside the model path with the step, such as "net.ckpt-000100.json" for "net.json",
+        // then remove the older checkpoints except the latest "keep_count" ones.
+        let checkpoint_path: PathBuf = get_checkpoint_path(&path, step)?;
+        self.save(&checkpoint_path)?;
+        remove_old_checkpoints(&path, keep_count.max(1))?;
+        Ok(checkpoint_path)
+    }
+    pub fn load_latest_checkpoint(
+        self: &mut Self,
+        path: impl AsRef<Path>,
+    ) -> Result<Option<usize>> {
+        // Load the checkpoint of the latest step and return the step, None if there is no checkpoint.
+        match get_checkpoint_list(path)?.pop() {
+            Some((step, checkpoint_path)) => {
+                self.load(checkpoint_path)?;
+                Ok(Some(step))
+            }
+            None => Ok(None),
+        }
+    }
+    pub fn add_output_head(
+        self: &mut Self,
+        name: &str,
+        size: usize,
+        loss_fn_enum: LossFunctionEnum,
+        weight: f64,
+    ) -> Result<usize> {
+        // Name the next "size" values of the output, and return the index of the head.
+        if self.output_heads.iter().any(|head| head.name == name) {
+            return Err(NetworkError::MalformedData(format!(
+                "output head \"{}\" is added already",
+                name
+            )));
+        }
+        let head_size: usize = self
+            .output_heads
+            .iter()
+            .map(|head| head.size)
+            .sum::<usize>()
+            + size;
+        if let Some(output_shape) = self.get_output_shape() {
+            let output_size: usize = output_shape.iter().product();
+            if head_size > output_size {
+                return Err(NetworkError::InvalidShape(format!(
+                    "the output heads take {} values, but the network gives {}",
+                    head_size, output_size
+                )));
+            }
+        }
+        self.output_heads.push(OutputHead {
+            name: name.to_string(),
+            size,
+            loss_fn_enum,
+            weight,
+        });
+        Ok(self.output_heads.len() - 1)
+    }
+    pub fn get_output_heads(self: &Self) -> &Vec<OutputHead> {
+        &self.output_heads
+    }
+    pub fn get_output_head_name_list(self: &Self) -> Vec<String> {
+        self.output_heads
+            .iter()
+            .map(|head| head.name.clone())
+            .collect()
+    }
+    fn get_output_head_range(self: &Self, name: &str) -> Result<std::ops::Range<usize>> {
+        let mut start: usize = 0;
+        for head in self.output_heads.iter() {
+            if head.name == name {
+                return Ok(start..start + head.size);
+            }
+            start += head.size;
+        }
+        Err(NetworkError::HeadNotFound(name.to_string()))
+    }
+    pub fn set_output_head_weight(self: &mut Self, name: &str, weight: f64) -> Result<()> {
+        match self.output_heads.iter_mut().find(|head| head.name == name) {
+            Some(head) => {
+                head.weight = weight;
+                Ok(())
+            }
+            None => Err(NetworkError::HeadNotFound(name.to_string())),
+        }
+    }
+    pub fn get_named_output(self: &Self, name: &str) -> Result<Vec<f64>> {
+        // Return the values of the head in the output of the last "next".
+        let range: std::ops::Range<usize> = self.get_output_head_range(name)?;
+        if range.end > self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: range.end,
+                actual: self.output_value.len(),
+            });
+        }
+        Ok(self.output_value[range].to_vec())
+    }
+    pub fn get_named_output_partial(
+        self: &Self,
+        anticipated_data: &BTreeMap<String, Vec<f64>>,
+    ) -> Result<Vec<f64>> {
+        // Return $\frac{\partial lost}{\partial output}$ of the sum of the weighted head losses,
+        // the heads without anticipated data and the values not in any head have no partial.
+        for name in anticipated_data.keys() {
+            self.get_output_head_range(name)?;
+        }
+        let mut output_partial: Vec<f64> = vec![0.0; self.output_value.len()];
+        let mut start: usize = 0;
+        for head in self.output_heads.iter() {
+            if let Some(head_anticipated) = anticipated_data.get(&head.name) {
+                let head_output: Vec<f64> = self.get_named_output(&head.name)?;
+                let head_partial: Vec<f64> =
+                    get_lost_partial(head.loss_fn_enum, &head_output, head_anticipated)?;
+                for (i, partial) in head_partial.into_iter().enumerate() {
+                    output_partial[start + i] = head.weight * partial;
+                }
+            }
+            start += head.size;
+        }
+        Ok(output_partial)
+    }
+    fn get_output_partial(self: &Self, anticipated_data: &[f64]) -> Result<Vec<f64>> {
+        // Split the anticipated data by the heads, and the values after them use the loss of the network.
+        if self.output_heads.is_empty() {
+            return get_lost_partial(self.loss_fn_enum, &self.output_value, anticipated_data);
+        }
+        if anticipated_data.len() != self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_value.len(),
+                actual: anticipated_data.len(),
+            });
+        }
+        let mut named_anticipated: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for head in self.output_heads.iter() {
+            let range: std::ops::Range<usize> = self.get_output_head_range(&head.name)?;
+            named_anticipated.insert(head.name.clone(), anticipated_data[range].to_vec());
+        }
+        let mut output_partial: Vec<f64> = self.get_named_output_partial(&named_anticipated)?;
+        let head_size: usize = self.output_heads.iter().map(|head| head.size).sum();
+        if head_size < self.output_value.len() {
+            let rest_partial: Vec<f64> = get_lost_partial(
+                self.loss_fn_enum,
+                &self.output_value[head_size..],
+                &anticipated_data[head_size..],
+            )?;
+            output_partial.splice(head_size.., rest_partial);
+        }
+        Ok(output_partial)
+    }
+    pub fn fitting_named(
+        self: &mut Self,
+        anticipated_data: &BTreeMap<String, Vec<f64>>,
+        rate: f64,
+    ) -> Result<()> {
+        // Fit the output of the last "next" with the anticipated data of some heads, such as only the "value" head.
+        let output_partial: Vec<f64> = self.get_named_output_partial(anticipated_data)?;
+        self.backward(output_partial, rate)?;
+        Ok(())
+    }
+    pub fn fitting_named_batch(
+        self: &mut Self,
+        data_pair_list: &[NamedDataPair],
+        rate: f64,
+    ) -> Result<()> {
+        // Fit with the average gradient of the (input, named anticipated data) pairs in one step.
+        self.zero_grad();
+        for (input, anticipated_data) in data_pair_list.iter() {
+            self.set_input(input.clone())?;
+            self.next()?;
+            let output_partial: Vec<f64> = self.get_named_output_partial(anticipated_data)?;
+            self.accumulate_gradients(output_partial)?;
+        }
+        self.apply_gradients(rate);
+        self.zero_grad();
+        Ok(())
+    }
 }
+pub mod binary_format;
+pub mod conv2d;
+pub mod dense_layer;
+pub mod error;
+pub mod flatten;
 pub mod flexible_network;
 pub mod function_segment;
+pub mod gradient_check;
+pub mod initializer;
+pub mod layer_parameters;
+pub mod loss_function;
+pub mod model_file;
+pub mod optimizer;
+pub mod parallel;
+pub mod pooling;
+pub mod registry;
+pub mod schema;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conv2d::Conv2D;
+    use crate::dense_layer::DenseLayer;
+    use crate::flexible_network::{ActivationFunctionEnum, FlexibleNetwork};
+    use crate::function_segment::FunctionSegment;
+    use crate::function_segment::FunctionSegmentFunctionEnum;
+    use crate::initializer::InitializerEnum;
+    use crate::pooling::Pooling2D;
+
+    fn soft_max_pipeline() -> MultiSegNetwork {
+        let mut flexible_net = FlexibleNetwork::new();
+        let input_layer: usize = flexible_net.new_layer(2, 0.0, ActivationFunctionEnum::DoNothing);
+        let hidden_layer: usize = flexible_net.new_layer(4, 0.1, ActivationFunctionEnum::ReLU);
+        let output_layer: usize = flexible_net.new_layer(3, 0.0, ActivationFunctionEnum::DoNothing);
+        flexible_net
+            .connect_layer(
+                input_layer,
+                hidden_layer,
+                InitializerEnum::Constant { value: 0.3 },
+            )
+            .unwrap();
+        for f in hidden_layer..hidden_layer + 4 {
+            for t in output_layer..output_layer + 3 {
+                flexible_net
+                    .connect(f, t, 0.1 * (f + t) as f64 - 1.0)
+                    .unwrap();
+            }
+        }
+        flexible_net.set_input_layer(input_layer).unwrap();
+        flexible_net.set_output_layer(output_layer).unwrap();
+
+        let mut output_function: FunctionSegment = FunctionSegment::new();
+        output_function
+            .set_function(FunctionSegmentFunctionEnum::SoftMax)
+            .unwrap();
+
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(flexible_net).unwrap();
+        multi_seg.push_seg(output_function).unwrap();
+        multi_seg
+    }
+
+    #[test]
+    fn test_multi_seg_network_backward_matches_finite_difference() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        const LOST_WEIGHT: [f64; 3] = [1.0, -2.0, 0.5];
+        const H: f64 = 1e-6;
+        let mut multi_seg: MultiSegNetwork = soft_max_pipeline();
+        let lost = |multi_seg: &mut MultiSegNetwork, input: Vec<f64>| -> f64 {
+            multi_seg.set_input(input).unwrap();
+            multi_seg.next().unwrap();
+            multi_seg
+                .get_output()
+                .iter()
+                .zip(LOST_WEIGHT.iter())
+                .map(|(o, w)| o * w)
+                .sum()
+        };
+
+        lost(&mut multi_seg, Vec::from(INPUT_VALUE));
+        let input_partial: Vec<f64> = multi_seg.backward(Vec::from(LOST_WEIGHT), 0.0).unwrap();
+
+        for i in 0..INPUT_VALUE.len() {
+            let mut input_plus: Vec<f64> = Vec::from(INPUT_VALUE);
+            input_plus[i] += H;
+            let mut input_minus: Vec<f64> = Vec::from(INPUT_VALUE);
+            input_minus[i] -= H;
+            let numerical_partial: f64 =
+                (lost(&mut multi_seg, input_plus) - lost(&mut multi_seg, input_minus)) / (2.0 * H);
+            assert!((numerical_partial - input_partial[i]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_policy_gradient_loss_prefers_the_action_with_advantage() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        let mut multi_seg: MultiSegNetwork = soft_max_pipeline();
+        multi_seg.set_loss_function(LossFunctionEnum::PolicyGradient);
+        let data_pair_list: Vec<[Vec<f64>; 2]> = Vec::from([
+            [Vec::from(INPUT_VALUE), Vec::from([0.0, 1.0, 0.0])],
+            [Vec::from(INPUT_VALUE), Vec::from([-1.0, 0.0, 0.0])],
+        ]);
+        // Action 1 got a positive advantage, and action 0 got a negative one.
+
+        for _ in 0..500 {
+            multi_seg.fitting_batch(&data_pair_list, 0.05).unwrap();
+        }
+
+        multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        multi_seg.next().unwrap();
+        let output: Vec<f64> = multi_seg.get_output();
+        assert!(output[1] > 0.9);
+        assert!(output[0] < 0.05);
+    }
+
+    #[test]
+    fn test_multi_seg_network_fitting_trains_through_soft_max() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        const ANTICIPATED_VALUE: [f64; 3] = [0.7, 0.2, 0.1];
+        let mut multi_seg: MultiSegNetwork = soft_max_pipeline();
+
+        for _ in 0..2000 {
+            multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+            multi_seg.next().unwrap();
+            multi_seg
+                .fitting(Vec::from(ANTICIPATED_VALUE), 0.1)
+                .unwrap();
+        }
+
+        multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        multi_seg.next().unwrap();
+        let output: Vec<f64> = multi_seg.get_output();
+        for i in 0..ANTICIPATED_VALUE.len() {
+            assert!((output[i] - ANTICIPATED_VALUE[i]).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_push_seg_checks_the_shape_between_segments() {
+        let mut first_layer: DenseLayer = DenseLayer::new();
+        first_layer.set_size(2, 4, InitializerEnum::default());
+        let mut wrong_layer: DenseLayer = DenseLayer::new();
+        wrong_layer.set_size(3, 1, InitializerEnum::default());
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(FunctionSegment::new()).unwrap();
+        multi_seg.push_seg(first_layer).unwrap();
+        multi_seg.push_seg(FunctionSegment::new()).unwrap();
+        // The function segments keep the shape of their input.
+
+        assert_eq!(
+            multi_seg.push_seg(wrong_layer),
+            Err(NetworkError::ShapeMismatch {
+                segment: 3,
+                expected: Vec::from([3]),
+                actual: Vec::from([4]),
+            })
+        );
+        assert_eq!(multi_seg.get_input_shape(), Some(Vec::from([2])));
+        assert_eq!(multi_seg.get_output_shape(), Some(Vec::from([4])));
+
+        let mut conv: Conv2D = Conv2D::new();
+        conv.set_size(
+            [1, 5, 5],
+            4,
+            [3, 3],
+            [1, 1],
+            [0, 0],
+            InitializerEnum::default(),
+        )
+        .unwrap();
+        let mut pooling: Pooling2D = Pooling2D::new();
+        pooling.set_size([4, 3, 3], [2, 2], [1, 1]).unwrap();
+        let mut wrong_pooling: Pooling2D = Pooling2D::new();
+        wrong_pooling.set_size([1, 6, 6], [2, 2], [1, 1]).unwrap();
+        let mut output_layer: DenseLayer = DenseLayer::new();
+        output_layer.set_size(16, 2, InitializerEnum::default());
+        let mut screen_seg: MultiSegNetwork = MultiSegNetwork::new();
+        screen_seg.push_seg(conv).unwrap();
+        assert!(screen_seg.push_seg(wrong_pooling).is_err());
+        // The same size but not the same shape.
+        screen_seg.push_seg(pooling).unwrap();
+        screen_seg.push_seg(output_layer).unwrap();
+        // A list takes the values of any shape with the same size.
+        assert_eq!(screen_seg.get_input_shape(), Some(Vec::from([1, 5, 5])));
+
+        let mut agent_seg: MultiSegNetwork = MultiSegNetwork::new();
+        agent_seg.push_seg(screen_seg).unwrap();
+        assert!(agent_seg.push_seg(multi_seg).is_ok());
+        assert!(agent_seg.set_input(vec![0.0; 25]).is_ok());
+    }
+
+    #[test]
+    fn test_next_returns_the_shape_mismatch() {
+        let mut layer: DenseLayer = DenseLayer::new();
+        layer.set_size(2, 4, InitializerEnum::default());
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(FunctionSegment::new()).unwrap();
+        multi_seg.push_seg(layer).unwrap();
+        multi_seg.set_input(vec![0.5; 3]).unwrap();
+        // The function segment takes the values of any size.
+
+        assert_eq!(
+            multi_seg.next(),
+            Err(NetworkError::ShapeMismatch {
+                segment: 1,
+                expected: Vec::from([2]),
+                actual: Vec::from([3]),
+            })
+        );
+        assert_eq!(
+            multi_seg.forward_batch(&[vec![0.5; 3]]),
+            Err(NetworkError::ShapeMismatch {
+                segment: 1,
+                expected: Vec::from([2]),
+                actual: Vec::from([3]),
+            })
+        );
+        // The batch path reports the same error.
+        multi_seg.set_input(vec![0.5; 2]).unwrap();
+        assert!(multi_seg.next().is_ok());
+
+        let mut layer: DenseLayer = DenseLayer::new();
+        layer.set_size(2, 4, InitializerEnum::default());
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(layer).unwrap();
+        assert_eq!(
+            multi_seg.set_input(vec![0.5; 3]),
+            Err(NetworkError::InputSizeMismatch {
+                expected: 2,
+                actual: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_import_rejects_segments_which_do_not_fit() {
+        let mut first_layer: DenseLayer = DenseLayer::new();
+        first_layer.set_size(2, 4, InitializerEnum::default());
+        let mut second_layer: DenseLayer = DenseLayer::new();
+        second_layer.set_size(4, 3, InitializerEnum::default());
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(first_layer).unwrap();
+        multi_seg.push_seg(second_layer).unwrap();
+        let data: String = multi_seg.export_data();
+
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        assert!(imported_seg.import_data(data.clone()).is_ok());
+        assert!(matches!(
+            imported_seg.import_data(data.replace(r#"\"i_len\":4"#, r#"\"i_len\":3"#)),
+            Err(NetworkError::MalformedData(_))
+        ));
+        let mut data: MultiSegNetworkData = serde_json::from_str(&data).unwrap();
+        data.types.swap(0, 1);
+        data.data.swap(0, 1);
+        assert!(matches!(
+            imported_seg.import_data(serde_json::to_string(&data).unwrap()),
+            Err(NetworkError::ShapeMismatch { segment: 1, .. })
+        ));
+    }
+
+    fn actor_critic() -> MultiSegNetwork {
+        let mut agent: MultiSegNetwork = crate::parallel::tests::actor_critic();
+        agent
+            .add_output_head("policy", 3, LossFunctionEnum::PolicyGradient, 1.0)
+            .unwrap();
+        agent
+            .add_output_head("value", 1, LossFunctionEnum::MeanSquaredError, 0.5)
+            .unwrap();
+        agent
+    }
+
+    #[test]
+    fn test_named_output_heads() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        let mut agent: MultiSegNetwork = actor_critic();
+        assert!(matches!(
+            agent.add_output_head("value", 1, LossFunctionEnum::MeanSquaredError, 1.0),
+            Err(NetworkError::MalformedData(_))
+        ));
+        assert!(matches!(
+            agent.add_output_head("entropy", 1, LossFunctionEnum::MeanSquaredError, 1.0),
+            Err(NetworkError::InvalidShape(_))
+        ));
+        assert_eq!(
+            agent.get_output_head_name_list(),
+            Vec::from([String::from("policy"), String::from("value")])
+        );
+
+        agent.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        agent.next().unwrap();
+        let output: Vec<f64> = agent.get_output();
+        assert_eq!(agent.get_named_output("policy").unwrap(), output[..3]);
+        assert_eq!(agent.get_named_output("value").unwrap(), output[3..]);
+        assert_eq!(
+            agent.get_named_output("entropy"),
+            Err(NetworkError::HeadNotFound(String::from("entropy")))
+        );
+
+        let anticipated_data: BTreeMap<String, Vec<f64>> = BTreeMap::from([
+            (String::from("policy"), Vec::from([0.0, 2.0, 0.0])),
+            (String::from("value"), Vec::from([1.0])),
+        ]);
+        let output_partial: Vec<f64> = agent.get_named_output_partial(&anticipated_data).unwrap();
+        let policy_partial: Vec<f64> = get_lost_partial(
+            LossFunctionEnum::PolicyGradient,
+            &output[..3],
+            &Vec::from([0.0, 2.0, 0.0]),
+        )
+        .unwrap();
+        assert_eq!(output_partial[..3], policy_partial[..]);
+        assert_eq!(output_partial[3], 0.5 * 2.0 * (output[3] - 1.0));
+        assert_eq!(
+            agent
+                .get_output_partial(&Vec::from([0.0, 2.0, 0.0, 1.0]))
+                .unwrap(),
+            output_partial
+        );
+        // The flat anticipated data is split by the heads.
+
+        let data: String = agent.export_data();
+        let mut imported_agent: MultiSegNetwork = MultiSegNetwork::new();
+        imported_agent.import_data(data.clone()).unwrap();
+        assert_eq!(imported_agent.get_output_heads(), agent.get_output_heads());
+        assert_eq!(imported_agent.export_data(), data);
+    }
+
+    #[test]
+    fn test_fitting_one_named_head() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        let mut agent: MultiSegNetwork = actor_critic();
+        let data_pair_list: Vec<NamedDataPair> = Vec::from([(
+            Vec::from(INPUT_VALUE),
+            BTreeMap::from([(String::from("value"), Vec::from([0.7]))]),
+        )]);
+        for _ in 0..500 {
+            agent.fitting_named_batch(&data_pair_list, 0.1).unwrap();
+        }
+        agent.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        agent.next().unwrap();
+        assert!((agent.get_named_output("value").unwrap()[0] - 0.7).abs() < 1e-3);
+
+        agent.set_output_head_weight("value", 0.0).unwrap();
+        let policy_output: Vec<f64> = agent.get_named_output("policy").unwrap();
+        agent
+            .fitting_named(
+                &BTreeMap::from([(String::from("value"), Vec::from([-5.0]))]),
+                0.1,
+            )
+            .unwrap();
+        agent.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        agent.next().unwrap();
+        assert_eq!(agent.get_named_output("policy").unwrap(), policy_output);
+        // A zero weight head does not move the shared trunk.
+    }
+
+    #[test]
+    fn test_forward_batch_matches_next() {
+        let input_batch: Vec<Vec<f64>> = Vec::from([
+            Vec::from([0.4, 0.9]),
+            Vec::from([-1.0, 0.2]),
+            Vec::from([0.0, 3.0]),
+        ]);
+        for mut multi_seg in [soft_max_pipeline(), actor_critic()] {
+            let output_batch: Vec<Vec<f64>> = multi_seg.forward_batch(&input_batch).unwrap();
+            assert_eq!(multi_seg.get_output(), output_batch[2]);
+            for (input, output) in input_batch.iter().zip(output_batch.iter()) {
+                multi_seg.set_input(input.clone()).unwrap();
+                multi_seg.next().unwrap();
+                assert_eq!(&multi_seg.get_output(), output);
+            }
+            assert!(multi_seg.forward_batch(&[]).unwrap().is_empty());
+            assert!(multi_seg.forward_batch(&[Vec::from([1.0])]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_fitting_after_forward_batch() {
+        let input_batch: Vec<Vec<f64>> = Vec::from([Vec::from([0.4, 0.9]), Vec::from([-1.0, 0.2])]);
+        for (mut multi_seg, mut single_seg, anticipated) in [
+            (
+                soft_max_pipeline(),
+                soft_max_pipeline(),
+                Vec::from([0.0, 1.0, 0.0]),
+            ),
+            (
+                actor_critic(),
+                actor_critic(),
+                Vec::from([0.0, 1.0, 0.0, 0.5]),
+            ),
+        ] {
+            multi_seg.forward_batch(&input_batch).unwrap();
+            multi_seg.fitting(anticipated.clone(), 0.1).unwrap();
+            single_seg.set_input(input_batch[1].clone()).unwrap();
+            single_seg.next().unwrap();
+            single_seg.fitting(anticipated, 0.1).unwrap();
+            // The dense layers in the segments and the branches keep the last sample of the batch.
+            assert_eq!(multi_seg.parameters(), single_seg.parameters());
+        }
+    }
+
+    #[test]
+    fn test_flat_parameters_and_gradients() {
+        const INPUT_VALUE: [f64; 2] = [0.4, 0.9];
+        const RATE: f64 = 0.1;
+        for (mut multi_seg, parameter_count) in [
+            (soft_max_pipeline(), 2 * 4 + 4 + 4 * 3 + 3),
+            (actor_critic(), 2 * 4 + 4 + 4 * 3 + 3 + 4 + 1),
+        ] {
+            let parameters: Vec<f64> = multi_seg.parameters();
+            assert_eq!(parameters.len(), parameter_count);
+            assert_eq!(multi_seg.parameter_count(), parameter_count);
+            assert_eq!(
+                multi_seg.set_parameters(&parameters[1..]),
+                Err(NetworkError::InputSizeMismatch {
+                    expected: parameter_count,
+                    actual: parameter_count - 1,
+                })
+            );
+
+            let mut target_seg: MultiSegNetwork = MultiSegNetwork::new();
+            target_seg.import_data(multi_seg.export_data()).unwrap();
+            target_seg
+                .set_parameters(&vec![0.0; parameter_count])
+                .unwrap();
+            let average: Vec<f64> = target_seg
+                .parameters()
+                .iter()
+                .zip(parameters.iter())
+                .map(|(target, p)| 0.5 * target + 0.5 * p)
+                .collect();
+            target_seg.set_parameters(&average).unwrap();
+            assert_eq!(target_seg.parameters(), average);
+            // Polyak averaging of a target network.
+
+            multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
+            multi_seg.next().unwrap();
+            let output_partial: Vec<f64> = (0..multi_seg.get_output().len())
+                .map(|i| i as f64 - 1.0)
+                .collect();
+            multi_seg
+                .accumulate_gradients(output_partial.clone())
+                .unwrap();
+            multi_seg.accumulate_gradients(output_partial).unwrap();
+            let gradients: Vec<f64> = multi_seg.gradients();
+            assert_eq!(gradients.len(), parameter_count);
+            assert!(gradients.iter().any(|&gradient| gradient != 0.0));
+            multi_seg.zero_grad();
+            assert_eq!(multi_seg.gradients(), vec![0.0; parameter_count]);
+            multi_seg.set_gradients(&gradients).unwrap();
+            for (x, y) in multi_seg.gradients().iter().zip(gradients.iter()) {
+                assert!((x - y).abs() < 1e-12);
+            }
+            multi_seg.apply_gradients(RATE);
+            for ((p, gradient), updated_p) in parameters
+                .iter()
+                .zip(gradients.iter())
+                .zip(multi_seg.parameters().iter())
+            {
+                assert!((p - RATE * gradient - updated_p).abs() < 1e-12);
+            }
+            // The plain gradient descent steps with the given gradients.
+        }
+    }
 
     #[test]
     #[ignore]
@@ -125,7 +1117,7 @@ mod tests {
         const INPUT_VALUE: [f64; 3] = [1.0, 2.0, 3.0];
         let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
 
-        multi_seg.set_input(Vec::from(INPUT_VALUE));
+        multi_seg.set_input(Vec::from(INPUT_VALUE)).unwrap();
 
         assert_eq!(multi_seg.input_value, Vec::from(INPUT_VALUE));
     }
@@ -0,0 +1,268 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/model_file.rs
+ */
+
+use std::fs;
+use std::io::Write;
+// Write and sync the temporary file
+use std::path::{Path, PathBuf};
+// Path of the model files
+use std::sync::atomic::{AtomicUsize, Ordering};
+// Number the temporary files of the writes
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+
+const CHECKPOINT_MARK: &str = ".ckpt-";
+const CHECKPOINT_STEP_WIDTH: usize = 6;
+// "net.json" at step 100 is checkpointed as "net.ckpt-000100.json".
+static TEMPORARY_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+// Each write gets its own temporary file, even when the threads of a process write the same path.
+
+pub fn write_atomically(path: impl AsRef<Path>, bytes: &[u8]) -> Result<()> {
+    // Write into a temporary file beside the target and rename it, so the target is either the old file or the new one,
+    // even if the program stops in the middle. The missing parent directories are created.
+    let path: &Path = path.as_ref();
+    let file_name: String = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None => {
+            return Err(NetworkError::FileError(format!(
+                "{} is not a file path",
+                path.display()
+            )))
+        }
+    };
+    let directory: &Path = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(directory)?;
+    let temporary_path: PathBuf = path.with_file_name(format!(
+        ".{}.tmp-{}-{}",
+        file_name,
+        std::process::id(),
+        TEMPORARY_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result: std::io::Result<()> = (|| {
+        let mut file: fs::File = fs::File::create(&temporary_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&temporary_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    // Do not leave the half written file behind.
+    result?;
+    sync_directory(directory)?;
+    Ok(())
+}
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> std::io::Result<()> {
+    // The rename is only durable after the directory entry is written to the disk.
+    fs::File::open(directory)?.sync_all()
+}
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> std::io::Result<()> {
+    // Directories can not be opened as files on the other platforms, the rename is flushed by the system.
+    Ok(())
+}
+fn split_file_name(path: &Path) -> Result<(PathBuf, String, String)> {
+    // Return the directory, the file stem and the extension with its dot.
+    let file_name: String = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None => {
+            return Err(NetworkError::FileError(format!(
+                "{} is not a file path",
+                path.display()
+            )))
+        }
+    };
+    let (stem, extension): (String, String) = match file_name.rfind('.') {
+        Some(index) if index > 0 => (
+            file_name[..index].to_string(),
+            file_name[index..].to_string(),
+        ),
+        _ => (file_name, String::new()),
+    };
+    let directory: PathBuf = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok((directory, stem, extension))
+}
+pub fn get_checkpoint_path(path: impl AsRef<Path>, step: usize) -> Result<PathBuf> {
+    let (directory, stem, extension) = split_file_name(path.as_ref())?;
+    Ok(directory.join(format!(
+        "{}{}{:0width$}{}",
+        stem,
+        CHECKPOINT_MARK,
+        step,
+        extension,
+        width = CHECKPOINT_STEP_WIDTH
+    )))
+}
+pub fn get_checkpoint_list(path: impl AsRef<Path>) -> Result<Vec<(usize, PathBuf)>> {
+    // Return the steps and paths of the checkpoints of the model path, from the oldest step to the latest.
+    let (directory, stem, extension) = split_file_name(path.as_ref())?;
+    let prefix: String = format!("{}{}", stem, CHECKPOINT_MARK);
+    let mut checkpoint_list: Vec<(usize, PathBuf)> = Vec::new();
+    if !directory.exists() {
+        return Ok(checkpoint_list);
+    }
+    for entry in fs::read_dir(&directory)? {
+        let entry: fs::DirEntry = entry?;
+        let file_name: String = entry.file_name().to_string_lossy().to_string();
+        if file_name.len() < prefix.len() + extension.len()
+            || !file_name.starts_with(&prefix)
+            || !file_name.ends_with(&extension)
+        {
+            continue;
+        }
+        // The prefix and the extension can overlap, such as the extension ".ckpt-".
+        let step_text: &str = &file_name[prefix.len()..file_name.len() - extension.len()];
+        if !step_text.is_empty() && step_text.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(step) = step_text.parse::<usize>() {
+                checkpoint_list.push((step, entry.path()));
+            }
+        }
+    }
+    checkpoint_list.sort();
+    Ok(checkpoint_list)
+}
+pub fn remove_old_checkpoints(path: impl AsRef<Path>, keep_count: usize) -> Result<()> {
+    // Keep the latest "keep_count" checkpoints of the model path.
+    let checkpoint_list: Vec<(usize, PathBuf)> = get_checkpoint_list(path)?;
+    let remove_count: usize = checkpoint_list.len().saturating_sub(keep_count);
+    for (_, checkpoint_path) in checkpoint_list.into_iter().take(remove_count) {
+        fs::remove_file(checkpoint_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_checkpoint_list, get_checkpoint_path, write_atomically};
+    use crate::multi_seg_network::dense_layer::DenseLayer;
+    use crate::multi_seg_network::error::NetworkError;
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+    use std::path::{Path, PathBuf};
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory: PathBuf = std::env::temp_dir().join(format!(
+            "rust-rl-model-file-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+    fn network(seed: u64) -> MultiSegNetwork {
+        let mut layer: DenseLayer = DenseLayer::new();
+        layer.set_seed(seed);
+        layer.set_size(2, 2, InitializerEnum::XavierUniform);
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(layer).unwrap();
+        multi_seg
+    }
+
+    #[test]
+    fn test_save_and_load_create_the_directories() {
+        let directory: PathBuf = temporary_directory("save");
+        let path: PathBuf = directory.join("model").join("net.json");
+        let mut multi_seg: MultiSegNetwork = network(0);
+        multi_seg.save(&path).unwrap();
+        multi_seg.save(&path).unwrap();
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+        // No temporary file is left.
+
+        let mut loaded_seg: MultiSegNetwork = MultiSegNetwork::new();
+        loaded_seg.load(&path).unwrap();
+        assert_eq!(loaded_seg.export_data(), multi_seg.export_data());
+        assert!(matches!(
+            loaded_seg.load(directory.join("missing.json")),
+            Err(NetworkError::FileError(_))
+        ));
+        assert!(write_atomically(Path::new("/"), b"").is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_threads_write_the_same_path() {
+        let directory: PathBuf = temporary_directory("threads");
+        let path: PathBuf = directory.join("net.bin");
+        let handle_list: Vec<std::thread::JoinHandle<()>> = (0..8)
+            .map(|i| {
+                let path: PathBuf = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        write_atomically(&path, &[i as u8; 64]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handle_list {
+            handle.join().unwrap();
+        }
+        let bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 64);
+        assert!(bytes.iter().all(|&byte| byte == bytes[0]));
+        // The file is written by one of the threads as a whole.
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoints_keep_the_latest_ones() {
+        let directory: PathBuf = temporary_directory("checkpoint");
+        let path: PathBuf = directory.join("net.json");
+        assert_eq!(
+            get_checkpoint_path(&path, 100).unwrap(),
+            directory.join("net.ckpt-000100.json")
+        );
+        assert!(get_checkpoint_list(&path).unwrap().is_empty());
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        assert_eq!(multi_seg.load_latest_checkpoint(&path).unwrap(), None);
+
+        for step in [100, 200, 300, 1000000] {
+            network(step as u64)
+                .save_checkpoint(&path, step, 2)
+                .unwrap();
+        }
+        network(0).save(&path).unwrap();
+        std::fs::write(directory.join("net.ckpt-abc.json"), "").unwrap();
+        assert_eq!(
+            get_checkpoint_list(&path).unwrap(),
+            Vec::from([
+                (300, directory.join("net.ckpt-000300.json")),
+                (1000000, directory.join("net.ckpt-1000000.json")),
+            ])
+        );
+
+        assert_eq!(
+            multi_seg.load_latest_checkpoint(&path).unwrap(),
+            Some(1000000)
+        );
+        assert_eq!(multi_seg.export_data(), network(1000000).export_data());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_list_of_an_overlapping_extension() {
+        let directory: PathBuf = temporary_directory("overlap");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path: PathBuf = directory.join("net.ckpt-");
+        std::fs::write(directory.join("net.ckpt-"), "").unwrap();
+        assert!(get_checkpoint_list(&path).unwrap().is_empty());
+        std::fs::write(get_checkpoint_path(&path, 7).unwrap(), "").unwrap();
+        assert_eq!(
+            get_checkpoint_list(&path).unwrap(),
+            Vec::from([(7, directory.join("net.ckpt-000007.ckpt-"))])
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
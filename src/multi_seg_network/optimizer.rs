@@ -0,0 +1,366 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/optimizer.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+pub struct Optimizer();
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OptimizerEnum {
+    SGD {
+        momentum: f64,
+    },
+    // momentum = 0.0 is the plain gradient descent.
+    Nesterov {
+        momentum: f64,
+    },
+    RMSProp {
+        decay: f64,
+        epsilon: f64,
+    },
+    // Commonly decay = 0.9, epsilon = 1e-8.
+    Adam {
+        beta1: f64,
+        beta2: f64,
+        epsilon: f64,
+    },
+    // Commonly beta1 = 0.9, beta2 = 0.999, epsilon = 1e-8.
+    AdamW {
+        beta1: f64,
+        beta2: f64,
+        epsilon: f64,
+        weight_decay: f64,
+    },
+    // Adam with decoupled weight decay, the decay is not applied on bias terms.
+}
+impl Default for OptimizerEnum {
+    fn default() -> Self {
+        OptimizerEnum::SGD { momentum: 0.0 }
+    }
+}
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OptimizerState {
+    // Per-parameter state of a node, the moments are only allocated when the optimizer needs them.
+    #[serde(default)]
+    t: u64,
+    // Step count, used by the bias correction of Adam.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    w_m: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    w_v: Vec<f64>,
+    // First and second moments of the input weights.
+    #[serde(default)]
+    b_m: f64,
+    #[serde(default)]
+    b_v: f64,
+    // First and second moments of the bias term.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    p_m: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    p_v: Vec<f64>,
+    // First and second moments of the trainable activation parameters.
+}
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Moment {
+    // First and second moments of one parameter, taken out of the state for "Optimizer::update".
+    pub m: f64,
+    pub v: f64,
+}
+impl Moment {
+    fn load(m_list: &[f64], v_list: &[f64], i: usize) -> Self {
+        // The moments which are not allocated are zero.
+        Self {
+            m: m_list.get(i).copied().unwrap_or(0.0),
+            v: v_list.get(i).copied().unwrap_or(0.0),
+        }
+    }
+    fn store(self: &Self, m_list: &mut [f64], v_list: &mut [f64], i: usize) {
+        // Write back the moments which are allocated.
+        if let Some(m) = m_list.get_mut(i) {
+            *m = self.m;
+        }
+        if let Some(v) = v_list.get_mut(i) {
+            *v = self.v;
+        }
+    }
+}
+#[allow(dead_code)]
+impl OptimizerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_empty(self: &Self) -> bool {
+        self.t == 0
+            && self.w_m.is_empty()
+            && self.w_v.is_empty()
+            && self.b_m == 0.0
+            && self.b_v == 0.0
+            && self.p_m.is_empty()
+            && self.p_v.is_empty()
+    }
+    pub fn get_step_count(self: &Self) -> u64 {
+        self.t
+    }
+    fn prepare(self: &mut Self, optimizer_enum: OptimizerEnum, weight_count: usize) {
+        // Allocate the moments which the optimizer needs, and count the step.
+        let (use_m, use_v) = Optimizer::get_moment_usage(optimizer_enum);
+        if use_m {
+            self.w_m.resize(weight_count, 0.0);
+        }
+        if use_v {
+            self.w_v.resize(weight_count, 0.0);
+        }
+        if use_m && use_v {
+            self.t += 1;
+        }
+    }
+}
+#[allow(dead_code)]
+impl Optimizer {
+    pub fn get_moment_usage(optimizer_enum: OptimizerEnum) -> (bool, bool) {
+        // Return whether the optimizer uses the first and the second moment.
+        match optimizer_enum {
+            OptimizerEnum::SGD { momentum } => (momentum != 0.0, false),
+            OptimizerEnum::Nesterov { .. } => (true, false),
+            OptimizerEnum::RMSProp { .. } => (false, true),
+            OptimizerEnum::Adam { .. } => (true, true),
+            OptimizerEnum::AdamW { .. } => (true, true),
+        }
+    }
+    pub fn update(
+        optimizer_enum: OptimizerEnum,
+        t: u64,
+        moment: &mut Moment,
+        parameter: f64,
+        gradient: f64,
+        learning_rate: f64,
+        with_weight_decay: bool,
+    ) -> f64 {
+        // Return the parameter after one step, and update its moments.
+        match optimizer_enum {
+            OptimizerEnum::SGD { momentum } => {
+                moment.m = momentum * moment.m + gradient;
+                parameter - learning_rate * moment.m
+            }
+            OptimizerEnum::Nesterov { momentum } => {
+                moment.m = momentum * moment.m + gradient;
+                parameter - learning_rate * (gradient + momentum * moment.m)
+            }
+            OptimizerEnum::RMSProp { decay, epsilon } => {
+                moment.v = decay * moment.v + (1.0 - decay) * gradient.powi(2);
+                parameter - learning_rate * gradient / (moment.v.sqrt() + epsilon)
+            }
+            OptimizerEnum::Adam {
+                beta1,
+                beta2,
+                epsilon,
+            } => {
+                moment.m = beta1 * moment.m + (1.0 - beta1) * gradient;
+                moment.v = beta2 * moment.v + (1.0 - beta2) * gradient.powi(2);
+                let m_hat: f64 = moment.m / (1.0 - beta1.powi(t as i32));
+                let v_hat: f64 = moment.v / (1.0 - beta2.powi(t as i32));
+                parameter - learning_rate * m_hat / (v_hat.sqrt() + epsilon)
+            }
+            OptimizerEnum::AdamW {
+                beta1,
+                beta2,
+                epsilon,
+                weight_decay,
+            } => {
+                let parameter: f64 = if with_weight_decay {
+                    parameter - learning_rate * weight_decay * parameter
+                } else {
+                    parameter
+                };
+                Optimizer::update(
+                    OptimizerEnum::Adam {
+                        beta1,
+                        beta2,
+                        epsilon,
+                    },
+                    t,
+                    moment,
+                    parameter,
+                    gradient,
+                    learning_rate,
+                    with_weight_decay,
+                )
+            }
+        }
+    }
+    pub fn step(
+        optimizer_enum: OptimizerEnum,
+        state: &mut OptimizerState,
+        w: &mut [f64],
+        w_gradient: &[f64],
+        b: &mut f64,
+        b_gradient: f64,
+        learning_rate: f64,
+    ) {
+        // Update the weights and bias of a node with their gradients.
+        Optimizer::step_weights(optimizer_enum, state, w, w_gradient, learning_rate);
+        let (use_m, use_v) = Optimizer::get_moment_usage(optimizer_enum);
+        let mut moment: Moment = Moment {
+            m: if use_m { state.b_m } else { 0.0 },
+            v: if use_v { state.b_v } else { 0.0 },
+        };
+        *b = Optimizer::update(
+            optimizer_enum,
+            state.t,
+            &mut moment,
+            *b,
+            b_gradient,
+            learning_rate,
+            false,
+        );
+        if use_m {
+            state.b_m = moment.m;
+        }
+        if use_v {
+            state.b_v = moment.v;
+        }
+        // The bias moments are only kept when the optimizer uses them, so the state of SGD stays empty.
+    }
+    pub fn step_weights(
+        optimizer_enum: OptimizerEnum,
+        state: &mut OptimizerState,
+        w: &mut [f64],
+        w_gradient: &[f64],
+        learning_rate: f64,
+    ) {
+        // Count the step and update the weights with the weight decay, "step" calls it before updating the bias term.
+        state.prepare(optimizer_enum, w.len());
+        for i in 0..w.len() {
+            let mut moment: Moment = Moment::load(&state.w_m, &state.w_v, i);
+            w[i] = Optimizer::update(
+                optimizer_enum,
+                state.t,
+                &mut moment,
+                w[i],
+                w_gradient[i],
+                learning_rate,
+                true,
+            );
+            moment.store(&mut state.w_m, &mut state.w_v, i);
+        }
+    }
+    pub fn step_parameters(
+        optimizer_enum: OptimizerEnum,
+        state: &mut OptimizerState,
+        p: &mut [f64],
+        p_gradient: &[f64],
+        learning_rate: f64,
+    ) {
+        // Update other parameters of the node after "step", they share its step count but not the weight decay.
+        let (use_m, use_v) = Optimizer::get_moment_usage(optimizer_enum);
+        if use_m {
+            state.p_m.resize(p.len(), 0.0);
+        }
+        if use_v {
+            state.p_v.resize(p.len(), 0.0);
+        }
+        for i in 0..p.len() {
+            let mut moment: Moment = Moment::load(&state.p_m, &state.p_v, i);
+            p[i] = Optimizer::update(
+                optimizer_enum,
+                state.t,
+                &mut moment,
+                p[i],
+                p_gradient[i],
+                learning_rate,
+                false,
+            );
+            moment.store(&mut state.p_m, &mut state.p_v, i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Optimizer, OptimizerEnum, OptimizerState};
+
+    #[test]
+    fn test_every_optimizer_minimizes_a_quadratic() {
+        // lost := (w - 3.0).powi(2) + (b + 1.0).powi(2)
+        let optimizer_list: [(OptimizerEnum, f64); 5] = [
+            (OptimizerEnum::SGD { momentum: 0.9 }, 0.01),
+            (OptimizerEnum::Nesterov { momentum: 0.9 }, 0.01),
+            (
+                OptimizerEnum::RMSProp {
+                    decay: 0.9,
+                    epsilon: 1e-8,
+                },
+                0.01,
+            ),
+            (
+                OptimizerEnum::Adam {
+                    beta1: 0.9,
+                    beta2: 0.999,
+                    epsilon: 1e-8,
+                },
+                0.05,
+            ),
+            (
+                OptimizerEnum::AdamW {
+                    beta1: 0.9,
+                    beta2: 0.999,
+                    epsilon: 1e-8,
+                    weight_decay: 0.0,
+                },
+                0.05,
+            ),
+        ];
+        for (optimizer_enum, learning_rate) in optimizer_list {
+            let mut state: OptimizerState = OptimizerState::new();
+            let mut w: Vec<f64> = Vec::from([0.0]);
+            let mut b: f64 = 0.0;
+            for _ in 0..2000 {
+                let w_gradient: Vec<f64> = Vec::from([2.0 * (w[0] - 3.0)]);
+                let b_gradient: f64 = 2.0 * (b + 1.0);
+                Optimizer::step(
+                    optimizer_enum,
+                    &mut state,
+                    &mut w,
+                    &w_gradient,
+                    &mut b,
+                    b_gradient,
+                    learning_rate,
+                );
+            }
+            assert!(
+                (w[0] - 3.0).abs() < 0.01,
+                "{:?}: w = {}",
+                optimizer_enum,
+                w[0]
+            );
+            assert!((b + 1.0).abs() < 0.01, "{:?}: b = {}", optimizer_enum, b);
+        }
+    }
+
+    #[test]
+    fn test_plain_sgd_keeps_no_state() {
+        let mut state: OptimizerState = OptimizerState::new();
+        let mut w: Vec<f64> = Vec::from([1.0, 2.0]);
+        let mut b: f64 = 0.5;
+        Optimizer::step(
+            OptimizerEnum::SGD { momentum: 0.0 },
+            &mut state,
+            &mut w,
+            &Vec::from([1.0, -1.0]),
+            &mut b,
+            2.0,
+            0.1,
+        );
+
+        assert_eq!(w, Vec::from([0.9, 2.1]));
+        assert_eq!(b, 0.3);
+        assert!(state.is_empty());
+
+        let mut state: OptimizerState = OptimizerState::new();
+        state.b_m = 0.5;
+        assert!(!state.is_empty());
+        // The moment of the bias term is a state as well.
+    }
+}
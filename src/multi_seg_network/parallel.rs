@@ -0,0 +1,619 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/parallel.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::function_segment::FunctionSegment;
+use crate::multi_seg_network::loss_function::LossFunctionEnum;
+use crate::multi_seg_network::registry::new_segment;
+use crate::multi_seg_network::{check_parameter_count, is_shape_compatible, Segment};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RouteEnum {
+    Broadcast,
+    // Every branch takes the whole input.
+    Split,
+    // Every branch takes the next slice of the input, as long as the size of its input shape.
+}
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MergeEnum {
+    Concat,
+    // Join the outputs of the branches one after another, "get_branch_output" gives them separately.
+    Add,
+    // Add up the outputs of the branches, they should have the same shape.
+}
+pub struct ParallelSegment {
+    // Run several segments side by side, such as the policy head and the value head on a shared trunk.
+    branches: Vec<Box<dyn Segment + 'static>>,
+    route_enum: RouteEnum,
+    merge_enum: MergeEnum,
+    input_value: Vec<f64>,
+    branch_output: Vec<Vec<f64>>,
+    output_value: Vec<f64>,
+    loss_fn_enum: LossFunctionEnum,
+}
+#[derive(Serialize, Deserialize)]
+struct ParallelSegmentData {
+    r: RouteEnum,
+    m: MergeEnum,
+    types: Vec<String>,
+    data: Vec<String>,
+    #[serde(default)]
+    l_fn: LossFunctionEnum,
+}
+#[allow(dead_code)]
+impl Segment for ParallelSegment {
+    fn new() -> Self {
+        Self {
+            branches: Vec::new(),
+            route_enum: RouteEnum::Broadcast,
+            merge_enum: MergeEnum::Concat,
+            input_value: Vec::new(),
+            branch_output: Vec::new(),
+            output_value: Vec::new(),
+            loss_fn_enum: LossFunctionEnum::default(),
+        }
+    }
+    fn get_type(self: &Self) -> String {
+        String::from("ParallelSegment")
+    }
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        match self.route_enum {
+            RouteEnum::Broadcast => {
+                for branch in self.branches.iter_mut() {
+                    branch.set_input(input.clone())?;
+                }
+            }
+            RouteEnum::Split => {
+                let split_size_list: Vec<usize> = self.get_split_size();
+                let input_size: usize = split_size_list.iter().sum();
+                if input.len() != input_size {
+                    return Err(NetworkError::InputSizeMismatch {
+                        expected: input_size,
+                        actual: input.len(),
+                    });
+                }
+                let mut start: usize = 0;
+                for (branch, split_size) in self.branches.iter_mut().zip(split_size_list) {
+                    branch.set_input(input[start..start + split_size].to_vec())?;
+                    start += split_size;
+                }
+            }
+        }
+        self.input_value = input;
+        Ok(())
+    }
+    fn get_output(self: &mut Self) -> Vec<f64> {
+        self.output_value.clone()
+    }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        match self.route_enum {
+            RouteEnum::Broadcast => self
+                .branches
+                .iter()
+                .find_map(|branch| branch.get_input_shape()),
+            RouteEnum::Split => Some(Vec::from([self.get_split_size().iter().sum()])),
+        }
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        let mut output_shape_list: Vec<Vec<usize>> = Vec::new();
+        for index in 0..self.branches.len() {
+            output_shape_list.push(self.get_branch_output_shape(index)?);
+        }
+        // Unknown if any branch keeps the shape of an unknown input.
+        match self.merge_enum {
+            MergeEnum::Concat => Some(Vec::from([output_shape_list
+                .iter()
+                .map(|shape| shape.iter().product::<usize>())
+                .sum()])),
+            MergeEnum::Add => output_shape_list.into_iter().next(),
+        }
+    }
+    fn export_data(self: &mut Self) -> String {
+        let mut data = ParallelSegmentData {
+            r: self.route_enum,
+            m: self.merge_enum,
+            types: Vec::new(),
+            data: Vec::new(),
+            l_fn: self.loss_fn_enum,
+        };
+        for branch in self.branches.iter_mut() {
+            data.types.push(branch.get_type());
+            data.data.push(branch.export_data());
+        }
+        serde_json::to_string(&data).unwrap()
+    }
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: ParallelSegmentData = serde_json::from_str(data.as_str())?;
+        if data.types.len() != data.data.len() {
+            return Err(NetworkError::MalformedData(format!(
+                "{} branch types but {} branch data",
+                data.types.len(),
+                data.data.len()
+            )));
+        }
+        let mut imported_seg: ParallelSegment = ParallelSegment::new();
+        imported_seg.route_enum = data.r;
+        imported_seg.merge_enum = data.m;
+        imported_seg.loss_fn_enum = data.l_fn;
+        for i in 0..data.types.len() {
+            let mut branch = new_segment(&data.types[i])?;
+            branch.import_data(data.data[i].clone())?;
+            imported_seg.push_boxed_branch(branch)?;
+        }
+        *self = imported_seg;
+        Ok(())
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        self.branch_output = Vec::new();
+        for branch in self.branches.iter_mut() {
+            branch.next()?;
+            self.branch_output.push(branch.get_output());
+        }
+        self.output_value = self.get_merged_output(&self.branch_output)?;
+        Ok(())
+    }
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        let mut branch_output_batch: Vec<Vec<Vec<f64>>> = Vec::new();
+        // Indexed by [branch][sample].
+        match self.route_enum {
+            RouteEnum::Broadcast => {
+                for branch in self.branches.iter_mut() {
+                    branch_output_batch.push(branch.forward_batch(input_batch)?);
+                }
+            }
+            RouteEnum::Split => {
+                let split_size_list: Vec<usize> = self.get_split_size();
+                let input_size: usize = split_size_list.iter().sum();
+                for input in input_batch.iter() {
+                    if input.len() != input_size {
+                        return Err(NetworkError::InputSizeMismatch {
+                            expected: input_size,
+                            actual: input.len(),
+                        });
+                    }
+                }
+                let mut start: usize = 0;
+                for (branch, split_size) in self.branches.iter_mut().zip(split_size_list) {
+                    let slice_batch: Vec<Vec<f64>> = input_batch
+                        .iter()
+                        .map(|input| input[start..start + split_size].to_vec())
+                        .collect();
+                    branch_output_batch.push(branch.forward_batch(&slice_batch)?);
+                    start += split_size;
+                }
+            }
+        }
+        let mut output_batch: Vec<Vec<f64>> = Vec::with_capacity(input_batch.len());
+        for sample in 0..input_batch.len() {
+            self.branch_output = branch_output_batch
+                .iter_mut()
+                .map(|output_batch| std::mem::take(&mut output_batch[sample]))
+                .collect();
+            output_batch.push(self.get_merged_output(&self.branch_output)?);
+        }
+        // The branch outputs of the last sample are kept, like "next".
+        if let (Some(input), Some(output)) = (input_batch.last(), output_batch.last()) {
+            self.input_value = input.clone();
+            self.output_value = output.clone();
+        }
+        Ok(output_batch)
+    }
+    fn get_loss_function(self: &Self) -> LossFunctionEnum {
+        self.loss_fn_enum
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_value.len(),
+                actual: output_partial.len(),
+            });
+        }
+        let mut input_partial: Vec<f64> = match self.route_enum {
+            RouteEnum::Broadcast => vec![0.0; self.input_value.len()],
+            RouteEnum::Split => Vec::new(),
+        };
+        let mut start: usize = 0;
+        for (branch, output) in self.branches.iter_mut().zip(self.branch_output.iter()) {
+            let branch_partial: Vec<f64> = match self.merge_enum {
+                MergeEnum::Concat => output_partial[start..start + output.len()].to_vec(),
+                MergeEnum::Add => output_partial.clone(),
+            };
+            start += output.len();
+            let branch_input_partial: Vec<f64> = branch.accumulate_gradients(branch_partial)?;
+            match self.route_enum {
+                RouteEnum::Broadcast => input_partial
+                    .iter_mut()
+                    .zip(branch_input_partial.iter())
+                    .for_each(|(sum, partial)| *sum += partial),
+                RouteEnum::Split => input_partial.extend(branch_input_partial),
+            }
+        }
+        Ok(input_partial)
+    }
+    fn parameters(self: &Self) -> Vec<f64> {
+        self.branches
+            .iter()
+            .flat_map(|branch| branch.parameters())
+            .collect::<Vec<f64>>()
+    }
+    fn parameter_count(self: &Self) -> usize {
+        self.branches
+            .iter()
+            .map(|branch| branch.parameter_count())
+            .sum()
+    }
+    fn set_parameters(self: &mut Self, parameters: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), parameters.len())?;
+        let mut start: usize = 0;
+        for branch in self.branches.iter_mut() {
+            let count: usize = branch.parameter_count();
+            branch.set_parameters(&parameters[start..start + count])?;
+            start += count;
+        }
+        Ok(())
+    }
+    fn gradients(self: &Self) -> Vec<f64> {
+        self.branches
+            .iter()
+            .flat_map(|branch| branch.gradients())
+            .collect::<Vec<f64>>()
+    }
+    fn set_gradients(self: &mut Self, gradients: &[f64]) -> Result<()> {
+        check_parameter_count(self.parameter_count(), gradients.len())?;
+        let mut start: usize = 0;
+        for branch in self.branches.iter_mut() {
+            let count: usize = branch.parameter_count();
+            branch.set_gradients(&gradients[start..start + count])?;
+            start += count;
+        }
+        Ok(())
+    }
+    fn apply_gradients(self: &mut Self, rate: f64) {
+        for branch in self.branches.iter_mut() {
+            branch.apply_gradients(rate);
+        }
+    }
+    fn zero_grad(self: &mut Self) {
+        for branch in self.branches.iter_mut() {
+            branch.zero_grad();
+        }
+    }
+    fn reset_state(self: &mut Self) {
+        for branch in self.branches.iter_mut() {
+            branch.reset_state();
+        }
+    }
+}
+#[allow(dead_code)]
+impl ParallelSegment {
+    pub fn residual(segment: (impl Segment + 'static)) -> Result<Self> {
+        // $output = input + segment(input)$, the segment should keep the shape of its input.
+        let mut residual_seg: ParallelSegment = ParallelSegment::new();
+        residual_seg.set_merge(MergeEnum::Add);
+        residual_seg.push_branch(FunctionSegment::new())?;
+        residual_seg.push_branch(segment)?;
+        Ok(residual_seg)
+    }
+    pub fn concat_with_input(segment: (impl Segment + 'static)) -> Result<Self> {
+        // $output = [input, segment(input)]$
+        let mut concat_seg: ParallelSegment = ParallelSegment::new();
+        concat_seg.push_branch(FunctionSegment::new())?;
+        concat_seg.push_branch(segment)?;
+        Ok(concat_seg)
+    }
+    fn get_merged_output(self: &Self, branch_output: &[Vec<f64>]) -> Result<Vec<f64>> {
+        match self.merge_enum {
+            MergeEnum::Concat => Ok(branch_output.concat()),
+            MergeEnum::Add => {
+                let mut output_value: Vec<f64> = Vec::new();
+                for (index, output) in branch_output.iter().enumerate() {
+                    if index == 0 {
+                        output_value = output.clone();
+                    } else if output.len() != output_value.len() {
+                        return Err(NetworkError::ShapeMismatch {
+                            segment: index,
+                            expected: Vec::from([output_value.len()]),
+                            actual: Vec::from([output.len()]),
+                        });
+                        // The branches of unknown output shapes are only compared here.
+                    } else {
+                        output_value
+                            .iter_mut()
+                            .zip(output.iter())
+                            .for_each(|(sum, value)| *sum += value);
+                    }
+                }
+                Ok(output_value)
+            }
+        }
+    }
+    fn get_split_size(self: &Self) -> Vec<usize> {
+        self.branches
+            .iter()
+            .map(|branch| {
+                branch
+                    .get_input_shape()
+                    .map_or(0, |shape| shape.iter().product())
+            })
+            .collect::<Vec<usize>>()
+    }
+    fn get_branch_output_shape(self: &Self, index: usize) -> Option<Vec<usize>> {
+        let branch: &dyn Segment = self.branches[index].as_ref();
+        branch.get_output_shape().or(match self.route_enum {
+            RouteEnum::Broadcast => self.get_input_shape(),
+            RouteEnum::Split => branch.get_input_shape(),
+        })
+    }
+    fn push_boxed_branch(self: &mut Self, branch: Box<dyn Segment + 'static>) -> Result<usize> {
+        let index: usize = self.branches.len();
+        let input_shape: Option<Vec<usize>> = branch.get_input_shape();
+        match (self.route_enum, &input_shape) {
+            (RouteEnum::Broadcast, Some(input_shape)) => {
+                if let Some(last_input_shape) = self.get_input_shape() {
+                    if !is_shape_compatible(&last_input_shape, input_shape) {
+                        return Err(NetworkError::ShapeMismatch {
+                            segment: index,
+                            expected: input_shape.clone(),
+                            actual: last_input_shape,
+                        });
+                    }
+                }
+            }
+            (RouteEnum::Split, None) => {
+                return Err(NetworkError::InvalidShape(format!(
+                    "branch {} of type {} does not declare the size of its slice",
+                    index,
+                    branch.get_type()
+                )));
+            }
+            _ => {}
+        }
+        self.branches.push(branch);
+        if self.merge_enum == MergeEnum::Add {
+            let output_shape_list: Vec<Vec<usize>> = (0..self.branches.len())
+                .filter_map(|i| self.get_branch_output_shape(i))
+                .collect();
+            // The branches keeping the input shape are known after the new one declares it.
+            if let Some(output_shape) = output_shape_list
+                .iter()
+                .find(|&shape| shape != &output_shape_list[0])
+            {
+                let output_shape: Vec<usize> = output_shape.clone();
+                self.branches.pop();
+                return Err(NetworkError::ShapeMismatch {
+                    segment: index,
+                    expected: output_shape_list[0].clone(),
+                    actual: output_shape,
+                });
+            }
+        }
+        // The added outputs should have the same shape.
+        Ok(index)
+    }
+    pub fn push_branch(self: &mut Self, segment: (impl Segment + 'static)) -> Result<usize> {
+        // Append a branch and return its index, if it can work with the branches before.
+        self.push_boxed_branch(Box::new(segment))
+    }
+    pub fn set_route(self: &mut Self, route_enum: RouteEnum) {
+        // Select how the input is given to the branches, set it before pushing the branches.
+        self.route_enum = route_enum;
+    }
+    pub fn set_merge(self: &mut Self, merge_enum: MergeEnum) {
+        // Select how the outputs of the branches are joined, set it before pushing the branches.
+        self.merge_enum = merge_enum;
+    }
+    pub fn set_loss_function(self: &mut Self, loss_fn_enum: LossFunctionEnum) {
+        // Select the loss of the output used by "fitting_batch".
+        self.loss_fn_enum = loss_fn_enum;
+    }
+    pub fn get_branch_count(self: &Self) -> usize {
+        self.branches.len()
+    }
+    pub fn get_branch_output(self: &Self, index: usize) -> Vec<f64> {
+        // Return the output of a branch in the last "next".
+        self.branch_output[index].clone()
+    }
+    pub fn operate_branch(
+        self: &mut Self,
+        index: usize,
+        call_back: impl FnOnce(&mut Box<dyn Segment + 'static>),
+    ) {
+        call_back(&mut self.branches[index]);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{MergeEnum, ParallelSegment, RouteEnum};
+    use crate::multi_seg_network::dense_layer::DenseLayer;
+    use crate::multi_seg_network::error::NetworkError;
+    use crate::multi_seg_network::flexible_network::ActivationFunctionEnum;
+    use crate::multi_seg_network::function_segment::{
+        FunctionSegment, FunctionSegmentFunctionEnum,
+    };
+    use crate::multi_seg_network::gradient_check::gradient_check;
+    use crate::multi_seg_network::initializer::InitializerEnum;
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+
+    fn dense_layer(
+        input_size: usize,
+        output_size: usize,
+        activation_fn_enum: ActivationFunctionEnum,
+        seed: u64,
+    ) -> DenseLayer {
+        let mut layer: DenseLayer = DenseLayer::new();
+        layer.set_seed(seed);
+        layer.set_activation_function(activation_fn_enum);
+        layer.set_size(input_size, output_size, InitializerEnum::XavierUniform);
+        layer
+    }
+
+    pub(crate) fn actor_critic() -> MultiSegNetwork {
+        // A shared trunk feeding the policy head and the value head, the tests of "MultiSegNetwork" name them.
+        let mut policy_head: MultiSegNetwork = MultiSegNetwork::new();
+        policy_head
+            .push_seg(dense_layer(4, 3, ActivationFunctionEnum::DoNothing, 1))
+            .unwrap();
+        let mut soft_max: FunctionSegment = FunctionSegment::new();
+        soft_max
+            .set_function(FunctionSegmentFunctionEnum::SoftMax)
+            .unwrap();
+        policy_head.push_seg(soft_max).unwrap();
+        let mut heads: ParallelSegment = ParallelSegment::new();
+        heads.push_branch(policy_head).unwrap();
+        heads
+            .push_branch(dense_layer(4, 1, ActivationFunctionEnum::DoNothing, 2))
+            .unwrap();
+
+        let mut agent: MultiSegNetwork = MultiSegNetwork::new();
+        agent
+            .push_seg(dense_layer(2, 4, ActivationFunctionEnum::Tanh, 0))
+            .unwrap();
+        agent.push_seg(heads).unwrap();
+        agent
+    }
+
+    #[test]
+    fn test_shared_trunk_with_two_heads() {
+        const INPUT_VALUE: [f64; 2] = [0.4, -0.9];
+        let mut agent: MultiSegNetwork = actor_critic();
+        assert_eq!(agent.get_output_shape(), Some(Vec::from([4])));
+
+        let report = gradient_check(
+            &mut agent,
+            &Vec::from(INPUT_VALUE),
+            &Vec::from([0.0, 1.0, 0.0, 0.5]),
+            1e-6,
+        )
+        .unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+
+        let output: Vec<f64> = agent.get_output();
+        assert!((output[..3].iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        agent.operate_seg(1, |heads| {
+            assert_eq!(heads.get_output(), output);
+        });
+
+        let data: String = agent.export_data();
+        let mut imported_agent: MultiSegNetwork = MultiSegNetwork::new();
+        imported_agent.import_data(data.clone()).unwrap();
+        imported_agent.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        imported_agent.next().unwrap();
+        assert_eq!(imported_agent.get_output(), output);
+        assert_eq!(imported_agent.export_data(), data);
+    }
+
+    #[test]
+    fn test_branch_outputs_are_kept_separately() {
+        let mut heads: ParallelSegment = ParallelSegment::new();
+        heads
+            .push_branch(dense_layer(2, 3, ActivationFunctionEnum::DoNothing, 1))
+            .unwrap();
+        heads
+            .push_branch(dense_layer(2, 1, ActivationFunctionEnum::DoNothing, 2))
+            .unwrap();
+        heads.set_input(Vec::from([1.0, 2.0])).unwrap();
+        heads.next().unwrap();
+
+        assert_eq!(heads.get_branch_count(), 2);
+        assert_eq!(heads.get_branch_output(0).len(), 3);
+        assert_eq!(
+            [heads.get_branch_output(0), heads.get_branch_output(1)].concat(),
+            heads.get_output()
+        );
+        assert!(heads
+            .push_branch(dense_layer(3, 1, ActivationFunctionEnum::DoNothing, 3))
+            .is_err());
+    }
+
+    #[test]
+    fn test_split_routes_the_slices_of_the_input() {
+        let mut split_seg: ParallelSegment = ParallelSegment::new();
+        split_seg.set_route(RouteEnum::Split);
+        split_seg
+            .push_branch(dense_layer(2, 1, ActivationFunctionEnum::Tanh, 1))
+            .unwrap();
+        split_seg
+            .push_branch(dense_layer(3, 2, ActivationFunctionEnum::Sigmoid, 2))
+            .unwrap();
+        assert!(matches!(
+            split_seg.push_branch(FunctionSegment::new()),
+            Err(NetworkError::InvalidShape(_))
+        ));
+        assert_eq!(split_seg.get_input_shape(), Some(Vec::from([5])));
+        assert_eq!(split_seg.get_output_shape(), Some(Vec::from([3])));
+
+        let input_value: Vec<f64> = Vec::from([0.1, 0.2, 0.3, 0.4, 0.5]);
+        split_seg.set_input(input_value.clone()).unwrap();
+        split_seg.next().unwrap();
+        let mut first_layer: DenseLayer = dense_layer(2, 1, ActivationFunctionEnum::Tanh, 1);
+        first_layer.set_input(input_value[..2].to_vec()).unwrap();
+        first_layer.next().unwrap();
+        assert_eq!(split_seg.get_branch_output(0), first_layer.get_output());
+        let output: Vec<f64> = split_seg.get_output();
+        let output_batch: Vec<Vec<f64>> = split_seg
+            .forward_batch(&[Vec::from([0.0; 5]), input_value.clone()])
+            .unwrap();
+        assert_eq!(output_batch[1], output);
+        assert_eq!(split_seg.get_branch_output(0), first_layer.get_output());
+
+        let report = gradient_check(
+            &mut split_seg,
+            &input_value,
+            &Vec::from([1.0, 0.0, 1.0]),
+            1e-6,
+        )
+        .unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+    }
+
+    #[test]
+    fn test_residual_and_concat_with_input() {
+        let input_value: Vec<f64> = Vec::from([0.5, -1.0, 2.0]);
+        let mut layer: DenseLayer = dense_layer(3, 3, ActivationFunctionEnum::Tanh, 4);
+        layer.set_input(input_value.clone()).unwrap();
+        layer.next().unwrap();
+        let layer_output: Vec<f64> = layer.get_output();
+
+        let mut residual_seg: ParallelSegment = ParallelSegment::residual(layer).unwrap();
+        residual_seg.set_input(input_value.clone()).unwrap();
+        residual_seg.next().unwrap();
+        for i in 0..3 {
+            assert_eq!(
+                residual_seg.get_output()[i],
+                input_value[i] + layer_output[i]
+            );
+        }
+        let report = gradient_check(
+            &mut residual_seg,
+            &input_value,
+            &Vec::from([1.0, 0.0, 1.0]),
+            1e-6,
+        )
+        .unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+        assert_eq!(report.parameter_error.len(), 3 * 3 + 3);
+        assert!(matches!(
+            ParallelSegment::residual(dense_layer(3, 2, ActivationFunctionEnum::Tanh, 4)),
+            Err(NetworkError::ShapeMismatch { segment: 1, .. })
+        ));
+
+        let mut concat_seg: ParallelSegment =
+            ParallelSegment::concat_with_input(dense_layer(3, 2, ActivationFunctionEnum::Tanh, 4))
+                .unwrap();
+        assert_eq!(concat_seg.get_output_shape(), Some(Vec::from([5])));
+        concat_seg.set_input(input_value.clone()).unwrap();
+        concat_seg.next().unwrap();
+        assert_eq!(concat_seg.get_output()[..3], input_value[..]);
+        let mut add_seg: ParallelSegment = ParallelSegment::new();
+        add_seg.set_merge(MergeEnum::Add);
+        add_seg.push_branch(FunctionSegment::new()).unwrap();
+        add_seg.push_branch(FunctionSegment::new()).unwrap();
+        assert_eq!(add_seg.get_output_shape(), None);
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/pooling.rs
+ */
+
+use serde::{Deserialize, Serialize};
+// Make the customize struct be able to json stringify
+
+use crate::multi_seg_network::conv2d::get_output_length;
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::Segment;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PoolingEnum {
+    Max,
+    Average,
+}
+pub struct Pooling2D {
+    // Pool every channel of the values in the order of [channel][row][column].
+    pooling_enum: PoolingEnum,
+    input_shape: [usize; 3],
+    output_shape: [usize; 3],
+    // [channel count, height, width]
+    pool_size: [usize; 2],
+    stride: [usize; 2],
+    // [height, width]
+    input_value: Vec<f64>,
+    output_value: Vec<f64>,
+    max_index: Vec<usize>,
+    // Input index of the max value of every output, the partial only flows back to it.
+}
+#[derive(Serialize, Deserialize)]
+struct Pooling2DData {
+    p_fn: PoolingEnum,
+    i_s: [usize; 3],
+    p_s: [usize; 2],
+    s: [usize; 2],
+}
+#[allow(dead_code)]
+impl Segment for Pooling2D {
+    fn new() -> Self {
+        Self {
+            pooling_enum: PoolingEnum::Max,
+            input_shape: [0; 3],
+            output_shape: [0; 3],
+            pool_size: [1; 2],
+            stride: [1; 2],
+            input_value: Vec::new(),
+            output_value: Vec::new(),
+            max_index: Vec::new(),
+        }
+    }
+    fn get_type(self: &Self) -> String {
+        String::from("Pooling2D")
+    }
+    fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+        let input_size: usize = self.input_shape.iter().product();
+        if input.len() != input_size {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: input_size,
+                actual: input.len(),
+            });
+        }
+        self.input_value = input;
+        Ok(())
+    }
+    fn get_output(self: &mut Self) -> Vec<f64> {
+        self.output_value.clone()
+    }
+    fn get_input_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from(self.input_shape))
+    }
+    fn get_output_shape(self: &Self) -> Option<Vec<usize>> {
+        Some(Vec::from(self.output_shape))
+    }
+    fn export_data(self: &mut Self) -> String {
+        let data = Pooling2DData {
+            p_fn: self.pooling_enum,
+            i_s: self.input_shape,
+            p_s: self.pool_size,
+            s: self.stride,
+        };
+        serde_json::to_string(&data).unwrap()
+    }
+    fn import_data(self: &mut Self, data: String) -> Result<()> {
+        let data: Pooling2DData = serde_json::from_str(data.as_str())?;
+        self.set_pooling(data.p_fn);
+        self.set_size(data.i_s, data.p_s, data.s)?;
+        Ok(())
+    }
+    fn next(self: &mut Self) -> Result<()> {
+        (self.output_value, self.max_index) =
+            self.calc_value(&self.get_pool_list(), &self.input_value);
+        Ok(())
+    }
+    fn forward_batch(self: &mut Self, input_batch: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        // Pool every input of the batch from its slice, only the last one is kept for "accumulate_gradients".
+        let input_size: usize = self.input_shape.iter().product();
+        for input in input_batch.iter() {
+            if input.len() != input_size {
+                return Err(NetworkError::InputSizeMismatch {
+                    expected: input_size,
+                    actual: input.len(),
+                });
+            }
+        }
+        let pool_list: Vec<Vec<usize>> = self.get_pool_list();
+        let mut output_batch: Vec<Vec<f64>> = Vec::with_capacity(input_batch.len());
+        for input in input_batch.iter() {
+            let (output_value, max_index) = self.calc_value(&pool_list, input);
+            output_batch.push(output_value);
+            self.max_index = max_index;
+        }
+        if let (Some(input), Some(output)) = (input_batch.last(), output_batch.last()) {
+            self.input_value = input.clone();
+            self.output_value = output.clone();
+        }
+        Ok(output_batch)
+    }
+    fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+        if output_partial.len() != self.output_value.len() {
+            return Err(NetworkError::InputSizeMismatch {
+                expected: self.output_value.len(),
+                actual: output_partial.len(),
+            });
+        }
+        let mut input_partial: Vec<f64> = vec![0.0; self.input_value.len()];
+        match self.pooling_enum {
+            PoolingEnum::Max => {
+                for (&index, partial) in self.max_index.iter().zip(output_partial.iter()) {
+                    input_partial[index] += partial;
+                }
+            }
+            PoolingEnum::Average => {
+                for (index_list, partial) in self.get_pool_list().iter().zip(output_partial.iter())
+                {
+                    for &index in index_list.iter() {
+                        input_partial[index] += partial / index_list.len() as f64;
+                    }
+                }
+            }
+        }
+        // Overlapping pools add up their partial.
+        Ok(input_partial)
+    }
+}
+#[allow(dead_code)]
+impl Pooling2D {
+    fn get_pool_list(self: &Self) -> Vec<Vec<usize>> {
+        // Return the input indexes under every pool, in the order of the output values.
+        let [channels, input_height, input_width] = self.input_shape;
+        let [_, output_height, output_width] = self.output_shape;
+        let mut pool_list: Vec<Vec<usize>> = Vec::new();
+        for c in 0..channels {
+            for oy in 0..output_height {
+                for ox in 0..output_width {
+                    let mut index_list: Vec<usize> = Vec::new();
+                    for py in 0..self.pool_size[0] {
+                        for px in 0..self.pool_size[1] {
+                            index_list.push(
+                                (c * input_height + oy * self.stride[0] + py) * input_width
+                                    + ox * self.stride[1]
+                                    + px,
+                            );
+                        }
+                    }
+                    pool_list.push(index_list);
+                }
+            }
+        }
+        pool_list
+    }
+    fn calc_value(
+        self: &Self,
+        pool_list: &[Vec<usize>],
+        input_value: &[f64],
+    ) -> (Vec<f64>, Vec<usize>) {
+        // Return the pooled values of the input, and the input index of every max value.
+        let mut output_value: Vec<f64> = Vec::new();
+        let mut max_index: Vec<usize> = Vec::new();
+        for index_list in pool_list.iter() {
+            match self.pooling_enum {
+                PoolingEnum::Max => {
+                    let index: usize = index_list
+                        .iter()
+                        .cloned()
+                        .reduce(|a, b| {
+                            if input_value[b] > input_value[a] {
+                                b
+                            } else {
+                                a
+                            }
+                        })
+                        .unwrap();
+                    output_value.push(input_value[index]);
+                    max_index.push(index);
+                }
+                PoolingEnum::Average => {
+                    let sum: f64 = index_list.iter().map(|&i| input_value[i]).sum();
+                    output_value.push(sum / index_list.len() as f64);
+                }
+            }
+        }
+        (output_value, max_index)
+    }
+    pub fn set_pooling(self: &mut Self, pooling_enum: PoolingEnum) {
+        self.pooling_enum = pooling_enum;
+    }
+    pub fn set_size(
+        self: &mut Self,
+        input_shape: [usize; 3],
+        pool_size: [usize; 2],
+        stride: [usize; 2],
+    ) -> Result<()> {
+        // Set the [channel count, height, width] of the input, the [height, width] of the pool and stride.
+        let output_height: usize = get_output_length(input_shape[1], pool_size[0], stride[0], 0)?;
+        let output_width: usize = get_output_length(input_shape[2], pool_size[1], stride[1], 0)?;
+        self.input_shape = input_shape;
+        self.output_shape = [input_shape[0], output_height, output_width];
+        self.pool_size = pool_size;
+        self.stride = stride;
+        self.input_value.clear();
+        self.output_value.clear();
+        self.max_index.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pooling2D, PoolingEnum};
+    use crate::multi_seg_network::gradient_check::gradient_check;
+    use crate::multi_seg_network::Segment;
+
+    const INPUT_VALUE: [f64; 16] = [
+        1.0, 5.0, 2.0, 0.0, //
+        3.0, 4.0, 8.0, 6.0, //
+        0.0, 2.0, 1.0, 1.0, //
+        7.0, 1.0, 3.0, 9.0, //
+    ];
+
+    #[test]
+    fn test_max_pooling_routes_the_partial_to_the_max() {
+        let mut pooling: Pooling2D = Pooling2D::new();
+        pooling.set_size([1, 4, 4], [2, 2], [2, 2]).unwrap();
+
+        pooling.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        pooling.next().unwrap();
+        let input_partial: Vec<f64> = pooling
+            .accumulate_gradients(Vec::from([1.0, 2.0, 3.0, 4.0]))
+            .unwrap();
+
+        assert_eq!(pooling.get_output(), Vec::from([5.0, 8.0, 7.0, 9.0]));
+        let mut expected_partial: Vec<f64> = vec![0.0; 16];
+        expected_partial[1] = 1.0;
+        expected_partial[6] = 2.0;
+        expected_partial[12] = 3.0;
+        expected_partial[15] = 4.0;
+        assert_eq!(input_partial, expected_partial);
+
+        let reversed_value: Vec<f64> = INPUT_VALUE.iter().rev().cloned().collect::<Vec<f64>>();
+        assert_eq!(
+            pooling
+                .forward_batch(&[reversed_value, Vec::from(INPUT_VALUE)])
+                .unwrap(),
+            Vec::from([
+                Vec::from([9.0, 7.0, 8.0, 5.0]),
+                Vec::from([5.0, 8.0, 7.0, 9.0])
+            ])
+        );
+        assert_eq!(
+            pooling
+                .accumulate_gradients(Vec::from([1.0, 2.0, 3.0, 4.0]))
+                .unwrap(),
+            expected_partial
+        );
+        // The max indexes of the last input are kept.
+    }
+
+    #[test]
+    fn test_average_pooling_with_overlapping_pools() {
+        let mut pooling: Pooling2D = Pooling2D::new();
+        pooling.set_pooling(PoolingEnum::Average);
+        pooling.set_size([1, 4, 4], [3, 3], [1, 1]).unwrap();
+
+        pooling.set_input(Vec::from(INPUT_VALUE)).unwrap();
+        pooling.next().unwrap();
+
+        assert_eq!(pooling.get_output_shape(), Some(Vec::from([1, 2, 2])));
+        assert_eq!(
+            pooling.get_output(),
+            Vec::from([26.0 / 9.0, 29.0 / 9.0, 29.0 / 9.0, 35.0 / 9.0])
+        );
+        let report = gradient_check(
+            &mut pooling,
+            &Vec::from(INPUT_VALUE),
+            &Vec::from([0.0, 1.0, 2.0, 3.0]),
+            1e-6,
+        )
+        .unwrap();
+        assert!(report.get_worst_error() < 1e-6, "{:?}", report);
+        assert!(pooling.set_size([1, 4, 4], [5, 5], [1, 1]).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/registry.rs
+ */
+
+use std::collections::BTreeMap;
+// BTreeMap Type, list the registered types in order
+use std::sync::{OnceLock, RwLock};
+// The registry is shared by every MultiSegNetwork, including the nested ones.
+
+use crate::multi_seg_network::conv2d::Conv2D;
+use crate::multi_seg_network::dense_layer::DenseLayer;
+use crate::multi_seg_network::error::{NetworkError, Result};
+use crate::multi_seg_network::flatten::Flatten;
+use crate::multi_seg_network::flexible_network::FlexibleNetwork;
+use crate::multi_seg_network::function_segment::FunctionSegment;
+use crate::multi_seg_network::parallel::ParallelSegment;
+use crate::multi_seg_network::pooling::Pooling2D;
+use crate::multi_seg_network::{MultiSegNetwork, Segment};
+
+pub type SegmentConstructor = fn() -> Box<dyn Segment + 'static>;
+static SEGMENT_REGISTRY: OnceLock<RwLock<BTreeMap<String, SegmentConstructor>>> = OnceLock::new();
+// Constructors keyed by the name given by "Segment::get_type".
+
+fn new_boxed_segment<T: Segment + 'static>() -> Box<dyn Segment + 'static> {
+    Box::new(T::new())
+}
+fn get_registry() -> &'static RwLock<BTreeMap<String, SegmentConstructor>> {
+    SEGMENT_REGISTRY.get_or_init(|| {
+        let mut constructor_map: BTreeMap<String, SegmentConstructor> = BTreeMap::new();
+        for constructor in [
+            new_boxed_segment::<MultiSegNetwork> as SegmentConstructor,
+            new_boxed_segment::<FlexibleNetwork>,
+            new_boxed_segment::<FunctionSegment>,
+            new_boxed_segment::<DenseLayer>,
+            new_boxed_segment::<Conv2D>,
+            new_boxed_segment::<Pooling2D>,
+            new_boxed_segment::<Flatten>,
+            new_boxed_segment::<ParallelSegment>,
+        ] {
+            constructor_map.insert(constructor().get_type(), constructor);
+        }
+        // The built-in segments are always registered.
+        RwLock::new(constructor_map)
+    })
+}
+#[allow(dead_code)]
+pub fn register_segment<T: Segment + 'static>() {
+    // Register the segment type under the name given by its "get_type", so "MultiSegNetwork::import_data" can build it.
+    // Registering a name again replaces the constructor.
+    register_segment_constructor(T::new().get_type(), new_boxed_segment::<T>);
+}
+#[allow(dead_code)]
+pub fn register_segment_constructor(type_name: String, constructor: SegmentConstructor) {
+    get_registry()
+        .write()
+        .unwrap()
+        .insert(type_name, constructor);
+}
+#[allow(dead_code)]
+pub fn is_registered(type_name: &str) -> bool {
+    get_registry().read().unwrap().contains_key(type_name)
+}
+pub fn get_registered_type_list() -> Vec<String> {
+    get_registry().read().unwrap().keys().cloned().collect()
+}
+pub fn new_segment(type_name: &str) -> Result<Box<dyn Segment + 'static>> {
+    // Build an empty segment of the registered type, fill it by "import_data".
+    let constructor: SegmentConstructor = match get_registry().read().unwrap().get(type_name) {
+        Some(&constructor) => constructor,
+        None => return Err(NetworkError::SegmentTypeNotFound(type_name.to_string())),
+    };
+    Ok(constructor())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_registered_type_list, is_registered, new_segment, register_segment};
+    use crate::multi_seg_network::error::{NetworkError, Result};
+    use crate::multi_seg_network::function_segment::FunctionSegment;
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+    use serde::{Deserialize, Serialize};
+
+    struct AddOne {
+        // A user-defined segment, add "offset" to every value.
+        offset: f64,
+        value: Vec<f64>,
+    }
+    #[derive(Serialize, Deserialize)]
+    struct AddOneData {
+        o: f64,
+    }
+    impl Segment for AddOne {
+        fn new() -> Self {
+            Self {
+                offset: 1.0,
+                value: Vec::new(),
+            }
+        }
+        fn get_type(self: &Self) -> String {
+            String::from("registry_test::AddOne")
+        }
+        fn set_input(self: &mut Self, input: Vec<f64>) -> Result<()> {
+            self.value = input;
+            Ok(())
+        }
+        fn get_output(self: &mut Self) -> Vec<f64> {
+            self.value.iter().map(|v| v + self.offset).collect()
+        }
+        fn export_data(self: &mut Self) -> String {
+            serde_json::to_string(&AddOneData { o: self.offset }).unwrap()
+        }
+        fn import_data(self: &mut Self, data: String) -> Result<()> {
+            self.offset = serde_json::from_str::<AddOneData>(&data)?.o;
+            Ok(())
+        }
+        fn next(self: &mut Self) -> Result<()> {
+            Ok(())
+        }
+        fn accumulate_gradients(self: &mut Self, output_partial: Vec<f64>) -> Result<Vec<f64>> {
+            Ok(output_partial)
+        }
+    }
+
+    #[test]
+    fn test_built_in_segments_are_registered() {
+        for type_name in [
+            "MultiSegNetwork",
+            "FlexibleNetwork",
+            "FunctionSegment",
+            "DenseLayer",
+            "Conv2D",
+            "Pooling2D",
+            "Flatten",
+            "ParallelSegment",
+        ] {
+            assert!(is_registered(type_name));
+            assert_eq!(new_segment(type_name).unwrap().get_type(), type_name);
+        }
+        assert_eq!(
+            new_segment("NotASegment").err(),
+            Some(NetworkError::SegmentTypeNotFound(String::from(
+                "NotASegment"
+            )))
+        );
+        assert!(new_segment("NotASegment")
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("DenseLayer"));
+        // The message lists the types which can be built.
+    }
+
+    #[test]
+    fn test_user_defined_segment_round_trip() {
+        let mut add_two: AddOne = AddOne::new();
+        add_two.offset = 2.0;
+        let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+        multi_seg.push_seg(add_two).unwrap();
+        multi_seg.push_seg(FunctionSegment::new()).unwrap();
+        let data: String = multi_seg.export_data();
+
+        let mut imported_seg: MultiSegNetwork = MultiSegNetwork::new();
+        assert_eq!(
+            imported_seg.import_data(data.clone()).err(),
+            Some(NetworkError::SegmentTypeNotFound(String::from(
+                "registry_test::AddOne"
+            )))
+        );
+        register_segment::<AddOne>();
+        assert!(get_registered_type_list().contains(&String::from("registry_test::AddOne")));
+        imported_seg.import_data(data.clone()).unwrap();
+        imported_seg.set_input(Vec::from([1.0, -1.0])).unwrap();
+        imported_seg.next().unwrap();
+        assert_eq!(imported_seg.get_output(), Vec::from([3.0, 1.0]));
+        assert_eq!(imported_seg.export_data(), data);
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * 2024 (c) MaoHuPi
+ * rust-rl/src/multi_seg_network/schema.rs
+ */
+
+use serde_json::{Map, Value};
+// The old data is upgraded as a json tree, before it is parsed into the data struct.
+
+use crate::multi_seg_network::error::{NetworkError, Result};
+
+pub const VERSION_KEY: &str = "v";
+// The data without it is version 0, such as the files written before the version is added.
+pub type Migration = fn(Map<String, Value>) -> Result<Map<String, Value>>;
+// Upgrade the data by one version.
+
+pub fn get_version(data: &Map<String, Value>) -> Result<u32> {
+    match data.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(version) => match version.as_u64() {
+            Some(version) => Ok(version as u32),
+            None => Err(NetworkError::MalformedData(format!(
+                "schema version {} is not a number",
+                version
+            ))),
+        },
+    }
+}
+pub fn migrate(data: &str, migration_list: &[Migration]) -> Result<Value> {
+    // Run the migrations from the version of the data, "migration_list[i]" upgrades version i to i + 1,
+    // so the current version is the count of the migrations.
+    let mut data: Map<String, Value> = match serde_json::from_str::<Value>(data)? {
+        Value::Object(data) => data,
+        _ => {
+            return Err(NetworkError::MalformedData(String::from(
+                "the segment data is not an object",
+            )))
+        }
+    };
+    let version: u32 = get_version(&data)?;
+    if version as usize > migration_list.len() {
+        return Err(NetworkError::MalformedData(format!(
+            "schema version {} is newer than the supported version {}",
+            version,
+            migration_list.len()
+        )));
+    }
+    for migration in migration_list[version as usize..].iter() {
+        data = migration(data)?;
+    }
+    data.insert(
+        VERSION_KEY.to_string(),
+        Value::from(migration_list.len() as u32),
+    );
+    Ok(Value::Object(data))
+}
+pub fn insert_default(data: &mut Map<String, Value>, key: &str, value: Value) {
+    // Fill the field which is missing in the older versions.
+    if !data.contains_key(key) {
+        data.insert(key.to_string(), value);
+    }
+}
+pub fn check_required(data: &Map<String, Value>, key_list: &[&str]) -> Result<()> {
+    for &key in key_list.iter() {
+        if !data.contains_key(key) {
+            return Err(NetworkError::MalformedData(format!(
+                "missing field \"{}\"",
+                key
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{insert_default, migrate, Migration};
+    use crate::multi_seg_network::error::{NetworkError, Result};
+    use crate::multi_seg_network::{MultiSegNetwork, Segment};
+    use serde_json::{Map, Value};
+
+    fn rename_a_to_b(mut data: Map<String, Value>) -> Result<Map<String, Value>> {
+        if let Some(value) = data.remove("a") {
+            data.insert(String::from("b"), value);
+        }
+        Ok(data)
+    }
+    fn fill_c(mut data: Map<String, Value>) -> Result<Map<String, Value>> {
+        insert_default(&mut data, "c", Value::from(3));
+        Ok(data)
+    }
+    const MIGRATION_LIST: [Migration; 2] = [rename_a_to_b, fill_c];
+
+    #[test]
+    fn test_migrations_run_from_the_version_of_the_data() {
+        assert_eq!(
+            migrate(r#"{"a":1}"#, &MIGRATION_LIST).unwrap(),
+            serde_json::json!({"b": 1, "c": 3, "v": 2})
+        );
+        assert_eq!(
+            migrate(r#"{"a":1,"v":1}"#, &MIGRATION_LIST).unwrap(),
+            serde_json::json!({"a": 1, "c": 3, "v": 2})
+        );
+        assert!(matches!(
+            migrate(r#"{"b":1,"v":3}"#, &MIGRATION_LIST),
+            Err(NetworkError::MalformedData(_))
+        ));
+        assert!(migrate("[1]", &MIGRATION_LIST).is_err());
+    }
+
+    #[test]
+    fn test_the_model_files_are_upgraded() {
+        for data in [
+            include_str!("../../model/net.json"),
+            include_str!("../../model/net001.json"),
+        ] {
+            let mut multi_seg: MultiSegNetwork = MultiSegNetwork::new();
+            multi_seg.import_data(data.to_string()).unwrap();
+            let upgraded_data: String = multi_seg.export_data();
+            let value: Value = serde_json::from_str(&upgraded_data).unwrap();
+            assert_eq!(value["v"], Value::from(1));
+            for child_data in value["data"].as_array().unwrap().iter() {
+                let child_value: Value =
+                    serde_json::from_str(child_data.as_str().unwrap()).unwrap();
+                assert_eq!(child_value["v"], Value::from(1));
+            }
+
+            multi_seg.set_input(vec![0.5; 5]).unwrap();
+            multi_seg.next().unwrap();
+            let output: Vec<f64> = multi_seg.get_output();
+            let mut upgraded_seg: MultiSegNetwork = MultiSegNetwork::new();
+            upgraded_seg.import_data(upgraded_data.clone()).unwrap();
+            upgraded_seg.set_input(vec![0.5; 5]).unwrap();
+            upgraded_seg.next().unwrap();
+            assert_eq!(upgraded_seg.get_output(), output);
+            assert_eq!(upgraded_seg.export_data(), upgraded_data);
+        }
+    }
+}